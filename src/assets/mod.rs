@@ -16,10 +16,7 @@ pub struct Assets {
     pub guy: GuyAssets,
     pub surfaces: Listed<SurfaceAssets>,
     pub tiles: Listed<TileAssets>,
-    #[load(
-        load_with = "Listed::load_with_ext(&manager, &base_path.join(\"objects\"), Some(\"svg\"))"
-    )]
-    pub objects: Listed<Texture>,
+    pub objects: Listed<ObjectAssets>,
     #[load(load_with = "load_font(&manager, &base_path.join(\"Ludum-Dairy-0.2.0.ttf\"))")]
     pub font: geng::Font,
     #[load(ext = "svg")]
@@ -82,6 +79,8 @@ pub struct Config {
     pub bubble_acceleration: f32,
     pub bubble_target_speed: f32,
     pub camera_fov: f32,
+    #[serde(default)]
+    pub camera_follow_gravity: bool,
 
     pub cannon: features::cannon::Config,
 }
@@ -0,0 +1,167 @@
+use super::*;
+
+/// Copied part of a level, stored as a level fragment centered around the origin
+pub fn copy_selection(
+    level: &LevelInfo,
+    layer: usize,
+    center: vec2<f32>,
+    is_selected: impl Fn(vec2<f32>) -> bool,
+) -> LevelInfo {
+    let source = &level.layers[layer];
+    let mut fragment_layer = LevelLayer::new(source.name.clone(), source.gameplay);
+    let offset = |p: vec2<f32>| p - center;
+    for surface in &source.surfaces {
+        if is_selected(surface.p1) && is_selected(surface.p2) {
+            fragment_layer.surfaces.push(Surface {
+                p1: offset(surface.p1),
+                p2: offset(surface.p2),
+                ..surface.clone()
+            });
+        }
+    }
+    for curve in &source.curves {
+        if curve.points.iter().all(|&p| is_selected(p)) {
+            fragment_layer.curves.push(CurveSurface {
+                points: curve.points.iter().copied().map(offset).collect(),
+                ..curve.clone()
+            });
+        }
+    }
+    for tile in &source.tiles {
+        if tile.points().all(&is_selected) {
+            fragment_layer.tiles.push(tile.map_points(offset));
+        }
+    }
+    for object in &source.objects {
+        if is_selected(object.pos) {
+            fragment_layer.objects.push(Object {
+                pos: offset(object.pos),
+                ..object.clone()
+            });
+        }
+    }
+    for instance in &source.prefabs {
+        if is_selected(instance.pos) {
+            fragment_layer.prefabs.push(PrefabInstance {
+                pos: offset(instance.pos),
+                ..instance.clone()
+            });
+        }
+    }
+
+    let mut cannons = vec![];
+    for cannon in &level.cannon.cannons {
+        if is_selected(cannon.pos) {
+            cannons.push(features::cannon::Cannon {
+                pos: offset(cannon.pos),
+                ..cannon.clone()
+            });
+        }
+    }
+
+    // Portal destinations are kept only when both ends are copied
+    let portal_indices: Vec<Option<usize>> = level
+        .portals
+        .iter()
+        .scan(0, |next, portal| {
+            Some(is_selected(portal.pos).then(|| {
+                *next += 1;
+                *next - 1
+            }))
+        })
+        .collect();
+    let portals = level
+        .portals
+        .iter()
+        .filter(|portal| is_selected(portal.pos))
+        .map(|portal| Portal {
+            pos: offset(portal.pos),
+            dest: portal
+                .dest
+                .and_then(|dest| portal_indices.get(dest).copied().flatten()),
+            ..portal.clone()
+        })
+        .collect();
+
+    LevelInfo {
+        layers: vec![fragment_layer],
+        cannon: features::cannon::LevelInfo { cannons },
+        portals,
+        ..default()
+    }
+}
+
+/// Adds the fragment to the layer with its origin at the given position,
+/// returns the pasted points to be selected
+pub fn paste(
+    level: &mut LevelInfo,
+    layer: usize,
+    fragment: &LevelInfo,
+    pos: vec2<f32>,
+) -> Vec<vec2<f32>> {
+    level.insert_fragment(layer, fragment, mat3::translate(pos))
+}
+
+/// Removes everything that has a selected point,
+/// or only what would be copied if `whole_only` is set
+pub fn remove_selection(
+    level: &mut LevelInfo,
+    layer: usize,
+    is_selected: impl Fn(vec2<f32>) -> bool,
+    whole_only: bool,
+) {
+    let remove = |points: &[vec2<f32>]| {
+        if whole_only {
+            points.iter().all(|&p| is_selected(p))
+        } else {
+            points.iter().any(|&p| is_selected(p))
+        }
+    };
+    let layer = &mut level.layers[layer];
+    layer
+        .surfaces
+        .retain(|surface| !remove(&[surface.p1, surface.p2]));
+    layer.curves.retain(|curve| !remove(&curve.points));
+    layer
+        .tiles
+        .retain(|tile| !remove(&tile.points().collect::<Vec<_>>()));
+    layer.objects.retain(|object| !is_selected(object.pos));
+    layer.prefabs.retain(|instance| !is_selected(instance.pos));
+    level
+        .cannon
+        .cannons
+        .retain(|cannon| !is_selected(cannon.pos));
+
+    let portal_indices: Vec<Option<usize>> = level
+        .portals
+        .iter()
+        .scan(0, |next, portal| {
+            Some((!is_selected(portal.pos)).then(|| {
+                *next += 1;
+                *next - 1
+            }))
+        })
+        .collect();
+    level.portals.retain(|portal| !is_selected(portal.pos));
+    for portal in &mut level.portals {
+        portal.dest = portal
+            .dest
+            .and_then(|dest| portal_indices.get(dest).copied().flatten());
+    }
+}
+
+/// Stored versioned like level files, so that it survives format changes
+pub fn save_clipboard(fragment: &LevelInfo) {
+    preferences::save("clipboard", &fragment.to_versioned_json());
+}
+
+pub fn load_clipboard() -> Option<LevelInfo> {
+    let json: String = preferences::load("clipboard")?;
+    match LevelInfo::from_versioned_json(&json) {
+        Ok((fragment, _version)) => Some(fragment),
+        Err(e) => {
+            log::error!("Failed to load clipboard: {e:#}");
+            None
+        }
+    }
+}
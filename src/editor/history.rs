@@ -0,0 +1,61 @@
+use super::*;
+
+/// How many entries around the current one are listed
+const HISTORY_PANEL_SIZE: usize = 15;
+
+/// List of recent edits, clicking one undoes or redoes up to it
+pub fn history_panel<'a>(
+    cx: &'a geng::ui::Controller,
+    level: &mut Level,
+) -> Box<dyn geng::ui::Widget + 'a> {
+    use geng::ui::*;
+    let history = level.history();
+    let current = history.index();
+    let end = (current + HISTORY_PANEL_SIZE / 2)
+        .max(HISTORY_PANEL_SIZE)
+        .min(history.entries().len());
+    let start = end.saturating_sub(HISTORY_PANEL_SIZE);
+    let opened = history.opened();
+    // Index `i` is the state after applying `i` entries
+    let labels: Vec<(usize, String)> = itertools::chain![
+        (start == 0).then(|| {
+            let label = if opened == Some(0) {
+                "(opened)"
+            } else {
+                "(oldest)"
+            };
+            (0, label.to_owned())
+        }),
+        history.entries()[start..end]
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| {
+                let index = start + i + 1;
+                if opened == Some(index) {
+                    (index, format!("{} (opened)", entry.label))
+                } else {
+                    (index, entry.label.clone())
+                }
+            }),
+    ]
+    .collect();
+    let mut jump_to = None;
+    let mut widgets: Vec<Box<dyn Widget + 'a>> = vec![];
+    for (index, label) in labels {
+        let button = Button::new(cx, &label);
+        if button.was_clicked() {
+            jump_to = Some(index);
+        }
+        let mut widget: Box<dyn Widget + 'a> = Box::new(button.uniform_padding(4.0).center());
+        if index == current {
+            widget = Box::new(widget.background_color(Rgba::new(0.5, 0.5, 1.0, 0.5)));
+        } else if index > current {
+            widget = Box::new(widget.background_color(Rgba::new(0.5, 0.5, 0.5, 0.5)));
+        }
+        widgets.push(widget);
+    }
+    if let Some(index) = jump_to {
+        level.jump_to_history(index);
+    }
+    column(widgets).boxed()
+}
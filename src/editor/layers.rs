@@ -0,0 +1,300 @@
+use super::*;
+
+/// Buttons changing a number by a step in both directions, clicking the value resets it
+pub fn stepper<'a>(
+    cx: &'a geng::ui::Controller,
+    label: &str,
+    value: f32,
+    step: f32,
+    default: f32,
+) -> (Box<dyn geng::ui::Widget + 'a>, Option<f32>) {
+    use geng::ui::*;
+    let minus = Button::new(cx, "-");
+    let reset = Button::new(cx, &format!("{label}: {value:.2}"));
+    let plus = Button::new(cx, "+");
+    let new_value = if minus.was_clicked() {
+        Some(value - step)
+    } else if plus.was_clicked() {
+        Some(value + step)
+    } else if reset.was_clicked() {
+        Some(default)
+    } else {
+        None
+    };
+    let widget = row(vec![
+        minus.uniform_padding(4.0).boxed(),
+        reset.uniform_padding(4.0).boxed(),
+        plus.uniform_padding(4.0).boxed(),
+    ])
+    .boxed();
+    (widget, new_value)
+}
+
+pub fn toggle<'a>(
+    cx: &'a geng::ui::Controller,
+    label: &str,
+    value: bool,
+) -> (Box<dyn geng::ui::Widget + 'a>, bool) {
+    use geng::ui::*;
+    let button = Button::new(
+        cx,
+        &format!("{label}: {}", if value { "on" } else { "off" }),
+    );
+    let clicked = button.was_clicked();
+    (button.uniform_padding(4.0).boxed(), clicked)
+}
+
+impl EditorState {
+    /// Keeps the selected layer valid after layers were removed or undone
+    pub fn clamp_selected_layer(&mut self, level: &Level) {
+        self.selected_layer = self.selected_layer.min(level.layers.len() - 1);
+    }
+
+    pub fn is_layer_hidden(&self, level: &Level, index: usize) -> bool {
+        self.hidden_layers.contains(&level.layers[index].name)
+    }
+
+    pub fn is_layer_locked(&self, level: &Level, index: usize) -> bool {
+        self.locked_layers.contains(&level.layers[index].name)
+    }
+
+    pub fn is_renaming_layer(&self) -> bool {
+        self.renaming_layer.is_some()
+    }
+
+    /// Text input for renaming the selected layer, returns whether the event was consumed
+    pub fn handle_rename_event(&mut self, event: &geng::Event, level: &mut Level) -> bool {
+        let Some(name) = &mut self.renaming_layer else {
+            return false;
+        };
+        let geng::Event::KeyDown { key } = event else {
+            return false;
+        };
+        match key {
+            geng::Key::Enter => {
+                let name = self.renaming_layer.take().unwrap();
+                if !name.is_empty() {
+                    let layer = &mut level.modify().layers[self.selected_layer];
+                    for set in [&mut self.hidden_layers, &mut self.locked_layers] {
+                        if set.remove(&layer.name) {
+                            set.insert(name.clone());
+                        }
+                    }
+                    layer.name = name;
+                }
+            }
+            geng::Key::Escape => {
+                self.renaming_layer = None;
+            }
+            geng::Key::Backspace => {
+                name.pop();
+            }
+            _ => {
+                if let Some(c) = key_char(*key) {
+                    name.push(c);
+                }
+            }
+        }
+        true
+    }
+
+    pub fn layer_panel<'a>(
+        &mut self,
+        cx: &'a geng::ui::Controller,
+        level: &mut Level,
+    ) -> Box<dyn geng::ui::Widget + 'a> {
+        use geng::ui::*;
+        self.clamp_selected_layer(level);
+
+        let mut widgets: Vec<Box<dyn Widget + 'a>> = vec![];
+        for (index, layer) in level.layers.iter().enumerate() {
+            let mut text = layer.name.clone();
+            if index == self.selected_layer {
+                if let Some(name) = &self.renaming_layer {
+                    text = format!("{name}_");
+                }
+            }
+            if self.hidden_layers.contains(&layer.name) {
+                text += " (hidden)";
+            }
+            if self.locked_layers.contains(&layer.name) {
+                text += " (locked)";
+            }
+            let button = Button::new(cx, &text);
+            if button.was_clicked() {
+                self.selected_layer = index;
+                self.renaming_layer = None;
+            }
+            let mut widget: Box<dyn Widget + 'a> = Box::new(button.uniform_padding(8.0).center());
+            if index == self.selected_layer {
+                widget = Box::new(widget.background_color(Rgba::new(0.5, 0.5, 1.0, 0.5)));
+            }
+            widgets.push(widget);
+        }
+
+        let index = self.selected_layer;
+        let layer = &level.layers[index];
+        let mut gameplay = layer.gameplay;
+        let (mut hidden, mut locked) = (
+            self.hidden_layers.contains(&layer.name),
+            self.locked_layers.contains(&layer.name),
+        );
+        let (mut parallax, mut reveal_radius, mut texture_scale, mut color) = (
+            layer.parallax,
+            layer.reveal_radius,
+            layer.texture_scale,
+            layer.color,
+        );
+
+        let add = Button::new(cx, "add");
+        let delete = Button::new(cx, "delete");
+        let up = Button::new(cx, "up");
+        let down = Button::new(cx, "down");
+        let rename = Button::new(cx, "rename");
+        let add_clicked = add.was_clicked();
+        let delete_clicked = delete.was_clicked();
+        let up_clicked = up.was_clicked();
+        let down_clicked = down.was_clicked();
+        let rename_clicked = rename.was_clicked();
+        widgets.push(
+            row(vec![
+                add.uniform_padding(4.0).boxed(),
+                delete.uniform_padding(4.0).boxed(),
+                up.uniform_padding(4.0).boxed(),
+                down.uniform_padding(4.0).boxed(),
+                rename.uniform_padding(4.0).boxed(),
+            ])
+            .boxed(),
+        );
+
+        for (label, value) in [
+            ("gameplay", &mut gameplay),
+            ("hidden", &mut hidden),
+            ("locked", &mut locked),
+        ] {
+            let (widget, clicked) = toggle(cx, label, *value);
+            if clicked {
+                *value = !*value;
+            }
+            widgets.push(widget);
+        }
+        let default_layer = LevelLayer::new(String::new(), false);
+        for (label, value, step, default) in [
+            ("parallax x", &mut parallax.x, 0.1, default_layer.parallax.x),
+            ("parallax y", &mut parallax.y, 0.1, default_layer.parallax.y),
+            ("reveal radius", &mut reveal_radius, 1.0, 0.0),
+            (
+                "texture scale",
+                &mut texture_scale,
+                0.1,
+                default_layer.texture_scale,
+            ),
+            ("red", &mut color.r, 0.1, default_layer.color.r),
+            ("green", &mut color.g, 0.1, default_layer.color.g),
+            ("blue", &mut color.b, 0.1, default_layer.color.b),
+            ("alpha", &mut color.a, 0.1, default_layer.color.a),
+        ] {
+            let (widget, new_value) = stepper(cx, label, *value, step, default);
+            if let Some(new_value) = new_value {
+                *value = new_value;
+            }
+            widgets.push(widget);
+        }
+        let layer = &level.layers[index];
+        for (set, value) in [
+            (&mut self.hidden_layers, hidden),
+            (&mut self.locked_layers, locked),
+        ] {
+            if value {
+                set.insert(layer.name.clone());
+            } else {
+                set.remove(&layer.name);
+            }
+        }
+        if gameplay != layer.gameplay
+            || parallax != layer.parallax
+            || reveal_radius != layer.reveal_radius
+            || texture_scale != layer.texture_scale
+            || color != layer.color
+        {
+            let layer = &mut level.modify().layers[index];
+            layer.gameplay = gameplay;
+            layer.parallax = parallax;
+            layer.reveal_radius = reveal_radius.max(0.0);
+            layer.texture_scale = texture_scale.max(0.1);
+            layer.color = color.map(|x| x.clamp(0.0, 1.0));
+        }
+
+        if add_clicked {
+            let name = format!("layer {}", level.layers.len());
+            level
+                .modify()
+                .layers
+                .insert(index + 1, LevelLayer::new(name, false));
+            self.selected_layer = index + 1;
+        }
+        if delete_clicked && level.layers.len() > 1 {
+            level.modify().layers.remove(index);
+            self.clamp_selected_layer(level);
+        }
+        // Layers earlier in the list are drawn first, so further back
+        if up_clicked && index > 0 {
+            level.modify().layers.swap(index, index - 1);
+            self.selected_layer = index - 1;
+        }
+        if down_clicked && index + 1 < level.layers.len() {
+            level.modify().layers.swap(index, index + 1);
+            self.selected_layer = index + 1;
+        }
+        if rename_clicked {
+            self.renaming_layer = Some(String::new());
+        }
+
+        column(widgets).boxed()
+    }
+}
+
+/// Character that the key types into a layer name
+fn key_char(key: geng::Key) -> Option<char> {
+    use geng::Key::*;
+    Some(match key {
+        A => 'a',
+        B => 'b',
+        C => 'c',
+        D => 'd',
+        E => 'e',
+        F => 'f',
+        G => 'g',
+        H => 'h',
+        I => 'i',
+        J => 'j',
+        K => 'k',
+        L => 'l',
+        M => 'm',
+        N => 'n',
+        O => 'o',
+        P => 'p',
+        Q => 'q',
+        R => 'r',
+        S => 's',
+        T => 't',
+        U => 'u',
+        V => 'v',
+        W => 'w',
+        X => 'x',
+        Y => 'y',
+        Z => 'z',
+        Num0 => '0',
+        Num1 => '1',
+        Num2 => '2',
+        Num3 => '3',
+        Num4 => '4',
+        Num5 => '5',
+        Num6 => '6',
+        Num7 => '7',
+        Num8 => '8',
+        Num9 => '9',
+        Space => ' ',
+        _ => return None,
+    })
+}
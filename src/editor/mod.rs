@@ -1,8 +1,16 @@
 use super::*;
 
 // mods are orthogonal to workspaces
+mod clipboard;
+mod history;
+mod layers;
+mod snapping;
 mod tools;
 
+use clipboard::*;
+use history::*;
+use layers::*;
+use snapping::*;
 use tools::*;
 
 // This struct is not the state of the editor
@@ -20,6 +28,13 @@ pub struct EditorState {
     available_tools: Vec<Box<dyn ToolConstructor>>,
     selected_tool_index: usize,
     selected_layer: usize,
+    /// New name being typed for the selected layer
+    renaming_layer: Option<String>,
+    /// Names of the layers that are not drawn, only a view setting so not part of the level
+    hidden_layers: HashSet<String>,
+    /// Names of the layers that tools can not change the contents of
+    locked_layers: HashSet<String>,
+    snapping: SnapSettings,
     tool: Box<dyn DynEditorTool>,
 }
 
@@ -28,12 +43,15 @@ impl EditorState {
         let available_tools = vec![
             tool_constructor::<EditTool>(geng, assets),
             tool_constructor::<SurfaceTool>(geng, assets),
+            tool_constructor::<CurveTool>(geng, assets),
             tool_constructor::<TileTool>(geng, assets),
             tool_constructor::<ObjectTool>(geng, assets),
             tool_constructor::<EndpointTool>(geng, assets),
             tool_constructor::<ProgressTool>(geng, assets),
             tool_constructor::<features::cannon::editor::CannonTool>(geng, assets),
+            tool_constructor::<features::platform::editor::PlatformTool>(geng, assets),
             tool_constructor::<PortalTool>(geng, assets),
+            tool_constructor::<PrefabTool>(geng, assets),
         ];
         let selected_tool_index = 0;
         Self {
@@ -46,6 +64,10 @@ impl EditorState {
             next_autosave: 0.0,
             selected_tool_index,
             selected_layer: 0,
+            renaming_layer: None,
+            hidden_layers: default(),
+            locked_layers: default(),
+            snapping: SnapSettings::load(),
             tool: available_tools[selected_tool_index].create(),
             available_tools,
         }
@@ -59,13 +81,20 @@ impl EditorState {
     }
 
     pub fn save_level(&self, level: &mut Level) {
-        level.save();
+        if let Err(e) = level.save() {
+            log::error!("Failed to save the level: {e:#}");
+        }
     }
 }
 
 impl Game {
-    pub fn snapped_cursor_position(&self, level: &Level) -> vec2<f32> {
-        let Some(editor) = &self.editor else { return vec2::ZERO; };
+    pub fn snapped_cursor_position(&self, level: &Level) -> Snap {
+        let Some(editor) = &self.editor else {
+            return Snap {
+                pos: vec2::ZERO,
+                kind: SnapKind::None,
+            };
+        };
         let camera = geng::Camera2d {
             center: self.camera.center * level.layers[editor.selected_layer].parallax,
             ..self.camera
@@ -79,37 +108,48 @@ impl Game {
         )
     }
 
-    pub fn snap_position(&self, level: &Level, pos: vec2<f32>) -> vec2<f32> {
-        let closest_point = itertools::chain![
-            level
-                .all_surfaces()
-                .flat_map(|surface| [surface.p1, surface.p2]),
-            level.all_tiles().flat_map(|tile| tile.vertices)
-        ]
-        .filter(|&p| (pos - p).len() < self.config.snap_distance)
-        .min_by_key(|&p| r32((pos - p).len()));
-        closest_point.unwrap_or(pos)
+    pub fn snap_position(&self, level: &Level, pos: vec2<f32>) -> Snap {
+        let Some(editor) = &self.editor else {
+            return Snap {
+                pos,
+                kind: SnapKind::None,
+            };
+        };
+        editor.snapping.snap(
+            level,
+            pos,
+            editor.tool.snap_anchor(),
+            self.config.snap_distance,
+        )
     }
 
     pub fn draw_level_editor(&self, framebuffer: &mut ugli::Framebuffer) {
         if let Some(editor) = &self.editor {
             let camera = geng::Camera2d {
-                center: self.camera.center * self.level.layers[editor.selected_layer].parallax,
+                center: self.camera.center
+                    * self.simulation.level.layers[editor.selected_layer].parallax,
                 ..self.camera
             };
+            editor.snapping.draw_overlay(
+                &self.geng,
+                editor.tool.snap_anchor(),
+                &camera,
+                framebuffer,
+            );
             editor.tool.draw(
                 &editor.cursor,
-                &self.level,
+                &self.simulation.level,
                 editor.selected_layer,
                 &camera,
                 framebuffer,
             );
+            let snap = self.snapped_cursor_position(&self.simulation.level);
             self.geng.draw2d().draw2d(
                 framebuffer,
                 &camera,
                 &draw2d::Quad::new(
-                    Aabb2::point(self.snapped_cursor_position(&self.level)).extend_uniform(0.1),
-                    Rgba::new(1.0, 0.0, 0.0, 0.5),
+                    Aabb2::point(snap.pos).extend_uniform(0.1),
+                    snap.kind.color(),
                 ),
             );
         }
@@ -119,8 +159,15 @@ impl Game {
         if self.editor.is_none() {
             return;
         }
-        let cursor_pos = self.snapped_cursor_position(&self.level);
+        self.editor
+            .as_mut()
+            .unwrap()
+            .clamp_selected_layer(&self.simulation.level);
+        let cursor_pos = self.snapped_cursor_position(&self.simulation.level).pos;
         let editor = self.editor.as_mut().unwrap();
+        if editor.handle_rename_event(event, &mut self.simulation.level) {
+            return;
+        }
         editor.cursor = Cursor {
             screen_pos: self.geng.window().cursor_position().map(|x| x as f32),
             world_pos: self.camera.screen_to_world(
@@ -130,22 +177,27 @@ impl Game {
             snapped_world_pos: cursor_pos,
         };
 
-        editor.tool.handle_event(
-            &editor.cursor,
-            event,
-            &mut self.level,
-            editor.selected_layer,
-        );
+        let locked = editor.is_layer_locked(&self.simulation.level, editor.selected_layer);
+        if !(locked && editor.tool.edits_layer()) {
+            editor.tool.handle_event(
+                &editor.cursor,
+                event,
+                &mut self.simulation.level,
+                editor.selected_layer,
+            );
+        }
 
         if let geng::Event::KeyDown { key } = event {
             match key {
                 geng::Key::Tab => {
+                    self.simulation.level.end_history_batch();
                     editor.selected_tool_index =
                         (editor.selected_tool_index + 1) % editor.available_tools.len();
                     editor.tool = editor.available_tools[editor.selected_tool_index].create();
                 }
                 geng::Key::T => {
-                    if let Some(guy) = self.my_guy.and_then(|id| self.guys.get_mut(&id)) {
+                    if let Some(guy) = self.my_guy.and_then(|id| self.simulation.guys.get_mut(&id))
+                    {
                         guy.state.pos = editor.cursor.world_pos;
                     }
                 }
@@ -155,26 +207,27 @@ impl Game {
                             if let Some(con) = &mut self.connection {
                                 con.send(ClientMessage::Despawn);
                             }
-                            self.guys.remove(&id);
+                            self.simulation.guys.remove(&id);
                         } else {
                             self.my_guy = Some(self.client_id);
-                            self.guys.insert(Guy::new(
+                            self.simulation.spawn_guy(
                                 self.client_id,
                                 editor.cursor.world_pos,
                                 false,
-                                &self.config,
-                            ));
+                            );
                         }
                     }
                 }
                 geng::Key::S if self.geng.window().is_key_pressed(geng::Key::LCtrl) => {
-                    editor.save_level(&mut self.level);
+                    editor.save_level(&mut self.simulation.level);
                 }
                 geng::Key::Z if self.geng.window().is_key_pressed(geng::Key::LCtrl) => {
-                    self.level.undo();
+                    self.simulation.level.undo();
+                    editor.clamp_selected_layer(&self.simulation.level);
                 }
                 geng::Key::Y if self.geng.window().is_key_pressed(geng::Key::LCtrl) => {
-                    self.level.redo();
+                    self.simulation.level.redo();
+                    editor.clamp_selected_layer(&self.simulation.level);
                 }
                 _ => {}
             }
@@ -191,6 +244,7 @@ impl Game {
             for (index, constructor) in editor.available_tools.iter().enumerate() {
                 let button = Button::new(cx, constructor.name());
                 if button.was_clicked() {
+                    self.simulation.level.end_history_batch();
                     editor.selected_tool_index = index;
                     editor.tool = constructor.create();
                 }
@@ -202,33 +256,15 @@ impl Game {
             }
             column(tools)
         };
+        let snapping = editor.snapping_panel(cx);
+        let history = history_panel(cx, &mut self.simulation.level);
+        let layer_selection = editor.layer_panel(cx, &mut self.simulation.level);
         let tool_config = editor.tool.ui(cx);
-        let layer_selection = {
-            column(
-                self.level
-                    .layers
-                    .iter()
-                    .enumerate()
-                    .map(|(index, layer)| {
-                        let button = Button::new(cx, &layer.name);
-                        if button.was_clicked() {
-                            editor.selected_layer = index;
-                        }
-                        let mut widget: Box<dyn Widget> =
-                            Box::new(button.uniform_padding(8.0).center());
-                        if index == editor.selected_layer {
-                            widget =
-                                Box::new(widget.background_color(Rgba::new(0.5, 0.5, 1.0, 0.5)));
-                        }
-                        widget
-                    })
-                    .collect(),
-            )
-        };
         (
             layer_selection.align(vec2(0.0, 1.0)),
-            tool_selection.align(vec2(0.0, 1.0)),
+            column(vec![tool_selection.boxed(), snapping]).align(vec2(0.0, 1.0)),
             tool_config.align(vec2(0.0, 1.0)),
+            history.align(vec2(0.0, 1.0)),
         )
             .row()
             .uniform_padding(16.0)
@@ -240,6 +276,9 @@ impl Game {
 
 pub trait EditorTool: 'static {
     const NAME: &'static str;
+    /// Whether the tool changes the contents of the selected layer,
+    /// such tools are disabled while the layer is locked
+    const EDITS_LAYER: bool = false;
     type Config: EditorToolConfig;
     fn new(geng: &Geng, assets: &AssetsHandle, config: Self::Config) -> Self;
     fn draw(
@@ -258,6 +297,10 @@ pub trait EditorTool: 'static {
         selected_layer: usize,
     );
     fn ui<'a>(&'a mut self, cx: &'a geng::ui::Controller) -> Box<dyn geng::ui::Widget + 'a>;
+    /// Point that angle snapping is relative to, like the start of a surface being drawn
+    fn snap_anchor(&self) -> Option<vec2<f32>> {
+        None
+    }
 }
 
 pub trait EditorToolConfig {
@@ -281,6 +324,8 @@ pub trait DynEditorTool {
         selected_layer: usize,
     );
     fn ui<'a>(&'a mut self, cx: &'a geng::ui::Controller) -> Box<dyn geng::ui::Widget + 'a>;
+    fn snap_anchor(&self) -> Option<vec2<f32>>;
+    fn edits_layer(&self) -> bool;
 }
 
 impl<T: EditorTool> DynEditorTool for T {
@@ -306,6 +351,12 @@ impl<T: EditorTool> DynEditorTool for T {
     fn ui<'a>(&'a mut self, cx: &'a geng::ui::Controller) -> Box<dyn geng::ui::Widget + 'a> {
         <T as EditorTool>::ui(self, cx)
     }
+    fn snap_anchor(&self) -> Option<vec2<f32>> {
+        <T as EditorTool>::snap_anchor(self)
+    }
+    fn edits_layer(&self) -> bool {
+        T::EDITS_LAYER
+    }
 }
 
 pub trait ToolConstructor {
@@ -0,0 +1,269 @@
+use super::*;
+
+/// Which snapping modes are enabled, persisted between sessions
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct SnapSettings {
+    pub vertices: bool,
+    pub edges: bool,
+    pub objects: bool,
+    pub grid: bool,
+    pub grid_size: f32,
+    /// Direction from the tool's anchor point is rounded to this many degrees
+    pub angle_step: Option<f32>,
+}
+
+impl Default for SnapSettings {
+    fn default() -> Self {
+        Self {
+            vertices: true,
+            edges: false,
+            objects: false,
+            grid: false,
+            grid_size: 1.0,
+            angle_step: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapKind {
+    None,
+    Vertex,
+    Object,
+    Edge,
+    Angle,
+    Grid,
+}
+
+impl SnapKind {
+    pub fn color(self) -> Rgba<f32> {
+        match self {
+            Self::None => Rgba::new(1.0, 0.0, 0.0, 0.5),
+            Self::Vertex => Rgba::new(0.0, 1.0, 0.0, 0.8),
+            Self::Object => Rgba::new(1.0, 1.0, 0.0, 0.8),
+            Self::Edge => Rgba::new(0.0, 1.0, 1.0, 0.8),
+            Self::Angle => Rgba::new(1.0, 0.5, 0.0, 0.8),
+            Self::Grid => Rgba::new(0.5, 0.5, 1.0, 0.8),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Snap {
+    pub pos: vec2<f32>,
+    pub kind: SnapKind,
+}
+
+/// Rotates `pos` around `anchor` so that the direction is a multiple of `step_degrees`
+pub fn snap_angle(anchor: vec2<f32>, pos: vec2<f32>, step_degrees: f32) -> vec2<f32> {
+    let delta = pos - anchor;
+    let step = step_degrees.to_radians();
+    let arg = (delta.arg().as_radians() / step).round() * step;
+    anchor + vec2(delta.len(), 0.0).rotate(Angle::from_radians(arg))
+}
+
+impl SnapSettings {
+    pub fn load() -> Self {
+        preferences::load("snapping").unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        preferences::save("snapping", self);
+    }
+
+    /// Point snaps take priority over angle snapping, which takes priority over the grid
+    pub fn snap(
+        &self,
+        level: &Level,
+        pos: vec2<f32>,
+        anchor: Option<vec2<f32>>,
+        snap_distance: f32,
+    ) -> Snap {
+        let level = level.flattened();
+        let closest = |points: &mut dyn Iterator<Item = vec2<f32>>| {
+            points
+                .filter(|&p| (pos - p).len() < snap_distance)
+                .min_by_key(|&p| r32((pos - p).len()))
+        };
+        let mut points: Vec<(Box<dyn Iterator<Item = vec2<f32>> + '_>, SnapKind)> = vec![];
+        if self.vertices {
+            points.push((
+                Box::new(itertools::chain![
+                    level
+                        .all_surfaces()
+                        .flat_map(|surface| [surface.p1, surface.p2]),
+                    level.all_tiles().flat_map(|tile| tile.points())
+                ]),
+                SnapKind::Vertex,
+            ));
+        }
+        if self.objects {
+            points.push((
+                Box::new(level.all_objects().map(|object| object.pos)),
+                SnapKind::Object,
+            ));
+        }
+        if self.edges {
+            points.push((
+                Box::new(
+                    level
+                        .all_surfaces()
+                        .map(move |surface| pos + surface.vector_from(pos)),
+                ),
+                SnapKind::Edge,
+            ));
+        }
+        for (mut points, kind) in points {
+            if let Some(pos) = closest(&mut points) {
+                return Snap { pos, kind };
+            }
+        }
+        if let (Some(anchor), Some(step)) = (anchor, self.angle_step) {
+            if pos != anchor {
+                let mut pos = snap_angle(anchor, pos, step);
+                if self.grid {
+                    // Length is snapped to the grid instead of the point itself
+                    let delta = pos - anchor;
+                    let len = (delta.len() / self.grid_size).round() * self.grid_size;
+                    pos = anchor + delta.normalize_or_zero() * len;
+                }
+                return Snap {
+                    pos,
+                    kind: SnapKind::Angle,
+                };
+            }
+        }
+        if self.grid {
+            return Snap {
+                pos: pos.map(|x| (x / self.grid_size).round() * self.grid_size),
+                kind: SnapKind::Grid,
+            };
+        }
+        Snap {
+            pos,
+            kind: SnapKind::None,
+        }
+    }
+
+    /// Grid lines and angle guides
+    pub fn draw_overlay(
+        &self,
+        geng: &Geng,
+        anchor: Option<vec2<f32>>,
+        camera: &geng::Camera2d,
+        framebuffer: &mut ugli::Framebuffer,
+    ) {
+        let framebuffer_size = framebuffer.size().map(|x| x as f32);
+        let view = Aabb2::point(camera.center).extend_symmetric(
+            vec2(
+                camera.fov * framebuffer_size.x / framebuffer_size.y,
+                camera.fov,
+            ) / 2.0,
+        );
+        let line_width = camera.fov / framebuffer_size.y;
+        // Too dense grid is just noise
+        if self.grid && view.height() / self.grid_size < 200.0 {
+            let color = Rgba::new(1.0, 1.0, 1.0, 0.1);
+            let range = |min: f32, max: f32| {
+                (min / self.grid_size).floor() as i32..=(max / self.grid_size).ceil() as i32
+            };
+            for i in range(view.min.x, view.max.x) {
+                let x = i as f32 * self.grid_size;
+                geng.draw2d().draw2d(
+                    framebuffer,
+                    camera,
+                    &draw2d::Segment::new(
+                        Segment(vec2(x, view.min.y), vec2(x, view.max.y)),
+                        line_width,
+                        color,
+                    ),
+                );
+            }
+            for i in range(view.min.y, view.max.y) {
+                let y = i as f32 * self.grid_size;
+                geng.draw2d().draw2d(
+                    framebuffer,
+                    camera,
+                    &draw2d::Segment::new(
+                        Segment(vec2(view.min.x, y), vec2(view.max.x, y)),
+                        line_width,
+                        color,
+                    ),
+                );
+            }
+        }
+        if let (Some(anchor), Some(step)) = (anchor, self.angle_step) {
+            let len = view.width().max(view.height());
+            let mut angle = 0.0;
+            while angle < 360.0 {
+                geng.draw2d().draw2d(
+                    framebuffer,
+                    camera,
+                    &draw2d::Segment::new(
+                        Segment(
+                            anchor,
+                            anchor + vec2(len, 0.0).rotate(Angle::from_degrees(angle)),
+                        ),
+                        line_width,
+                        Rgba::new(1.0, 0.5, 0.0, 0.25),
+                    ),
+                );
+                angle += step;
+            }
+        }
+    }
+}
+
+impl EditorState {
+    pub fn snapping_panel<'a>(
+        &mut self,
+        cx: &'a geng::ui::Controller,
+    ) -> Box<dyn geng::ui::Widget + 'a> {
+        use geng::ui::*;
+        let mut settings = self.snapping.clone();
+        let mut widgets: Vec<Box<dyn Widget + 'a>> = vec![];
+        for (label, value) in [
+            ("snap vertices", &mut settings.vertices),
+            ("snap edges", &mut settings.edges),
+            ("snap objects", &mut settings.objects),
+            ("snap grid", &mut settings.grid),
+        ] {
+            let (widget, clicked) = toggle(cx, label, *value);
+            if clicked {
+                *value = !*value;
+            }
+            widgets.push(widget);
+        }
+        let (widget, new_size) = stepper(
+            cx,
+            "grid size",
+            settings.grid_size,
+            0.25,
+            SnapSettings::default().grid_size,
+        );
+        if let Some(new_size) = new_size {
+            settings.grid_size = new_size.max(0.25);
+        }
+        widgets.push(widget);
+        let angle = Button::new(
+            cx,
+            &match settings.angle_step {
+                Some(step) => format!("snap angle: {step}°"),
+                None => "snap angle: off".to_owned(),
+            },
+        );
+        if angle.was_clicked() {
+            settings.angle_step = match settings.angle_step {
+                None => Some(15.0),
+                Some(step) if step < 45.0 => Some(45.0),
+                Some(_) => None,
+            };
+        }
+        widgets.push(angle.uniform_padding(4.0).boxed());
+        if settings != self.snapping {
+            settings.save();
+            self.snapping = settings;
+        }
+        column(widgets).boxed()
+    }
+}
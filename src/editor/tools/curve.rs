@@ -0,0 +1,301 @@
+use super::*;
+
+pub struct CurveToolConfig {
+    snap_distance: f32,
+    selected_type: String,
+    tolerance: f32,
+}
+
+impl EditorToolConfig for CurveToolConfig {
+    fn default(assets: &AssetsHandle) -> Self {
+        Self {
+            snap_distance: assets.get().config.snap_distance,
+            selected_type: assets.get().surfaces.keys().min().unwrap().to_owned(),
+            tolerance: default_tolerance(),
+        }
+    }
+}
+
+pub struct CurveTool {
+    geng: Geng,
+    assets: AssetsHandle,
+    /// Anchors of the curve being placed
+    anchors: Vec<vec2<f32>>,
+    /// Curve and the index of its control point being dragged
+    handle_drag: Option<(usize, usize)>,
+    config: CurveToolConfig,
+}
+
+impl CurveTool {
+    fn find_hovered_handle(
+        &self,
+        cursor: &Cursor,
+        level: &Level,
+        selected_layer: usize,
+    ) -> Option<(usize, usize)> {
+        level.layers[selected_layer]
+            .curves
+            .iter()
+            .enumerate()
+            .flat_map(|(curve_index, curve)| {
+                curve
+                    .points
+                    .iter()
+                    .enumerate()
+                    .map(move |(index, &p)| (curve_index, index, p))
+            })
+            .filter(|&(_, _, p)| (p - cursor.world_pos).len() < self.config.snap_distance)
+            .min_by_key(|&(_, _, p)| r32((p - cursor.world_pos).len()))
+            .map(|(curve_index, index, _)| (curve_index, index))
+    }
+
+    fn find_hovered_curve(
+        &self,
+        cursor: &Cursor,
+        level: &Level,
+        selected_layer: usize,
+    ) -> Option<usize> {
+        level.layers[selected_layer]
+            .curves
+            .iter()
+            .position(|curve| {
+                curve.surfaces().iter().any(|surface| {
+                    surface.vector_from(cursor.world_pos).len() < self.config.snap_distance
+                })
+            })
+    }
+
+    /// Moving an anchor also moves its handles
+    fn move_point(curve: &mut CurveSurface, index: usize, pos: vec2<f32>) {
+        let delta = pos - curve.points[index];
+        if CurveSurface::is_anchor(index) {
+            for handle in [index.wrapping_sub(1), index + 1] {
+                if let Some(p) = curve.points.get_mut(handle) {
+                    *p += delta;
+                }
+            }
+        }
+        curve.points[index] = pos;
+    }
+
+    /// Curve as it would be after releasing the mouse
+    fn dragged_curve(
+        &self,
+        cursor: &Cursor,
+        level: &Level,
+        selected_layer: usize,
+    ) -> Option<(usize, CurveSurface)> {
+        let (curve_index, index) = self.handle_drag?;
+        let mut curve = level.layers[selected_layer].curves[curve_index].clone();
+        Self::move_point(&mut curve, index, cursor.snapped_world_pos);
+        Some((curve_index, curve))
+    }
+
+    fn finish(&mut self, level: &mut Level, selected_layer: usize) {
+        let anchors = mem::take(&mut self.anchors);
+        if anchors.len() < 2 {
+            return;
+        }
+        level.modify().layers[selected_layer]
+            .curves
+            .push(CurveSurface {
+                tolerance: self.config.tolerance,
+                ..CurveSurface::new(&anchors, self.config.selected_type.clone())
+            });
+    }
+
+    fn draw_curve(
+        &self,
+        curve: &CurveSurface,
+        hovered_handle: Option<usize>,
+        camera: &geng::Camera2d,
+        framebuffer: &mut ugli::Framebuffer,
+    ) {
+        for window in curve.tessellate().windows(2) {
+            self.geng.draw2d().draw2d(
+                framebuffer,
+                camera,
+                &draw2d::Segment::new(
+                    Segment(window[0], window[1]),
+                    0.1,
+                    Rgba::new(1.0, 1.0, 1.0, 0.5),
+                ),
+            );
+        }
+        for (index, &p) in curve.points.iter().enumerate() {
+            let color = if Some(index) == hovered_handle {
+                Rgba::new(1.0, 0.0, 0.0, 0.8)
+            } else {
+                Rgba::new(0.0, 1.0, 0.0, 0.5)
+            };
+            if CurveSurface::is_anchor(index) {
+                self.geng.draw2d().draw2d(
+                    framebuffer,
+                    camera,
+                    &draw2d::Quad::new(Aabb2::point(p).extend_uniform(0.15), color),
+                );
+            } else {
+                let anchor = curve.points[(index + 1) / 3 * 3];
+                self.geng.draw2d().draw2d(
+                    framebuffer,
+                    camera,
+                    &draw2d::Segment::new(Segment(anchor, p), 0.03, color),
+                );
+                self.geng.draw2d().draw2d(
+                    framebuffer,
+                    camera,
+                    &draw2d::Ellipse::circle(p, 0.1, color),
+                );
+            }
+        }
+    }
+}
+
+impl EditorTool for CurveTool {
+    type Config = CurveToolConfig;
+    fn new(geng: &Geng, assets: &AssetsHandle, config: CurveToolConfig) -> Self {
+        Self {
+            geng: geng.clone(),
+            assets: assets.clone(),
+            anchors: vec![],
+            handle_drag: None,
+            config,
+        }
+    }
+    fn draw(
+        &self,
+        cursor: &Cursor,
+        level: &Level,
+        selected_layer: usize,
+        camera: &geng::Camera2d,
+        framebuffer: &mut ugli::Framebuffer,
+    ) {
+        let dragged = self.dragged_curve(cursor, level, selected_layer);
+        let hovered = match self.handle_drag {
+            Some(handle) => Some(handle),
+            None if self.anchors.is_empty() => {
+                self.find_hovered_handle(cursor, level, selected_layer)
+            }
+            None => None,
+        };
+        for (curve_index, curve) in level.layers[selected_layer].curves.iter().enumerate() {
+            let curve = match &dragged {
+                Some((index, dragged)) if *index == curve_index => dragged,
+                _ => curve,
+            };
+            let hovered_handle = hovered
+                .filter(|&(index, _)| index == curve_index)
+                .map(|(_, handle)| handle);
+            self.draw_curve(curve, hovered_handle, camera, framebuffer);
+        }
+        if !self.anchors.is_empty() {
+            let mut anchors = self.anchors.clone();
+            anchors.push(cursor.snapped_world_pos);
+            let preview = CurveSurface {
+                tolerance: self.config.tolerance,
+                ..CurveSurface::new(&anchors, self.config.selected_type.clone())
+            };
+            self.draw_curve(&preview, None, camera, framebuffer);
+        }
+    }
+    fn handle_event(
+        &mut self,
+        cursor: &Cursor,
+        event: &geng::Event,
+        level: &mut Level,
+        selected_layer: usize,
+    ) {
+        match event {
+            geng::Event::MouseDown {
+                button: geng::MouseButton::Left,
+                ..
+            } => {
+                if self.anchors.is_empty() {
+                    self.handle_drag = self.find_hovered_handle(cursor, level, selected_layer);
+                }
+                if self.handle_drag.is_none() {
+                    let pos = cursor.snapped_world_pos;
+                    if self.anchors.last().map_or(true, |&last| {
+                        (pos - last).len() >= self.config.snap_distance
+                    }) {
+                        self.anchors.push(pos);
+                    }
+                }
+            }
+            geng::Event::MouseUp {
+                button: geng::MouseButton::Left,
+                ..
+            } => {
+                if let Some((curve_index, curve)) =
+                    self.dragged_curve(cursor, level, selected_layer)
+                {
+                    level.modify().layers[selected_layer].curves[curve_index] = curve;
+                }
+                self.handle_drag = None;
+            }
+            geng::Event::MouseDown {
+                button: geng::MouseButton::Right,
+                ..
+            } => {
+                if !self.anchors.is_empty() {
+                    self.anchors.clear();
+                } else if let Some(index) = self.find_hovered_curve(cursor, level, selected_layer) {
+                    level.modify().layers[selected_layer].curves.remove(index);
+                }
+            }
+            geng::Event::KeyDown {
+                key: geng::Key::Enter,
+            } => {
+                self.finish(level, selected_layer);
+            }
+            geng::Event::KeyDown {
+                key: geng::Key::Backspace,
+            } => {
+                self.anchors.pop();
+            }
+            _ => {}
+        }
+    }
+
+    const NAME: &'static str = "Curve";
+    const EDITS_LAYER: bool = true;
+
+    fn ui<'a>(&'a mut self, cx: &'a geng::ui::Controller) -> Box<dyn geng::ui::Widget + 'a> {
+        use geng::ui::*;
+
+        let assets = self.assets.get();
+        let (tolerance, new_tolerance) = stepper(
+            cx,
+            "tolerance",
+            self.config.tolerance,
+            0.01,
+            default_tolerance(),
+        );
+        if let Some(new_tolerance) = new_tolerance {
+            self.config.tolerance = new_tolerance.max(0.01);
+        }
+        let mut options: Vec<&str> = assets.surfaces.keys().collect();
+        options.sort();
+        let options = column(
+            std::iter::once(tolerance)
+                .chain(options.into_iter().map(|name| {
+                    let button = Button::new(cx, name);
+                    if button.was_clicked() {
+                        self.config.selected_type = name.to_owned();
+                    }
+                    let mut widget: Box<dyn Widget + 'a> =
+                        Box::new(button.uniform_padding(8.0).align(vec2(0.0, 0.0)));
+                    if *name == self.config.selected_type {
+                        widget = Box::new(widget.background_color(Rgba::new(0.5, 0.5, 1.0, 0.5)))
+                    }
+                    widget
+                }))
+                .collect(),
+        );
+        options.boxed()
+    }
+
+    fn snap_anchor(&self) -> Option<vec2<f32>> {
+        self.anchors.last().copied()
+    }
+}
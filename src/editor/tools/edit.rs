@@ -8,13 +8,45 @@ impl EditorToolConfig for EditToolConfig {
     }
 }
 
+/// Scaling closer to zero than this would collapse the selection into a point
+const MIN_SCALE: f32 = 1e-3;
+
+#[derive(Clone, Copy)]
+enum TransformKind {
+    Grab,
+    Scale,
+    Rotate,
+}
+
 enum State {
     Idle,
-    DragSelection { start: vec2<f32> },
-    Copy { start: vec2<f32> },
-    Grab { start: vec2<f32> },
-    Scale { start: vec2<f32> },
-    Rotate { start: vec2<f32> },
+    DragSelection {
+        start: vec2<f32>,
+    },
+    Copy {
+        start: vec2<f32>,
+    },
+    /// Applied to the level as the cursor moves, recorded as a single history entry
+    Transform {
+        kind: TransformKind,
+        start: vec2<f32>,
+        center: vec2<f32>,
+        /// Transform that is currently applied to the level
+        applied: mat3<f32>,
+        original: Box<TransformOrigin>,
+    },
+}
+
+/// Everything that is transformed, as it was when the transform started,
+/// so that every move applies the whole transform to it instead of adding up steps
+struct TransformOrigin {
+    layer: LevelLayer,
+    cannons: Vec<features::cannon::Cannon>,
+    portals: Vec<Portal>,
+    /// Items that have some of their points selected
+    items: Vec<LevelItem>,
+    /// Also the selection to go back to if the transform is cancelled
+    selection: Vec<vec2<f32>>,
 }
 
 pub struct EditTool {
@@ -51,29 +83,185 @@ impl EditTool {
         }
         sum / count as f32
     }
-    fn transform(&self, level: &Level, cursor: &Cursor) -> mat3<f32> {
-        let center = self.find_selection_center(level);
-        match self.state {
+    /// None if the cursor is too close to the center to tell the scale
+    fn transform(&self, cursor: &Cursor) -> Option<mat3<f32>> {
+        Some(match self.state {
             State::Idle => mat3::identity(),
             State::DragSelection { .. } => mat3::identity(),
-            State::Grab { start } | State::Copy { start } => {
-                mat3::translate(cursor.snapped_world_pos - start)
-            }
-            State::Scale { start } => {
-                mat3::translate(center)
-                    * mat3::scale_uniform(
-                        (cursor.snapped_world_pos - center).len() / (start - center).len(),
-                    )
-                    * mat3::translate(-center)
+            State::Copy { start }
+            | State::Transform {
+                kind: TransformKind::Grab,
+                start,
+                ..
+            } => mat3::translate(cursor.snapped_world_pos - start),
+            State::Transform {
+                kind: TransformKind::Scale,
+                start,
+                center,
+                ..
+            } => {
+                let start_distance = (start - center).len();
+                if start_distance < MIN_SCALE {
+                    return None;
+                }
+                let scale = (cursor.snapped_world_pos - center).len() / start_distance;
+                if scale < MIN_SCALE {
+                    return None;
+                }
+                mat3::translate(center) * mat3::scale_uniform(scale) * mat3::translate(-center)
             }
-            State::Rotate { start } => {
+            State::Transform {
+                kind: TransformKind::Rotate,
+                start,
+                center,
+                ..
+            } => {
                 mat3::translate(center)
                     * mat3::rotate(
                         (cursor.snapped_world_pos - center).arg() - (start - center).arg(),
                     )
                     * mat3::translate(-center)
             }
+        })
+    }
+    fn start_transform(
+        &mut self,
+        cursor: &Cursor,
+        level: &mut Level,
+        selected_layer: usize,
+        kind: TransformKind,
+    ) {
+        if !matches!(self.state, State::Idle) {
+            return;
         }
+        let verb = match kind {
+            TransformKind::Grab => "move",
+            TransformKind::Scale => "scale",
+            TransformKind::Rotate => "rotate",
+        };
+        let layer = &level.layers[selected_layer];
+        let items: Vec<LevelItem> = itertools::chain![
+            (0..layer.surfaces.len())
+                .filter(|&i| [layer.surfaces[i].p1, layer.surfaces[i].p2]
+                    .into_iter()
+                    .any(|p| self.is_selected(p)))
+                .map(LevelItem::Surface),
+            (0..layer.curves.len())
+                .filter(|&i| layer.curves[i].points.iter().any(|&p| self.is_selected(p)))
+                .map(LevelItem::Curve),
+            (0..layer.tiles.len())
+                .filter(|&i| layer.tiles[i].points().any(|p| self.is_selected(p)))
+                .map(LevelItem::Tile),
+            (0..layer.objects.len())
+                .filter(|&i| self.is_selected(layer.objects[i].pos))
+                .map(LevelItem::Object),
+            (0..layer.prefabs.len())
+                .filter(|&i| self.is_selected(layer.prefabs[i].pos))
+                .map(LevelItem::Prefab),
+            (0..level.cannon.cannons.len())
+                .filter(|&i| self.is_selected(level.cannon.cannons[i].pos))
+                .map(LevelItem::Cannon),
+            (0..level.portals.len())
+                .filter(|&i| self.is_selected(level.portals[i].pos))
+                .map(LevelItem::Portal),
+        ]
+        .collect();
+        let surfaces = items
+            .iter()
+            .filter(|item| matches!(item, LevelItem::Surface(_)))
+            .count();
+        let tiles = items
+            .iter()
+            .filter(|item| matches!(item, LevelItem::Tile(_)))
+            .count();
+        level.begin_history_batch(format!("{verb} {surfaces} surfaces, {tiles} tiles"));
+        self.state = State::Transform {
+            kind,
+            start: cursor.snapped_world_pos,
+            center: self.find_selection_center(level),
+            applied: mat3::identity(),
+            original: Box::new(TransformOrigin {
+                layer: level.layers[selected_layer].clone(),
+                cannons: level.cannon.cannons.clone(),
+                portals: level.portals.clone(),
+                items,
+                selection: self.selected_vertices.clone(),
+            }),
+        };
+    }
+    fn cancel_transform(&mut self, level: &mut Level) {
+        if let State::Transform { original, .. } = mem::replace(&mut self.state, State::Idle) {
+            level.cancel_history_batch();
+            self.selected_vertices = original.selection;
+        }
+    }
+    /// Moves the selection from where it was at the start to where the cursor says
+    fn apply_transform(&mut self, cursor: &Cursor, level: &mut Level, selected_layer: usize) {
+        let Some(matrix) = self.transform(cursor) else {
+            return;
+        };
+        let State::Transform {
+            applied, original, ..
+        } = &mut self.state
+        else {
+            return;
+        };
+        if !level.in_history_batch() {
+            // The batch was ended from outside, like by undoing, so the origin is not valid anymore
+            self.state = State::Idle;
+            return;
+        }
+        if matrix == *applied {
+            return;
+        }
+        *applied = matrix;
+        let selection = &original.selection;
+        let transform = |p: vec2<f32>| -> vec2<f32> {
+            if selection.contains(&p) {
+                (matrix * p.extend(1.0)).into_2d()
+            } else {
+                p
+            }
+        };
+        let origin = &**original;
+        level.modify_items(selected_layer, &origin.items, |info| {
+            let (layer, target) = (&origin.layer, &mut info.layers[selected_layer]);
+            for &item in &origin.items {
+                match item {
+                    LevelItem::Surface(i) => {
+                        target.surfaces[i].p1 = transform(layer.surfaces[i].p1);
+                        target.surfaces[i].p2 = transform(layer.surfaces[i].p2);
+                    }
+                    LevelItem::Curve(i) => {
+                        for (p, &old) in target.curves[i]
+                            .points
+                            .iter_mut()
+                            .zip(&layer.curves[i].points)
+                        {
+                            *p = transform(old);
+                        }
+                    }
+                    LevelItem::Tile(i) => {
+                        for (p, old) in target.tiles[i].points_mut().zip(layer.tiles[i].points()) {
+                            *p = transform(old);
+                        }
+                    }
+                    LevelItem::Object(i) => {
+                        target.objects[i].pos = transform(layer.objects[i].pos);
+                    }
+                    LevelItem::Prefab(i) => {
+                        target.prefabs[i].pos = transform(layer.prefabs[i].pos);
+                    }
+                    LevelItem::Cannon(i) => {
+                        info.cannon.cannons[i].pos = transform(origin.cannons[i].pos);
+                    }
+                    LevelItem::Portal(i) => {
+                        info.portals[i].pos = transform(origin.portals[i].pos);
+                    }
+                }
+            }
+        });
+        self.selected_vertices = selection.iter().map(|&p| transform(p)).collect();
     }
     fn is_selected(&self, p: vec2<f32>) -> bool {
         self.selected_vertices.contains(&p)
@@ -106,7 +294,11 @@ impl EditorTool for EditTool {
         camera: &geng::Camera2d,
         framebuffer: &mut ugli::Framebuffer,
     ) {
-        let transform = self.transform(level, cursor);
+        // Transforms other than copying are already applied to the level
+        let transform = match self.state {
+            State::Copy { .. } => self.transform(cursor).unwrap_or_else(mat3::identity),
+            _ => mat3::identity(),
+        };
         let transform = |p: vec2<f32>| -> vec2<f32> {
             if self.is_selected(p) {
                 (transform * p.extend(1.0)).into_2d()
@@ -131,15 +323,18 @@ impl EditorTool for EditTool {
             }
         }
         for (index, tile) in level.layers[selected_layer].tiles.iter().enumerate() {
-            if tile.vertices.into_iter().any(|p| self.is_selected(p)) {
-                self.geng.draw2d().draw2d(
-                    framebuffer,
-                    camera,
-                    &draw2d::Polygon::new(
-                        tile.vertices.iter().copied().map(transform).collect(),
-                        Rgba::new(1.0, 1.0, 1.0, 0.5),
-                    ),
-                );
+            if tile.points().any(|p| self.is_selected(p)) {
+                let triangles = match self.state {
+                    State::Copy { .. } => tile.map_points(|p| transform(p)).triangles(),
+                    _ => level.tile_triangles(selected_layer, index).to_vec(),
+                };
+                for triangle in triangles {
+                    self.geng.draw2d().draw2d(
+                        framebuffer,
+                        camera,
+                        &draw2d::Polygon::new(triangle.to_vec(), Rgba::new(1.0, 1.0, 1.0, 0.5)),
+                    );
+                }
             }
         }
         for &p in &self.selected_vertices {
@@ -170,6 +365,8 @@ impl EditorTool for EditTool {
         level: &mut Level,
         selected_layer: usize,
     ) {
+        // Copying and pasting change the level under a transform in progress
+        let idle = matches!(self.state, State::Idle);
         match event {
             geng::Event::MouseDown { button, .. } => match self.state {
                 State::Idle => {
@@ -180,61 +377,27 @@ impl EditorTool for EditTool {
                     }
                 }
                 State::DragSelection { start } => {}
-                State::Grab { .. } | State::Scale { .. } | State::Rotate { .. } => {
+                State::Transform { .. } => {
                     if *button == geng::MouseButton::Left {
-                        let matrix = self.transform(level, cursor);
-                        let transform = |p: &mut vec2<f32>| {
-                            if self.is_selected(*p) {
-                                *p = (matrix * p.extend(1.0)).into_2d();
-                            }
-                        };
-                        let level = level.modify();
-                        for surface in &mut level.layers[selected_layer].surfaces {
-                            transform(&mut surface.p1);
-                            transform(&mut surface.p2);
-                        }
-                        for tile in &mut level.layers[selected_layer].tiles {
-                            for p in &mut tile.vertices {
-                                transform(p);
-                            }
-                        }
-                        for p in &mut self.selected_vertices {
-                            *p = (matrix * p.extend(1.0)).into_2d();
-                        }
+                        self.apply_transform(cursor, level, selected_layer);
+                        level.end_history_batch();
+                        self.state = State::Idle;
+                    } else {
+                        self.cancel_transform(level);
                     }
-                    self.state = State::Idle;
                 }
                 State::Copy { .. } => {
                     if *button == geng::MouseButton::Left {
-                        let matrix = self.transform(level, cursor);
-                        let transform = |p: &mut vec2<f32>| {
-                            *p = (matrix * p.extend(1.0)).into_2d();
-                        };
-                        let level = level.modify();
-                        let mut new_surfaces = Vec::new();
-                        for surface in &mut level.layers[selected_layer].surfaces {
-                            if self.is_selected(surface.p1) && self.is_selected(surface.p2) {
-                                let mut new_surface = surface.clone();
-                                transform(&mut new_surface.p1);
-                                transform(&mut new_surface.p2);
-                                new_surfaces.push(new_surface);
-                            }
-                        }
-                        level.layers[selected_layer].surfaces.extend(new_surfaces);
-                        let mut new_tiles = Vec::new();
-                        for tile in &mut level.layers[selected_layer].tiles {
-                            if tile.vertices.iter().all(|&p| self.is_selected(p)) {
-                                let mut new_tile = tile.clone();
-                                for p in &mut new_tile.vertices {
-                                    transform(p);
-                                }
-                                new_tiles.push(new_tile);
-                            }
-                        }
-                        level.layers[selected_layer].tiles.extend(new_tiles);
-                        for p in &mut self.selected_vertices {
-                            *p = (matrix * p.extend(1.0)).into_2d();
-                        }
+                        let matrix = self.transform(cursor).unwrap_or_else(mat3::identity);
+                        let center = self.find_selection_center(level);
+                        let fragment =
+                            copy_selection(level, selected_layer, center, |p| self.is_selected(p));
+                        self.selected_vertices = paste(
+                            level.modify_with_label("duplicate"),
+                            selected_layer,
+                            &fragment,
+                            (matrix * center.extend(1.0)).into_2d(),
+                        );
                     }
                     self.state = State::Idle;
                 }
@@ -289,7 +452,21 @@ impl EditorTool for EditTool {
                         level.layers[selected_layer]
                             .tiles
                             .iter()
-                            .flat_map(|tile| tile.vertices)
+                            .flat_map(|tile| tile.points()),
+                        level.layers[selected_layer]
+                            .curves
+                            .iter()
+                            .flat_map(|curve| curve.points.iter().copied()),
+                        level.layers[selected_layer]
+                            .objects
+                            .iter()
+                            .map(|object| object.pos),
+                        level.layers[selected_layer]
+                            .prefabs
+                            .iter()
+                            .map(|instance| instance.pos),
+                        level.cannon.cannons.iter().map(|cannon| cannon.pos),
+                        level.portals.iter().map(|portal| portal.pos),
                     ] {
                         if aabb.contains(p) && !self.is_selected(p) {
                             self.selected_vertices.push(p);
@@ -312,41 +489,78 @@ impl EditorTool for EditTool {
                     // }
                 }
             }
+            geng::Event::MouseMove { .. } => {
+                self.apply_transform(cursor, level, selected_layer);
+            }
             geng::Event::KeyDown { key: geng::Key::G } => {
-                self.state = State::Grab {
-                    start: cursor.snapped_world_pos,
-                };
+                self.start_transform(cursor, level, selected_layer, TransformKind::Grab);
             }
-            geng::Event::KeyDown { key: geng::Key::C }
-                if self.geng.window().is_key_pressed(geng::Key::LCtrl) =>
+            geng::Event::KeyDown { key: geng::Key::D }
+                if idle && self.geng.window().is_key_pressed(geng::Key::LCtrl) =>
             {
                 self.state = State::Copy {
                     start: cursor.snapped_world_pos,
                 };
             }
-            geng::Event::KeyDown { key: geng::Key::S } => {
-                self.state = State::Scale {
-                    start: cursor.snapped_world_pos,
-                };
+            geng::Event::KeyDown { key: geng::Key::C }
+                if idle && self.geng.window().is_key_pressed(geng::Key::LCtrl) =>
+            {
+                let center = self.find_selection_center(level);
+                save_clipboard(&copy_selection(level, selected_layer, center, |p| {
+                    self.is_selected(p)
+                }));
             }
-            geng::Event::KeyDown { key: geng::Key::R } => {
-                self.state = State::Rotate {
-                    start: cursor.snapped_world_pos,
-                };
+            geng::Event::KeyDown { key: geng::Key::X }
+                if idle && self.geng.window().is_key_pressed(geng::Key::LCtrl) =>
+            {
+                let center = self.find_selection_center(level);
+                save_clipboard(&copy_selection(level, selected_layer, center, |p| {
+                    self.is_selected(p)
+                }));
+                remove_selection(
+                    level.modify_with_label("cut"),
+                    selected_layer,
+                    |p| self.is_selected(p),
+                    true,
+                );
+                self.clear_selection();
+            }
+            geng::Event::KeyDown { key: geng::Key::V }
+                if idle && self.geng.window().is_key_pressed(geng::Key::LCtrl) =>
+            {
+                let Some(fragment) = load_clipboard() else { return };
+                self.selected_vertices = paste(
+                    level.modify_with_label("paste"),
+                    selected_layer,
+                    &fragment,
+                    cursor.snapped_world_pos,
+                );
+            }
+            geng::Event::KeyDown { key: geng::Key::S }
+                if !self.geng.window().is_key_pressed(geng::Key::LCtrl) =>
+            {
+                self.start_transform(cursor, level, selected_layer, TransformKind::Scale);
+            }
+            geng::Event::KeyDown { key: geng::Key::R }
+                if !self.geng.window().is_key_pressed(geng::Key::LCtrl) =>
+            {
+                self.start_transform(cursor, level, selected_layer, TransformKind::Rotate);
+            }
+            geng::Event::KeyDown {
+                key: geng::Key::Escape,
+            } => {
+                self.cancel_transform(level);
             }
             geng::Event::KeyDown {
                 key: geng::Key::Delete,
             } => {
                 if let State::Idle = self.state {
-                    let level = level.modify();
-                    level.layers[selected_layer].surfaces.retain(|surface| {
-                        ![surface.p1, surface.p2]
-                            .iter()
-                            .any(|&p| self.is_selected(p))
-                    });
-                    level.layers[selected_layer]
-                        .tiles
-                        .retain(|tile| !tile.vertices.iter().any(|&p| self.is_selected(p)));
+                    remove_selection(
+                        level.modify(),
+                        selected_layer,
+                        |p| self.is_selected(p),
+                        false,
+                    );
                 }
             }
             _ => {}
@@ -354,6 +568,7 @@ impl EditorTool for EditTool {
     }
 
     const NAME: &'static str = "Edit";
+    const EDITS_LAYER: bool = true;
 
     fn ui<'a>(&'a mut self, _cx: &'a geng::ui::Controller) -> Box<dyn geng::ui::Widget + 'a> {
         use geng::ui::*;
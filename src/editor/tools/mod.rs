@@ -2,18 +2,22 @@
 
 use super::*;
 
+mod curve;
 mod edit;
 mod endpoint;
 mod object;
 mod portal;
+mod prefab;
 mod progress;
 mod surface;
 mod tile;
 
+pub use curve::*;
 pub use edit::*;
 pub use endpoint::*;
 pub use object::*;
 pub use portal::*;
+pub use prefab::*;
 pub use progress::*;
 pub use surface::*;
 pub use tile::*;
@@ -95,6 +95,7 @@ impl EditorTool for ObjectTool {
     }
 
     const NAME: &'static str = "Object";
+    const EDITS_LAYER: bool = true;
 
     fn ui<'a>(&'a mut self, cx: &'a geng::ui::Controller) -> Box<dyn geng::ui::Widget + 'a> {
         use geng::ui::*;
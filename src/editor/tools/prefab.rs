@@ -0,0 +1,239 @@
+use super::*;
+
+pub struct PrefabToolConfig {
+    snap_distance: f32,
+    selected: Option<String>,
+}
+
+impl EditorToolConfig for PrefabToolConfig {
+    fn default(assets: &AssetsHandle) -> Self {
+        Self {
+            snap_distance: assets.get().config.snap_distance,
+            selected: None,
+        }
+    }
+}
+
+pub struct PrefabTool {
+    geng: Geng,
+    config: PrefabToolConfig,
+    /// Known prefabs, updated on every event since ui has no access to the level
+    names: Vec<String>,
+    start_drag: Option<vec2<f32>>,
+}
+
+impl PrefabTool {
+    fn find_hovered_instance(
+        &self,
+        cursor: &Cursor,
+        level: &Level,
+        selected_layer: usize,
+    ) -> Option<usize> {
+        level.layers[selected_layer]
+            .prefabs
+            .iter()
+            .enumerate()
+            .filter(|(_index, instance)| {
+                (instance.pos - cursor.world_pos).len() < self.config.snap_distance
+            })
+            .min_by_key(|(_index, instance)| r32((instance.pos - cursor.world_pos).len()))
+            .map(|(index, _instance)| index)
+    }
+
+    /// Turns everything inside the region into a prefab and places an instance of it instead,
+    /// either a new one or the selected one, replacing its contents everywhere
+    fn make_prefab(
+        &mut self,
+        level: &mut Level,
+        selected_layer: usize,
+        region: Aabb2<f32>,
+        replace_selected: bool,
+    ) {
+        let (name, label) = match &self.config.selected {
+            Some(name) if replace_selected => (name.clone(), format!("update prefab {name}")),
+            _ => {
+                let name = (1..)
+                    .map(|i| format!("prefab-{i}"))
+                    .find(|name| !level.prefabs().contains_key(name))
+                    .unwrap();
+                let label = format!("make prefab {name}");
+                (name, label)
+            }
+        };
+        let center = region.center();
+        let fragment = copy_selection(level, selected_layer, center, |p| region.contains(p));
+        let info = level.modify_with_label(label);
+        remove_selection(info, selected_layer, |p| region.contains(p), true);
+        info.layers[selected_layer]
+            .prefabs
+            .push(PrefabInstance::new(name.clone(), center));
+        level.set_prefab(&name, fragment);
+        self.config.selected = Some(name);
+    }
+}
+
+impl EditorTool for PrefabTool {
+    type Config = PrefabToolConfig;
+    fn new(geng: &Geng, _assets: &AssetsHandle, config: PrefabToolConfig) -> Self {
+        Self {
+            geng: geng.clone(),
+            config,
+            names: vec![],
+            start_drag: None,
+        }
+    }
+    fn draw(
+        &self,
+        cursor: &Cursor,
+        level: &Level,
+        selected_layer: usize,
+        camera: &geng::Camera2d,
+        framebuffer: &mut ugli::Framebuffer,
+    ) {
+        if let Some(start) = self.start_drag {
+            self.geng.draw2d().draw2d(
+                framebuffer,
+                camera,
+                &draw2d::Quad::new(
+                    Aabb2::from_corners(start, cursor.snapped_world_pos),
+                    Rgba::new(0.0, 0.0, 1.0, 0.3),
+                ),
+            );
+        }
+        let hovered = self.find_hovered_instance(cursor, level, selected_layer);
+        for (index, instance) in level.layers[selected_layer].prefabs.iter().enumerate() {
+            let color = if Some(index) == hovered {
+                Rgba::new(1.0, 0.0, 0.0, 0.5)
+            } else {
+                Rgba::new(0.0, 0.0, 1.0, 0.5)
+            };
+            self.geng.draw2d().draw2d(
+                framebuffer,
+                camera,
+                &draw2d::Quad::new(Aabb2::point(instance.pos).extend_uniform(0.3), color),
+            );
+            self.geng.draw2d().draw2d(
+                framebuffer,
+                camera,
+                &draw2d::Segment::new(
+                    Segment(
+                        instance.pos,
+                        instance.pos + vec2(instance.scale, 0.0).rotate(instance.rot),
+                    ),
+                    0.1,
+                    color,
+                ),
+            );
+        }
+    }
+    fn handle_event(
+        &mut self,
+        cursor: &Cursor,
+        event: &geng::Event,
+        level: &mut Level,
+        selected_layer: usize,
+    ) {
+        self.names = level.prefabs().keys().cloned().collect();
+        self.names.sort();
+        let shift = self.geng.window().is_key_pressed(geng::Key::LShift);
+        let ctrl = self.geng.window().is_key_pressed(geng::Key::LCtrl);
+        match event {
+            geng::Event::MouseDown {
+                button: geng::MouseButton::Left,
+                ..
+            } => {
+                if shift {
+                    self.start_drag = Some(cursor.snapped_world_pos);
+                } else if let Some(name) = &self.config.selected {
+                    if level.prefabs().contains_key(name) {
+                        level
+                            .modify_with_label(format!("place prefab {name}"))
+                            .layers[selected_layer]
+                            .prefabs
+                            .push(PrefabInstance::new(name.clone(), cursor.snapped_world_pos));
+                    }
+                }
+            }
+            geng::Event::MouseUp {
+                button: geng::MouseButton::Left,
+                ..
+            } => {
+                if let Some(start) = self.start_drag.take() {
+                    let region = Aabb2::from_corners(start, cursor.snapped_world_pos);
+                    if region.width() > 0.0 && region.height() > 0.0 {
+                        self.make_prefab(level, selected_layer, region, ctrl);
+                    }
+                }
+            }
+            geng::Event::MouseDown {
+                button: geng::MouseButton::Right,
+                ..
+            } => {
+                if let Some(index) = self.find_hovered_instance(cursor, level, selected_layer) {
+                    level.modify().layers[selected_layer].prefabs.remove(index);
+                }
+            }
+            geng::Event::KeyDown { key: geng::Key::R } => {
+                if let Some(index) = self.find_hovered_instance(cursor, level, selected_layer) {
+                    let delta = if shift { -15.0 } else { 15.0 };
+                    level.modify().layers[selected_layer].prefabs[index].rot +=
+                        Angle::from_degrees(delta);
+                }
+            }
+            geng::Event::KeyDown { key: geng::Key::S } if !ctrl => {
+                if let Some(index) = self.find_hovered_instance(cursor, level, selected_layer) {
+                    let factor = if shift { 1.0 / 1.1 } else { 1.1 };
+                    level.modify().layers[selected_layer].prefabs[index].scale *= factor;
+                }
+            }
+            // Unpacked contents can be edited and made into a new prefab by shift dragging over them,
+            // or saved back into the selected prefab by also holding ctrl
+            geng::Event::KeyDown { key: geng::Key::E } => {
+                if let Some(index) = self.find_hovered_instance(cursor, level, selected_layer) {
+                    let instance = level.layers[selected_layer].prefabs[index].clone();
+                    let Some(prefab) = level.prefabs().get(&instance.name) else {
+                        return;
+                    };
+                    let prefab = prefab.flatten(level.prefabs());
+                    let info = level.modify_with_label(format!("unpack prefab {}", instance.name));
+                    info.layers[selected_layer].prefabs.remove(index);
+                    info.insert_fragment(selected_layer, &prefab, instance.transform());
+                    self.config.selected = Some(instance.name);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    const NAME: &'static str = "Prefab";
+    const EDITS_LAYER: bool = true;
+
+    fn ui<'a>(&'a mut self, cx: &'a geng::ui::Controller) -> Box<dyn geng::ui::Widget + 'a> {
+        use geng::ui::*;
+
+        let new = Button::new(cx, "new prefab");
+        if new.was_clicked() {
+            self.config.selected = None;
+        }
+        let mut widgets: Vec<Box<dyn Widget + 'a>> = vec![];
+        let mut new: Box<dyn Widget + 'a> =
+            Box::new(new.uniform_padding(8.0).align(vec2(0.0, 0.0)));
+        if self.config.selected.is_none() {
+            new = Box::new(new.background_color(Rgba::new(0.5, 0.5, 1.0, 0.5)));
+        }
+        widgets.push(new);
+        for name in &self.names {
+            let button = Button::new(cx, name);
+            if button.was_clicked() {
+                self.config.selected = Some(name.clone());
+            }
+            let mut widget: Box<dyn Widget + 'a> =
+                Box::new(button.uniform_padding(8.0).align(vec2(0.0, 0.0)));
+            if self.config.selected.as_ref() == Some(name) {
+                widget = Box::new(widget.background_color(Rgba::new(0.5, 0.5, 1.0, 0.5)));
+            }
+            widgets.push(widget);
+        }
+        column(widgets).boxed()
+    }
+}
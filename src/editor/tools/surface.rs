@@ -3,6 +3,10 @@ use super::*;
 pub struct SurfaceToolConfig {
     snap_distance: f32,
     selected_type: String,
+    /// Clicks are chained into connected surfaces instead of dragging each one
+    polyline: bool,
+    /// Closed polylines are filled with tiles of this type
+    fill_type: Option<String>,
 }
 
 impl EditorToolConfig for SurfaceToolConfig {
@@ -10,6 +14,8 @@ impl EditorToolConfig for SurfaceToolConfig {
         Self {
             snap_distance: assets.get().config.snap_distance,
             selected_type: assets.get().surfaces.keys().min().unwrap().to_owned(),
+            polyline: false,
+            fill_type: None,
         }
     }
 }
@@ -18,6 +24,7 @@ pub struct SurfaceTool {
     geng: Geng,
     assets: AssetsHandle,
     start_drag: Option<vec2<f32>>,
+    polyline: Vec<vec2<f32>>,
     wind_drag: Option<(usize, vec2<f32>)>,
     saved_flow: f32,
     config: SurfaceToolConfig,
@@ -46,13 +53,73 @@ impl SurfaceTool {
             return None;
         }
         if self.geng.window().is_key_pressed(geng::Key::LShift) {
-            let arg = (p2 - p1).arg().as_radians();
-            let round_step = 15.0 * f32::PI / 180.0;
-            let arg = (arg / round_step).round() * round_step;
-            p2 = p1 + vec2((p2 - p1).len(), 0.0).rotate(Angle::from_radians(arg));
+            p2 = snap_angle(p1, p2, 15.0);
         }
         Some(Segment(p1, p2))
     }
+
+    fn next_polyline_point(&self, cursor: &Cursor) -> vec2<f32> {
+        let pos = cursor.snapped_world_pos;
+        match self.polyline.last() {
+            Some(&last) if self.geng.window().is_key_pressed(geng::Key::LShift) => {
+                snap_angle(last, pos, 15.0)
+            }
+            _ => pos,
+        }
+    }
+
+    /// Clicking near the first point closes the loop
+    fn closes_polyline(&self, pos: vec2<f32>) -> bool {
+        self.polyline.len() >= 3 && (pos - self.polyline[0]).len() < self.config.snap_distance
+    }
+
+    fn finish_polyline(&mut self, level: &mut Level, selected_layer: usize, closed: bool) {
+        let mut points = mem::take(&mut self.polyline);
+        if points.len() < 2 {
+            return;
+        }
+        // Surfaces are solid on their right side, so a clockwise loop is solid inside
+        if closed && polygon_area(&points) > 0.0 {
+            points.reverse();
+        }
+        let layer = &mut level.modify().layers[selected_layer];
+        let segments = if closed {
+            points.len()
+        } else {
+            points.len() - 1
+        };
+        for i in 0..segments {
+            layer.surfaces.push(Surface {
+                p1: points[i],
+                p2: points[(i + 1) % points.len()],
+                flow: 0.0,
+                type_name: self.config.selected_type.clone(),
+            });
+        }
+        if let Some(fill_type) = self.config.fill_type.as_ref().filter(|_| closed) {
+            layer.tiles.push(Tile::new(points, fill_type.clone()));
+        }
+    }
+}
+
+/// Surfaces connected to the given one through shared endpoints
+fn connected_surfaces(surfaces: &[Surface], index: usize) -> Vec<usize> {
+    let mut result = vec![index];
+    let mut visited = HashSet::from([index]);
+    let mut i = 0;
+    while i < result.len() {
+        let current = &surfaces[result[i]];
+        for (other_index, other) in surfaces.iter().enumerate() {
+            let connected = [current.p1, current.p2]
+                .into_iter()
+                .any(|p| (p - other.p1).len() < EPS || (p - other.p2).len() < EPS);
+            if connected && visited.insert(other_index) {
+                result.push(other_index);
+            }
+        }
+        i += 1;
+    }
+    result
 }
 
 impl EditorTool for SurfaceTool {
@@ -62,6 +129,7 @@ impl EditorTool for SurfaceTool {
             geng: geng.clone(),
             assets: assets.clone(),
             start_drag: None,
+            polyline: vec![],
             wind_drag: None,
             saved_flow: 0.0,
             config,
@@ -75,7 +143,22 @@ impl EditorTool for SurfaceTool {
         camera: &geng::Camera2d,
         framebuffer: &mut ugli::Framebuffer,
     ) {
-        if let Some(Segment(p1, p2)) = self.drag(cursor) {
+        if !self.polyline.is_empty() {
+            let next = self.next_polyline_point(cursor);
+            let color = if self.closes_polyline(next) {
+                Rgba::new(0.0, 1.0, 0.0, 0.5)
+            } else {
+                Rgba::new(1.0, 1.0, 1.0, 0.5)
+            };
+            let points: Vec<vec2<f32>> = self.polyline.iter().copied().chain([next]).collect();
+            for segment in points.windows(2) {
+                self.geng.draw2d().draw2d(
+                    framebuffer,
+                    camera,
+                    &draw2d::Segment::new(Segment(segment[0], segment[1]), 0.1, color),
+                );
+            }
+        } else if let Some(Segment(p1, p2)) = self.drag(cursor) {
             self.geng.draw2d().draw2d(
                 framebuffer,
                 camera,
@@ -128,12 +211,58 @@ impl EditorTool for SurfaceTool {
         selected_layer: usize,
     ) {
         match event {
+            geng::Event::MouseDown {
+                button: geng::MouseButton::Left,
+                ..
+            } if self.config.polyline => {
+                let pos = self.next_polyline_point(cursor);
+                if self.closes_polyline(pos) {
+                    self.finish_polyline(level, selected_layer, true);
+                } else if self.polyline.last().map_or(true, |&last| {
+                    (pos - last).len() >= self.config.snap_distance
+                }) {
+                    self.polyline.push(pos);
+                }
+            }
             geng::Event::MouseDown {
                 button: geng::MouseButton::Left,
                 ..
             } => {
                 self.start_drag = Some(cursor.snapped_world_pos);
             }
+            geng::Event::KeyDown {
+                key: geng::Key::Enter,
+            } => {
+                self.finish_polyline(level, selected_layer, false);
+            }
+            geng::Event::KeyDown {
+                key: geng::Key::Backspace,
+            } => {
+                self.polyline.pop();
+            }
+            geng::Event::KeyDown { key: geng::Key::P } => {
+                self.config.polyline = !self.config.polyline;
+                self.polyline.clear();
+            }
+            geng::Event::KeyDown { key: geng::Key::F } => {
+                if !self.polyline.is_empty() {
+                    self.polyline.reverse();
+                } else if let Some(index) = self.find_hovered_surface(cursor, level, selected_layer)
+                {
+                    // Shift flips the whole connected contour
+                    let indices = if self.geng.window().is_key_pressed(geng::Key::LShift) {
+                        connected_surfaces(&level.layers[selected_layer].surfaces, index)
+                    } else {
+                        vec![index]
+                    };
+                    let surfaces = &mut level.modify().layers[selected_layer].surfaces;
+                    for index in indices {
+                        let surface = &mut surfaces[index];
+                        mem::swap(&mut surface.p1, &mut surface.p2);
+                        surface.flow = -surface.flow;
+                    }
+                }
+            }
             geng::Event::MouseUp {
                 button: geng::MouseButton::Left,
                 ..
@@ -155,7 +284,10 @@ impl EditorTool for SurfaceTool {
                 button: geng::MouseButton::Right,
                 ..
             } => {
-                if let Some(index) = self.find_hovered_surface(cursor, level, selected_layer) {
+                if !self.polyline.is_empty() {
+                    self.polyline.clear();
+                } else if let Some(index) = self.find_hovered_surface(cursor, level, selected_layer)
+                {
                     level.modify().layers[selected_layer].surfaces.remove(index);
                 }
             }
@@ -195,30 +327,60 @@ impl EditorTool for SurfaceTool {
     }
 
     const NAME: &'static str = "Surface";
+    const EDITS_LAYER: bool = true;
 
     fn ui<'a>(&'a mut self, cx: &'a geng::ui::Controller) -> Box<dyn geng::ui::Widget + 'a> {
         use geng::ui::*;
 
         let assets = self.assets.get();
+        let (polyline, polyline_clicked) = toggle(cx, "polyline", self.config.polyline);
+        if polyline_clicked {
+            self.config.polyline = !self.config.polyline;
+            self.polyline.clear();
+        }
+        // Cycles through tile types and no fill
+        let fill = Button::new(
+            cx,
+            &format!(
+                "fill loops: {}",
+                self.config.fill_type.as_deref().unwrap_or("off")
+            ),
+        );
+        if fill.was_clicked() {
+            let mut tiles: Vec<&String> = assets.tiles.keys().collect();
+            tiles.sort();
+            self.config.fill_type = match &self.config.fill_type {
+                None => tiles.first().map(|name| name.to_string()),
+                Some(current) => tiles
+                    .iter()
+                    .skip_while(|&&name| name != current)
+                    .nth(1)
+                    .map(|name| name.to_string()),
+            };
+        }
         let mut options: Vec<&str> = assets.surfaces.keys().collect();
         options.sort();
         let options = column(
-            options
+            [polyline, fill.uniform_padding(4.0).boxed()]
                 .into_iter()
-                .map(|name| {
+                .chain(options.into_iter().map(|name| {
                     let button = Button::new(cx, name);
                     if button.was_clicked() {
                         self.config.selected_type = name.to_owned();
                     }
-                    let mut widget: Box<dyn Widget> =
+                    let mut widget: Box<dyn Widget + 'a> =
                         Box::new(button.uniform_padding(8.0).align(vec2(0.0, 0.0)));
                     if *name == self.config.selected_type {
                         widget = Box::new(widget.background_color(Rgba::new(0.5, 0.5, 1.0, 0.5)))
                     }
                     widget
-                })
+                }))
                 .collect(),
         );
         options.boxed()
     }
+
+    fn snap_anchor(&self) -> Option<vec2<f32>> {
+        self.polyline.last().copied().or(self.start_drag)
+    }
 }
@@ -3,6 +3,8 @@ use super::*;
 pub struct TileToolConfig {
     snap_distance: f32,
     selected_type: String,
+    /// Clicks build a polygon until it is closed instead of a triangle
+    polygon: bool,
 }
 
 impl EditorToolConfig for TileToolConfig {
@@ -10,6 +12,7 @@ impl EditorToolConfig for TileToolConfig {
         Self {
             snap_distance: assets.get().config.snap_distance,
             selected_type: assets.get().tiles.keys().min().unwrap().to_owned(),
+            polygon: false,
         }
     }
 }
@@ -18,6 +21,8 @@ pub struct TileTool {
     geng: Geng,
     assets: AssetsHandle,
     points: Vec<vec2<f32>>,
+    /// Tile and the position of its vertex being dragged
+    vertex_drag: Option<(usize, vec2<f32>)>,
     wind_drag: Option<(usize, vec2<f32>)>,
     saved_flow: vec2<f32>,
     config: TileToolConfig,
@@ -30,17 +35,56 @@ impl TileTool {
         level: &Level,
         selected_layer: usize,
     ) -> Option<usize> {
-        'tile_loop: for (index, tile) in level.layers[selected_layer].tiles.iter().enumerate() {
-            for i in 0..3 {
-                let p1 = tile.vertices[i];
-                let p2 = tile.vertices[(i + 1) % 3];
-                if vec2::skew(p2 - p1, cursor.world_pos - p1) < 0.0 {
-                    continue 'tile_loop;
-                }
-            }
-            return Some(index);
+        (0..level.layers[selected_layer].tiles.len())
+            .position(|index| level.tile_contains(selected_layer, index, cursor.world_pos))
+    }
+
+    fn find_hovered_vertex(
+        &self,
+        cursor: &Cursor,
+        level: &Level,
+        selected_layer: usize,
+    ) -> Option<(usize, vec2<f32>)> {
+        level.layers[selected_layer]
+            .tiles
+            .iter()
+            .enumerate()
+            .flat_map(|(index, tile)| tile.points().map(move |p| (index, p)))
+            .filter(|&(_index, p)| (p - cursor.world_pos).len() < self.config.snap_distance)
+            .min_by_key(|&(_index, p)| r32((p - cursor.world_pos).len()))
+    }
+
+    /// Clicking near the first point closes the polygon
+    fn closes_polygon(&self, pos: vec2<f32>) -> bool {
+        self.points.len() >= 3 && (pos - self.points[0]).len() < self.config.snap_distance
+    }
+
+    fn finish_polygon(&mut self, level: &mut Level, selected_layer: usize) {
+        let points = mem::take(&mut self.points);
+        if points.len() < 3 || polygon_area(&points).abs() < self.config.snap_distance.sqr() {
+            return;
         }
-        None
+        level.modify().layers[selected_layer]
+            .tiles
+            .push(Tile::new(points, self.config.selected_type.clone()));
+    }
+
+    /// Cuts the polygon out of the tile it is drawn inside of
+    fn finish_hole(&mut self, level: &mut Level, selected_layer: usize) {
+        let points = mem::take(&mut self.points);
+        if points.len() < 3 {
+            return;
+        }
+        let Some(index) = (0..level.layers[selected_layer].tiles.len()).position(|index| {
+            points
+                .iter()
+                .all(|&p| level.tile_contains(selected_layer, index, p))
+        }) else {
+            return;
+        };
+        level.modify().layers[selected_layer].tiles[index]
+            .holes
+            .push(points);
     }
 }
 
@@ -52,6 +96,7 @@ impl EditorTool for TileTool {
             assets: assets.clone(),
             wind_drag: None,
             points: vec![],
+            vertex_drag: None,
             saved_flow: vec2::ZERO,
             config,
         }
@@ -64,14 +109,42 @@ impl EditorTool for TileTool {
         camera: &geng::Camera2d,
         framebuffer: &mut ugli::Framebuffer,
     ) {
-        if self.points.is_empty() {
-            if let Some(index) = self.find_hovered_tile(cursor, level, selected_layer) {
-                let tile = &level.layers[selected_layer].tiles[index];
+        if let Some((index, old)) = self.vertex_drag {
+            let tile = &level.layers[selected_layer].tiles[index];
+            let tile = tile.map_points(|p| {
+                if p == old {
+                    cursor.snapped_world_pos
+                } else {
+                    p
+                }
+            });
+            for triangle in tile.triangles() {
                 self.geng.draw2d().draw2d(
                     framebuffer,
                     camera,
-                    &draw2d::Polygon::new(tile.vertices.into(), Rgba::new(0.0, 0.0, 1.0, 0.5)),
+                    &draw2d::Polygon::new(triangle.to_vec(), Rgba::new(0.0, 0.0, 1.0, 0.5)),
                 );
+            }
+        } else if self.points.is_empty() {
+            if let Some(index) = self.find_hovered_tile(cursor, level, selected_layer) {
+                let tile = &level.layers[selected_layer].tiles[index];
+                for triangle in level.tile_triangles(selected_layer, index).iter() {
+                    self.geng.draw2d().draw2d(
+                        framebuffer,
+                        camera,
+                        &draw2d::Polygon::new(triangle.to_vec(), Rgba::new(0.0, 0.0, 1.0, 0.5)),
+                    );
+                }
+                for p in tile.points() {
+                    self.geng.draw2d().draw2d(
+                        framebuffer,
+                        camera,
+                        &draw2d::Quad::new(
+                            Aabb2::point(p).extend_uniform(0.1),
+                            Rgba::new(0.0, 0.0, 1.0, 0.8),
+                        ),
+                    );
+                }
                 if self.wind_drag.is_none() {
                     self.geng.draw2d().draw2d(
                         framebuffer,
@@ -96,6 +169,23 @@ impl EditorTool for TileTool {
                 );
             }
             match *self.points {
+                _ if self.config.polygon => {
+                    let next = cursor.snapped_world_pos;
+                    let color = if self.closes_polygon(next) {
+                        Rgba::new(0.0, 1.0, 0.0, 0.5)
+                    } else {
+                        Rgba::new(1.0, 1.0, 1.0, 0.5)
+                    };
+                    let points: Vec<vec2<f32>> =
+                        self.points.iter().copied().chain([next]).collect();
+                    for segment in points.windows(2) {
+                        self.geng.draw2d().draw2d(
+                            framebuffer,
+                            camera,
+                            &draw2d::Segment::new(Segment(segment[0], segment[1]), 0.1, color),
+                        );
+                    }
+                }
                 [p1] => {
                     self.geng.draw2d().draw2d(
                         framebuffer,
@@ -140,6 +230,57 @@ impl EditorTool for TileTool {
         selected_layer: usize,
     ) {
         match event {
+            geng::Event::MouseDown {
+                button: geng::MouseButton::Left,
+                ..
+            } if self.points.is_empty() && self.geng.window().is_key_pressed(geng::Key::LCtrl) => {
+                self.vertex_drag = self.find_hovered_vertex(cursor, level, selected_layer);
+            }
+            geng::Event::MouseUp {
+                button: geng::MouseButton::Left,
+                ..
+            } => {
+                if let Some((index, old)) = self.vertex_drag.take() {
+                    let tile = &mut level.modify().layers[selected_layer].tiles[index];
+                    for p in tile.points_mut() {
+                        if *p == old {
+                            *p = cursor.snapped_world_pos;
+                        }
+                    }
+                }
+            }
+            geng::Event::MouseDown {
+                button: geng::MouseButton::Left,
+                ..
+            } if self.config.polygon => {
+                let pos = cursor.snapped_world_pos;
+                if self.closes_polygon(pos) {
+                    self.finish_polygon(level, selected_layer);
+                } else if self
+                    .points
+                    .iter()
+                    .all(|&p| (p - pos).len() >= self.config.snap_distance)
+                {
+                    self.points.push(pos);
+                }
+            }
+            geng::Event::KeyDown {
+                key: geng::Key::Enter,
+            } => {
+                self.finish_polygon(level, selected_layer);
+            }
+            geng::Event::KeyDown { key: geng::Key::H } => {
+                self.finish_hole(level, selected_layer);
+            }
+            geng::Event::KeyDown {
+                key: geng::Key::Backspace,
+            } => {
+                self.points.pop();
+            }
+            geng::Event::KeyDown { key: geng::Key::P } => {
+                self.config.polygon = !self.config.polygon;
+                self.points.clear();
+            }
             geng::Event::MouseDown {
                 button: geng::MouseButton::Left,
                 ..
@@ -167,16 +308,13 @@ impl EditorTool for TileTool {
                             return;
                         }
                     }
-                    let mut vertices: [vec2<f32>; 3] =
-                        mem::take(&mut self.points).try_into().unwrap();
+                    let mut vertices = mem::take(&mut self.points);
                     if vec2::skew(vertices[1] - vertices[0], vertices[2] - vertices[0]) < 0.0 {
                         vertices.reverse();
                     }
-                    level.modify().layers[selected_layer].tiles.push(Tile {
-                        vertices,
-                        flow: vec2::ZERO,
-                        type_name: self.config.selected_type.clone(),
-                    });
+                    level.modify().layers[selected_layer]
+                        .tiles
+                        .push(Tile::new(vertices, self.config.selected_type.clone()));
                 }
             }
             geng::Event::MouseDown {
@@ -229,30 +367,39 @@ impl EditorTool for TileTool {
     }
 
     const NAME: &'static str = "Tile";
+    const EDITS_LAYER: bool = true;
 
     fn ui<'a>(&'a mut self, cx: &'a geng::ui::Controller) -> Box<dyn geng::ui::Widget + 'a> {
         use geng::ui::*;
 
         let assets = self.assets.get();
+        let (polygon, polygon_clicked) = toggle(cx, "polygon", self.config.polygon);
+        if polygon_clicked {
+            self.config.polygon = !self.config.polygon;
+            self.points.clear();
+        }
         let mut options: Vec<&str> = assets.tiles.keys().collect();
         options.sort();
         let options = column(
-            options
-                .into_iter()
-                .map(|name| {
+            std::iter::once(polygon)
+                .chain(options.into_iter().map(|name| {
                     let button = Button::new(cx, name);
                     if button.was_clicked() {
                         self.config.selected_type = name.to_owned();
                     }
-                    let mut widget: Box<dyn Widget> =
+                    let mut widget: Box<dyn Widget + 'a> =
                         Box::new(button.uniform_padding(8.0).align(vec2(0.0, 0.0)));
                     if *name == self.config.selected_type {
                         widget = Box::new(widget.background_color(Rgba::new(0.5, 0.5, 1.0, 0.5)))
                     }
                     widget
-                })
+                }))
                 .collect(),
         );
         options.boxed()
     }
+
+    fn snap_anchor(&self) -> Option<vec2<f32>> {
+        self.points.last().copied()
+    }
 }
@@ -66,14 +66,16 @@ impl System {
             .push(farticle);
     }
 
-    pub fn update(&mut self, delta_time: f32, level: &LevelInfo) {
+    pub fn update(&mut self, delta_time: f32, level: &Level) {
         for (assets, farticles) in &mut self.farticles {
             for farticle in farticles.iter_mut() {
                 farticle.t -= delta_time / assets.config.farticle_lifetime;
                 farticle.pos += farticle.vel * delta_time;
                 farticle.rot += farticle.w * delta_time;
 
-                for surface in level.gameplay_surfaces() {
+                let aabb =
+                    Aabb2::point(farticle.pos).extend_uniform(assets.config.farticle_size / 2.0);
+                for surface in level.surfaces_in(aabb) {
                     let v = surface.vector_from(farticle.pos);
                     let penetration = assets.config.farticle_size / 2.0 - v.len();
                     if penetration > EPS && vec2::dot(v, farticle.vel) > 0.0 {
@@ -73,11 +73,8 @@ pub fn update_guy(
     delta_time: f32,
     level: &level::LevelInfo,
     config: &assets::Config,
-    assets: &assets::Assets, // TODO: optional
-    sound: &sound::System,
-    farticles: &mut farticle::System,
+    events: &mut Vec<simulation::Event>,
 ) -> std::ops::ControlFlow<()> {
-    let assets = &assets.cannon;
     let config = &config.cannon;
 
     // This is where we do the cannon mechanics aha
@@ -109,12 +106,7 @@ pub fn update_guy(
             guy.vel = dir * config.strength;
             guy.w = Angle::ZERO;
 
-            sound.play(
-                assets.farticles.sfx.choose(&mut thread_rng()).unwrap(),
-                1.0,
-                guy.pos,
-            );
-            farticles.spawn(&assets.farticles, guy.pos, dir * config.particle_speed);
+            events.push(simulation::Event::CannonShot { pos: guy.pos, dir });
         }
         return std::ops::ControlFlow::Break(());
     }
@@ -1,3 +1,5 @@
 use super::*;
 
 pub mod cannon;
+pub mod platform;
+pub mod sections;
@@ -0,0 +1,269 @@
+use super::*;
+
+pub struct PlatformToolConfig {
+    snap_distance: f32,
+    selected_type: String,
+    period: f32,
+}
+
+impl EditorToolConfig for PlatformToolConfig {
+    fn default(assets: &AssetsHandle) -> Self {
+        Self {
+            snap_distance: assets.get().config.snap_distance,
+            selected_type: assets.get().surfaces.keys().min().unwrap().to_owned(),
+            period: 4.0,
+        }
+    }
+}
+
+pub struct PlatformTool {
+    geng: Geng,
+    assets: AssetsHandle,
+    start_drag: Option<vec2<f32>>,
+    selected: Option<usize>,
+    config: PlatformToolConfig,
+}
+
+impl PlatformTool {
+    fn find_hovered_platform(&self, cursor: &Cursor, level: &Level) -> Option<usize> {
+        level
+            .platform
+            .platforms
+            .iter()
+            .enumerate()
+            .flat_map(|(index, platform)| {
+                platform
+                    .surfaces
+                    .iter()
+                    .map(move |surface| (index, surface.vector_from(cursor.world_pos).len()))
+            })
+            .filter(|&(_index, distance)| distance < self.config.snap_distance)
+            .min_by_key(|&(_index, distance)| r32(distance))
+            .map(|(index, _distance)| index)
+    }
+    fn selected<'a>(&self, level: &'a Level) -> Option<&'a Platform> {
+        level.platform.platforms.get(self.selected?)
+    }
+}
+
+impl crate::editor::EditorTool for PlatformTool {
+    type Config = PlatformToolConfig;
+    fn new(geng: &Geng, assets: &AssetsHandle, config: PlatformToolConfig) -> Self {
+        Self {
+            geng: geng.clone(),
+            assets: assets.clone(),
+            start_drag: None,
+            selected: None,
+            config,
+        }
+    }
+    fn draw(
+        &self,
+        cursor: &Cursor,
+        level: &Level,
+        _selected_layer: usize,
+        camera: &geng::Camera2d,
+        framebuffer: &mut ugli::Framebuffer,
+    ) {
+        if let Some(start) = self.start_drag {
+            self.geng.draw2d().draw2d(
+                framebuffer,
+                camera,
+                &draw2d::Segment::new(
+                    Segment(start, cursor.snapped_world_pos),
+                    0.1,
+                    Rgba::new(1.0, 1.0, 1.0, 0.5),
+                ),
+            );
+        }
+        if let Some(platform) = self.selected(level) {
+            for surface in &platform.surfaces {
+                self.geng.draw2d().draw2d(
+                    framebuffer,
+                    camera,
+                    &draw2d::Segment::new(
+                        Segment(surface.p1, surface.p2),
+                        0.2,
+                        Rgba::new(0.0, 0.0, 1.0, 0.5),
+                    ),
+                );
+            }
+            // Looped path of the pivot through all the keyframes
+            let path: Vec<vec2<f32>> = platform
+                .keyframes
+                .iter()
+                .chain(platform.keyframes.first())
+                .map(|keyframe| platform.pivot + keyframe.offset)
+                .collect();
+            for window in path.windows(2) {
+                self.geng.draw2d().draw2d(
+                    framebuffer,
+                    camera,
+                    &draw2d::Segment::new(
+                        Segment(window[0], window[1]),
+                        0.05,
+                        Rgba::new(0.0, 0.0, 1.0, 0.5),
+                    ),
+                );
+            }
+            for &p in &path {
+                self.geng.draw2d().draw2d(
+                    framebuffer,
+                    camera,
+                    &draw2d::Ellipse::circle(p, 0.2, Rgba::new(0.0, 0.0, 1.0, 0.5)),
+                );
+            }
+        } else if let Some(index) = self.find_hovered_platform(cursor, level) {
+            for surface in &level.platform.platforms[index].surfaces {
+                self.geng.draw2d().draw2d(
+                    framebuffer,
+                    camera,
+                    &draw2d::Segment::new(
+                        Segment(surface.p1, surface.p2),
+                        0.2,
+                        Rgba::new(1.0, 0.0, 0.0, 0.5),
+                    ),
+                );
+            }
+        }
+    }
+    fn handle_event(
+        &mut self,
+        cursor: &Cursor,
+        event: &geng::Event,
+        level: &mut Level,
+        _selected_layer: usize,
+    ) {
+        if self
+            .selected
+            .map_or(false, |index| index >= level.platform.platforms.len())
+        {
+            self.selected = None;
+        }
+        let shift = self.geng.window().is_key_pressed(geng::Key::LShift);
+        match event {
+            geng::Event::MouseDown {
+                button: geng::MouseButton::Left,
+                ..
+            } => self.start_drag = Some(cursor.snapped_world_pos),
+            geng::Event::MouseUp {
+                button: geng::MouseButton::Left,
+                ..
+            } => {
+                let Some(start) = self.start_drag.take() else {
+                    return;
+                };
+                let end = cursor.snapped_world_pos;
+                if (end - start).len() < self.config.snap_distance {
+                    return;
+                }
+                let surface = Surface {
+                    p1: start,
+                    p2: end,
+                    flow: 0.0,
+                    type_name: self.config.selected_type.clone(),
+                };
+                let platforms = &mut level.modify().platform.platforms;
+                let index = match self.selected {
+                    Some(index) => index,
+                    None => {
+                        platforms.push(Platform::new(start, self.config.period));
+                        platforms.len() - 1
+                    }
+                };
+                platforms[index].surfaces.push(surface);
+                self.selected = Some(index);
+            }
+            geng::Event::MouseDown {
+                button: geng::MouseButton::Right,
+                ..
+            } => {
+                self.selected = self.find_hovered_platform(cursor, level);
+            }
+            geng::Event::KeyDown { key: geng::Key::K } => {
+                let Some(index) = self.selected else { return };
+                let platform = &mut level.modify().platform.platforms[index];
+                let last = platform.keyframes.last();
+                let (offset, rot) = if shift {
+                    // Rotate around the pivot to face the cursor, staying in place
+                    let offset = last.map_or(vec2::ZERO, |keyframe| keyframe.offset);
+                    (offset, (cursor.world_pos - platform.pivot - offset).arg())
+                } else {
+                    (
+                        cursor.snapped_world_pos - platform.pivot,
+                        last.map_or(Angle::ZERO, |keyframe| keyframe.rot),
+                    )
+                };
+                platform.keyframes.push(Keyframe {
+                    time: 0.0,
+                    offset,
+                    rot,
+                });
+                // Spread keyframes evenly over the period
+                let n = platform.keyframes.len();
+                for (i, keyframe) in platform.keyframes.iter_mut().enumerate() {
+                    keyframe.time = i as f32 / n as f32;
+                }
+            }
+            geng::Event::KeyDown {
+                key: geng::Key::Backspace,
+            } => {
+                let Some(index) = self.selected else { return };
+                level.modify().platform.platforms[index].keyframes.pop();
+            }
+            geng::Event::KeyDown { key: geng::Key::P } => {
+                let Some(index) = self.selected else { return };
+                let platform = &mut level.modify().platform.platforms[index];
+                let delta = if shift { -0.5 } else { 0.5 };
+                platform.period = (platform.period + delta).max(0.5);
+                self.config.period = platform.period;
+            }
+            geng::Event::KeyDown { key: geng::Key::O } => {
+                let Some(index) = self.selected else { return };
+                let platform = &mut level.modify().platform.platforms[index];
+                let delta = if shift { -0.125 } else { 0.125 };
+                platform.phase = (platform.phase + delta).rem_euclid(1.0);
+            }
+            geng::Event::KeyDown {
+                key: geng::Key::Delete,
+            } => {
+                if let Some(index) = self.selected.take() {
+                    level.modify().platform.platforms.remove(index);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    const NAME: &'static str = "Platform";
+
+    fn ui<'a>(&'a mut self, cx: &'a geng::ui::Controller) -> Box<dyn geng::ui::Widget + 'a> {
+        use geng::ui::*;
+
+        let assets = self.assets.get();
+        let mut options: Vec<&str> = assets.surfaces.keys().collect();
+        options.sort();
+        let options = column(
+            options
+                .into_iter()
+                .map(|name| {
+                    let button = Button::new(cx, name);
+                    if button.was_clicked() {
+                        self.config.selected_type = name.to_owned();
+                    }
+                    let mut widget: Box<dyn Widget> =
+                        Box::new(button.uniform_padding(8.0).align(vec2(0.0, 0.0)));
+                    if *name == self.config.selected_type {
+                        widget = Box::new(widget.background_color(Rgba::new(0.5, 0.5, 1.0, 0.5)))
+                    }
+                    widget
+                })
+                .collect(),
+        );
+        options.boxed()
+    }
+
+    fn snap_anchor(&self) -> Option<vec2<f32>> {
+        self.start_drag
+    }
+}
@@ -0,0 +1,150 @@
+use super::*;
+
+pub mod editor;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Keyframe {
+    /// Fraction of the period, from 0 to 1
+    pub time: f32,
+    pub offset: vec2<f32>,
+    pub rot: Angle<f32>,
+}
+
+/// Group of surfaces moving together along a looped keyframed path
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Platform {
+    /// Surfaces in the rest position
+    pub surfaces: Vec<Surface>,
+    /// Point that the rotation is around
+    pub pivot: vec2<f32>,
+    pub keyframes: Vec<Keyframe>,
+    pub period: f32,
+    pub phase: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PlatformTransform {
+    pub pivot: vec2<f32>,
+    pub offset: vec2<f32>,
+    pub rot: Angle<f32>,
+    pub vel: vec2<f32>,
+    pub w: Angle<f32>,
+}
+
+impl PlatformTransform {
+    pub fn apply(&self, p: vec2<f32>) -> vec2<f32> {
+        self.pivot + self.offset + (p - self.pivot).rotate(self.rot)
+    }
+
+    /// Velocity of the platform at a point that is already transformed
+    pub fn vel_at(&self, p: vec2<f32>) -> vec2<f32> {
+        self.vel + (p - self.pivot - self.offset).rotate_90() * self.w.as_radians()
+    }
+
+    pub fn apply_surface(&self, surface: &Surface) -> Surface {
+        Surface {
+            p1: self.apply(surface.p1),
+            p2: self.apply(surface.p2),
+            ..surface.clone()
+        }
+    }
+}
+
+impl Platform {
+    pub fn new(pivot: vec2<f32>, period: f32) -> Self {
+        Self {
+            surfaces: vec![],
+            pivot,
+            keyframes: vec![],
+            period,
+            phase: 0.0,
+        }
+    }
+
+    /// Position of the platform at the given time of the shared clock
+    pub fn transform_at(&self, time: f64) -> PlatformTransform {
+        let mut result = PlatformTransform {
+            pivot: self.pivot,
+            offset: vec2::ZERO,
+            rot: Angle::ZERO,
+            vel: vec2::ZERO,
+            w: Angle::ZERO,
+        };
+        if self.keyframes.is_empty() || self.period <= 0.0 {
+            return result;
+        }
+        let t = (time / self.period as f64 + self.phase as f64).rem_euclid(1.0) as f32;
+        let next_index = self
+            .keyframes
+            .iter()
+            .position(|keyframe| keyframe.time > t)
+            .unwrap_or(0);
+        let prev_index = (next_index + self.keyframes.len() - 1) % self.keyframes.len();
+        let prev = &self.keyframes[prev_index];
+        let next = &self.keyframes[next_index];
+        let duration = (next.time - prev.time).rem_euclid(1.0);
+        if duration == 0.0 {
+            result.offset = prev.offset;
+            result.rot = prev.rot;
+            return result;
+        }
+        let k = (t - prev.time).rem_euclid(1.0) / duration;
+        let delta_rot = (next.rot - prev.rot).as_radians();
+        result.offset = prev.offset + (next.offset - prev.offset) * k;
+        result.rot = prev.rot + Angle::from_radians(delta_rot * k);
+        result.vel = (next.offset - prev.offset) / (duration * self.period);
+        result.w = Angle::from_radians(delta_rot / (duration * self.period));
+        result
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct LevelInfo {
+    pub platforms: Vec<Platform>,
+}
+
+impl LevelInfo {
+    /// Surfaces of all platforms at the given time, with the transform of their platform
+    pub fn surfaces_at(&self, time: f64) -> Vec<(Surface, PlatformTransform)> {
+        self.platforms
+            .iter()
+            .flat_map(|platform| {
+                let transform = platform.transform_at(time);
+                platform
+                    .surfaces
+                    .iter()
+                    .map(move |surface| (transform.apply_surface(surface), transform))
+            })
+            .collect()
+    }
+
+    pub fn draw(
+        &self,
+        geng: &Geng,
+        assets: &assets::Assets,
+        time: f64,
+        framebuffer: &mut ugli::Framebuffer,
+        camera: &Camera2d,
+    ) {
+        for (surface, _) in self.surfaces_at(time) {
+            let surface_assets = &assets.surfaces[&surface.type_name];
+            let Some(texture) = surface_assets
+                .textures
+                .front
+                .as_ref()
+                .or(surface_assets.textures.back.as_ref())
+            else {
+                continue;
+            };
+            let height = texture.size().y as f32 / texture.size().x as f32;
+            geng.draw2d().draw2d(
+                framebuffer,
+                camera,
+                &draw2d::TexturedQuad::unit(texture)
+                    .scale(vec2((surface.p2 - surface.p1).len() / 2.0, height / 2.0))
+                    .rotate((surface.p2 - surface.p1).arg())
+                    .translate((surface.p1 + surface.p2) / 2.0),
+            );
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use super::*;
+
+/// Named part of the level, from its start to the start of the next one along `expected_path`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Section {
+    pub name: String,
+    /// Projected onto the expected path to get where the section starts
+    pub start: vec2<f32>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct LevelInfo {
+    pub sections: Vec<Section>,
+}
+
+impl Level {
+    /// Index of the section containing the given progress
+    pub fn section_at(&self, progress: f32) -> Option<usize> {
+        self.sections
+            .sections
+            .iter()
+            .enumerate()
+            .filter_map(|(index, section)| Some((index, self.progress_at(section.start)?)))
+            .filter(|&(_index, start)| start <= progress)
+            .max_by_key(|&(_index, start)| r32(start))
+            .map(|(index, _start)| index)
+    }
+}
+
+/// Times when the guy entered each section, plus the finish as the last split
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Splits {
+    pub times: Vec<Option<f32>>,
+}
+
+impl Splits {
+    pub fn get(&self, split: usize) -> Option<f32> {
+        self.times.get(split).copied().flatten()
+    }
+
+    pub fn set(&mut self, split: usize, time: f32) {
+        if self.times.len() <= split {
+            self.times.resize(split + 1, None);
+        }
+        self.times[split] = Some(time);
+    }
+}
+
+/// Splits of the current run compared against personal bests
+pub struct SplitTimer {
+    pub current: Splits,
+    pub best: Splits,
+    /// Time gained (negative) or lost on the last entered split
+    pub last_delta: Option<f32>,
+    /// Bests are kept separately for every level
+    preferences_key: String,
+}
+
+impl SplitTimer {
+    pub fn new(level_path: &std::path::Path) -> Self {
+        let preferences_key = format!("best_splits {}", level_path.display());
+        Self {
+            current: default(),
+            best: preferences::load(&preferences_key).unwrap_or_default(),
+            last_delta: None,
+            preferences_key,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.current = default();
+        self.last_delta = None;
+    }
+
+    pub fn enter(&mut self, split: usize, time: f32) {
+        // Only the first time entering a split counts
+        if self.current.get(split).is_some() {
+            return;
+        }
+        self.current.set(split, time);
+        let best = self.best.get(split);
+        self.last_delta = best.map(|best| time - best);
+        if best.map_or(true, |best| time < best) {
+            self.best.set(split, time);
+            preferences::save(&self.preferences_key, &self.best);
+        }
+    }
+}
+
+/// Formats a split delta the way speedrun timers do, like `-1.25` or `+0.50`
+pub fn format_delta(delta: f32) -> String {
+    let sign = if delta < 0.0 { '-' } else { '+' };
+    format!("{sign}{:.2}", delta.abs())
+}
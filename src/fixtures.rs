@@ -0,0 +1,61 @@
+//! Shared setup of the unit tests
+
+use super::*;
+
+pub fn assets_path() -> std::path::PathBuf {
+    std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("assets")
+}
+
+/// Config and physics params from the assets of the game
+pub fn physics() -> (Config, PhysicsParams) {
+    let assets_path = assets_path();
+    futures::executor::block_on(async {
+        let config: Config = file::load_json(assets_path.join("config.json")).await?;
+        let params = PhysicsParams::load(&assets_path).await?;
+        Ok::<_, anyhow::Error>((config, params))
+    })
+    .unwrap()
+}
+
+/// Directory of its own for every call, since tests run in parallel
+pub fn temp_dir(name: &str) -> std::path::PathBuf {
+    static NEXT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+    let index = NEXT.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    std::env::temp_dir().join(format!(
+        "beans-of-power-{name}-{}-{index}",
+        std::process::id(),
+    ))
+}
+
+/// New empty level with no prefabs, nothing is written unless it is saved
+pub fn new_level() -> Level {
+    let path = temp_dir("level");
+    futures::executor::block_on(Level::load(
+        path.join("level.json"),
+        path.join("prefabs"),
+        true,
+    ))
+    .unwrap()
+}
+
+pub fn surface(p1: vec2<f32>, p2: vec2<f32>, type_name: &str) -> Surface {
+    Surface {
+        p1,
+        p2,
+        flow: 0.0,
+        type_name: type_name.to_owned(),
+    }
+}
+
+/// Counter-clockwise square polygon
+pub fn square(center: vec2<f32>, size: f32) -> Vec<vec2<f32>> {
+    [
+        vec2(-1.0, -1.0),
+        vec2(1.0, -1.0),
+        vec2(1.0, 1.0),
+        vec2(-1.0, 1.0),
+    ]
+    .into_iter()
+    .map(|p| center + p * size / 2.0)
+    .collect()
+}
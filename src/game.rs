@@ -14,19 +14,22 @@ pub struct Game {
     pub best_time: Option<f32>,
     pub emotes: Vec<(f32, Id, usize)>,
     pub best_progress: f32,
+    pub splits: features::sections::SplitTimer,
     pub framebuffer_size: vec2<f32>,
     pub prev_mouse_pos: vec2<f64>,
     pub geng: Geng,
     pub config: Rc<Config>,
     pub assets: AssetsHandle,
     pub camera: geng::Camera2d,
-    pub level: Level,
     pub editor: Option<EditorState>,
-    pub guys: Collection<Guy>,
+    pub simulation: Simulation,
     pub my_guy: Option<Id>,
     pub simulation_time: f32,
     pub remote_updates: HashMap<Id, Replay>,
     pub real_time: f32,
+    /// When the last ping was sent, to measure the round trip time
+    pub ping_sent: Option<f32>,
+    pub round_trip_time: f32,
     pub noise: noise::OpenSimplex,
     pub opt: Opt,
     pub client_id: Id,
@@ -46,6 +49,8 @@ pub struct Game {
     pub quicksave: Option<Guy>,
     pub replays: Vec<Replay>,
     pub recording: Option<Replay>,
+    pub input_replays: Vec<InputReplayer>,
+    pub input_recording: Option<InputReplay>,
     pub video_editor: Option<video_editor::VideoEditor>,
     pub active_gamepad: Option<gilrs::GamepadId>,
     pub next_save: f32,
@@ -56,7 +61,7 @@ pub struct Game {
 impl Drop for Game {
     fn drop(&mut self) {
         if let Some(editor) = &mut self.editor {
-            editor.save_level(&mut self.level);
+            editor.save_level(&mut self.simulation.level);
         }
     }
 }
@@ -73,6 +78,7 @@ impl Game {
             Some((client_id, connection)) => (client_id, Some(connection)),
             None => (Id::LOCALHOST, None),
         };
+        let splits = features::sections::SplitTimer::new(level.path());
         let mut result = Self {
             best_time: None,
             emotes: vec![],
@@ -90,10 +96,16 @@ impl Game {
             } else {
                 None
             },
-            level,
-            guys: Collection::new(),
+            simulation: Simulation::new(
+                assets.get().config.clone(),
+                Rc::new(PhysicsParams::from_assets(&assets.get())),
+                level,
+                thread_rng().gen(),
+            ),
             my_guy: None,
             real_time: 0.0,
+            ping_sent: None,
+            round_trip_time: 0.0,
             noise: noise::OpenSimplex::new(0),
             prev_mouse_pos: vec2::ZERO,
             opt: opt.clone(),
@@ -106,6 +118,7 @@ impl Game {
                 .unwrap_or_else(CustomizationOptions::random),
             mute_music: false,
             best_progress: 0.0,
+            splits,
             ui_controller: ui::Controller::new(geng, assets),
             buttons: vec![
                 ui::Button::new("PLAY", vec2(0.0, -3.0), 1.0, 0.5, UiMessage::Play),
@@ -145,6 +158,20 @@ impl Game {
                 }
             },
             recording: None,
+            input_replays: opt
+                .input_replay
+                .iter()
+                .filter_map(
+                    |path| match futures::executor::block_on(InputReplay::load(path)) {
+                        Ok(replay) => Some(InputReplayer::new(replay)),
+                        Err(e) => {
+                            log::error!("Failed to load input replay {path:?}: {e:#}");
+                            None
+                        }
+                    },
+                )
+                .collect(),
+            input_recording: None,
             video_editor: opt
                 .video
                 .as_ref()
@@ -155,11 +182,17 @@ impl Game {
         };
         if !opt.editor {
             result.my_guy = Some(client_id);
-            let mut me = Guy::new(client_id, result.level.spawn_point, true, &result.config);
+            result
+                .simulation
+                .spawn_guy(client_id, result.simulation.level.spawn_point, true);
+            let checkpoint = preferences::load::<Option<Checkpoint>>("checkpoint")
+                .flatten()
+                .filter(|checkpoint| result.simulation.is_checkpoint(checkpoint.pos));
+            let guy = result.simulation.guys.get_mut(&client_id).unwrap();
             if let Some(state) = preferences::load("save") {
-                me.state = state;
+                guy.state = state;
             }
-            result.guys.insert(me);
+            guy.progress.checkpoint = checkpoint;
         }
         result
     }
@@ -174,7 +207,7 @@ impl Game {
                 rotation: Angle::ZERO,
                 fov: 10.0,
             };
-            let guy = self.guys.get_mut(&id).unwrap();
+            let guy = self.simulation.guys.get_mut(&id).unwrap();
             let text_color = if guy.progress.finished {
                 Rgba::WHITE
             } else {
@@ -191,6 +224,7 @@ impl Game {
                 );
             }
             let progress = self
+                .simulation
                 .level
                 .progress_at(guy.state.pos)
                 .unwrap_or(guy.progress.current);
@@ -201,6 +235,33 @@ impl Game {
                 self.best_time = Some(self.simulation_time);
             }
             guy.progress.best_time = self.best_time;
+            let level = &self.simulation.level;
+            let section = level.section_at(progress);
+            if let Some(section) = section.filter(|_| !guy.progress.finished) {
+                self.assets.get().font.draw(
+                    framebuffer,
+                    &camera,
+                    &level.sections.sections[section].name,
+                    vec2::splat(geng::TextAlign::CENTER),
+                    mat3::translate(vec2(0.0, -2.6)) * mat3::scale_uniform(0.5),
+                    text_color,
+                );
+            }
+            if let Some(delta) = self.splits.last_delta {
+                let color = if delta < 0.0 {
+                    Rgba::new(0.0, 0.7, 0.0, 1.0)
+                } else {
+                    Rgba::new(0.8, 0.0, 0.0, 1.0)
+                };
+                self.assets.get().font.draw(
+                    framebuffer,
+                    &camera,
+                    &features::sections::format_delta(delta),
+                    vec2::splat(geng::TextAlign::CENTER),
+                    mat3::translate(vec2(0.0, -2.0)) * mat3::scale_uniform(0.5),
+                    color,
+                );
+            }
             let mut time_text = String::new();
             let seconds = self.simulation_time.round() as i32;
             let minutes = seconds / 60;
@@ -273,6 +334,15 @@ impl Game {
             .unwrap();
         }
     }
+    fn save_input_replay(&self, replay: &InputReplay) {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let path = run_dir().join("input_replays");
+            std::fs::create_dir_all(&path).unwrap();
+            let index = std::fs::read_dir(&path).unwrap().count();
+            replay.save(path.join(format!("{index}.bincode"))).unwrap();
+        }
+    }
 }
 
 impl geng::State for Game {
@@ -280,7 +350,7 @@ impl geng::State for Game {
         self.framebuffer_size = framebuffer.size().map(|x| x as f32);
         let finished = self
             .my_guy
-            .and_then(|id| self.guys.get(&id))
+            .and_then(|id| self.simulation.guys.get(&id))
             .map(|guy| guy.progress.finished)
             .unwrap_or(false);
         ugli::clear(
@@ -294,28 +364,39 @@ impl geng::State for Game {
             None,
         );
 
-        for (index, layer) in self.level.layers.iter().enumerate() {
-            if !finished {
-                self.draw_layer_back(&self.level, index, framebuffer);
+        for (index, layer) in self.simulation.level.layers.iter().enumerate() {
+            let hidden = self.editor.as_ref().map_or(false, |editor| {
+                editor.is_layer_hidden(&self.simulation.level, index)
+            });
+            if !finished && !hidden {
+                self.draw_layer_back(&self.simulation.level, index, framebuffer);
             }
             if layer.name == "main" {
                 self.geng.draw2d().draw2d(
                     framebuffer,
                     &self.camera,
                     &draw2d::TexturedQuad::unit(&self.assets.get().closed_outhouse)
-                        .translate(self.level.spawn_point),
+                        .translate(self.simulation.level.spawn_point),
                 );
                 self.geng.draw2d().draw2d(
                     framebuffer,
                     &self.camera,
                     &draw2d::TexturedQuad::unit(&self.assets.get().golden_toilet)
-                        .translate(self.level.finish_point),
+                        .translate(self.simulation.level.finish_point),
+                );
+                // Platforms are not in any layer, so they are drawn once together with the guys
+                self.simulation.level.platform.draw(
+                    &self.geng,
+                    &self.assets.get(),
+                    self.simulation.platform_time,
+                    framebuffer,
+                    &self.camera,
                 );
                 self.draw_guys(framebuffer);
                 self.farticles.draw(framebuffer, &self.camera);
             }
-            if !finished {
-                self.draw_layer_front(&self.level, index, framebuffer);
+            if !finished && !hidden {
+                self.draw_layer_front(&self.simulation.level, index, framebuffer);
             }
         }
         self.draw_level_editor(framebuffer);
@@ -359,14 +440,24 @@ impl geng::State for Game {
 
     fn fixed_update(&mut self, delta_time: f64) {
         let delta_time = delta_time as f32 * self.time_scale;
-        if let Some(me) = self.my_guy.and_then(|id| self.guys.get(&id)) {
+        if let Some(me) = self.my_guy.and_then(|id| self.simulation.guys.get(&id)) {
             if !me.progress.finished && !me.paused {
                 self.simulation_time += delta_time;
             }
         }
         self.update_my_guy_input();
-        self.update_guys(delta_time);
-        self.farticles.update(delta_time, &self.level);
+        self.update_input_replays();
+        self.simulation.update(delta_time);
+        self.check_input_replays(delta_time);
+        self.handle_simulation_events();
+        self.update_splits();
+        self.update_long_fart_sfx();
+        if let Some(me) = self.my_guy.and_then(|id| self.simulation.guys.get(&id)) {
+            if !me.paused {
+                self.music.set_speed(self.sfx_speed(me.state.pos));
+            }
+        }
+        self.farticles.update(delta_time, &self.simulation.level);
         self.update_remote(delta_time);
         self.update_replays(delta_time);
     }
@@ -377,8 +468,9 @@ impl geng::State for Game {
         self.next_save -= delta_time;
         if self.next_save < 0.0 {
             self.next_save = 1.0;
-            if let Some(me) = self.my_guy.and_then(|id| self.guys.get(&id)) {
+            if let Some(me) = self.my_guy.and_then(|id| self.simulation.guys.get(&id)) {
                 preferences::save("save", &me.state);
+                preferences::save("checkpoint", &me.progress.checkpoint);
                 preferences::save("simulation_time", &self.simulation_time);
             }
         }
@@ -401,19 +493,38 @@ impl geng::State for Game {
 
         self.real_time += delta_time;
 
+        {
+            let assets = self.assets.get();
+            // Hot reloading replaces all the assets, config included
+            if !Rc::ptr_eq(&assets.config, &self.simulation.config) {
+                self.simulation.config = assets.config.clone();
+                self.simulation.params = Rc::new(PhysicsParams::from_assets(&assets));
+            }
+        }
+
         let mut target_center = self.camera.center;
         if let Some(id) = self.my_guy {
-            let guy = self.guys.get(&id).unwrap();
+            let guy = self.simulation.guys.get(&id).unwrap();
             target_center = guy.state.pos;
             if self.show_customizer {
                 target_center.x += 1.0;
             }
         } else if let Some(id) = self.follow {
-            if let Some(guy) = self.guys.get(&id) {
+            if let Some(guy) = self.simulation.guys.get(&id) {
                 target_center = guy.state.pos;
             }
         }
         self.camera.center += (target_center - self.camera.center) * (delta_time * 5.0).min(1.0);
+        if self.config.camera_follow_gravity && self.editor.is_none() {
+            let gravity = self.simulation.gravity_at(self.camera.center);
+            if gravity.len() > EPS {
+                // Keep local gravity pointing down the screen
+                let target = gravity.arg().as_radians() + f32::PI / 2.0;
+                let delta = target - self.camera.rotation.as_radians();
+                let delta = f32::atan2(delta.sin(), delta.cos());
+                self.camera.rotation += Angle::from_radians(delta * (delta_time * 5.0).min(1.0));
+            }
+        }
         self.sound.sync_with_camera(&self.camera);
 
         if self.editor.is_none() {
@@ -422,13 +533,13 @@ impl geng::State for Game {
         }
 
         if let Some(editor) = &mut self.editor {
-            editor.update(&mut self.level, delta_time);
+            editor.update(&mut self.simulation.level, delta_time);
         }
 
         self.handle_connection();
 
         if let Some(id) = self.my_guy {
-            let guy = self.guys.get_mut(&id).unwrap();
+            let guy = self.simulation.guys.get_mut(&id).unwrap();
             guy.customization.name = self.customization.name.clone();
             guy.customization.colors = self.customization.colors.clone();
         }
@@ -439,7 +550,7 @@ impl geng::State for Game {
             let fart_assets = &assets.farts["glint"];
             self.farticles.spawn_single(
                 fart_assets,
-                thread_rng().gen_circle(self.level.finish_point, 1.0),
+                thread_rng().gen_circle(self.simulation.level.finish_point, 1.0),
                 vec2::ZERO,
             );
             self.next_golden_glint = 1.0 / fart_assets.config.farticle_count as f32;
@@ -449,6 +560,13 @@ impl geng::State for Game {
     }
 
     fn handle_event(&mut self, event: geng::Event) {
+        if let Some(editor) = &self.editor {
+            // Typed keys are not shortcuts while renaming
+            if editor.is_renaming_layer() {
+                self.handle_event_editor(&event);
+                return;
+            }
+        }
         self.handle_event_editor(&event);
         self.handle_customizer_event(&event);
         match event {
@@ -477,6 +595,7 @@ impl geng::State for Game {
                     .camera
                     .screen_to_world(self.framebuffer_size, position.map(|x| x as f32));
                 if let Some(guy) = self
+                    .simulation
                     .guys
                     .iter()
                     .min_by_key(|guy| r32((guy.state.pos - pos).len()))
@@ -495,6 +614,12 @@ impl geng::State for Game {
             geng::Event::Wheel { delta } if self.opt.editor => {
                 self.camera.fov = (self.camera.fov * 1.01f32.powf(-delta as f32)).clamp(1.0, 200.0);
             }
+            geng::Event::KeyDown { key: geng::Key::R }
+                if self.geng.window().is_key_pressed(geng::Key::LCtrl)
+                    && self.geng.window().is_key_pressed(geng::Key::LShift) =>
+            {
+                self.restart_my_guy();
+            }
             geng::Event::KeyDown { key: geng::Key::R }
                 if self.geng.window().is_key_pressed(geng::Key::LCtrl) =>
             {
@@ -548,34 +673,60 @@ impl geng::State for Game {
                 self.camera.fov = self.assets.get().config.camera_fov;
             }
             geng::Event::KeyDown { key: geng::Key::F5 } if self.opt.editor => {
-                self.quicksave = self.my_guy.and_then(|id| self.guys.get(&id)).cloned();
+                self.quicksave = self
+                    .my_guy
+                    .and_then(|id| self.simulation.guys.get(&id))
+                    .cloned();
             }
             geng::Event::KeyDown { key: geng::Key::F7 } if self.opt.editor => {
                 if let Some(save) = &self.quicksave {
                     let save = save.clone();
                     self.respawn_my_guy();
-                    *self.guys.get_mut(&self.my_guy.unwrap()).unwrap() = save;
+                    *self.simulation.guys.get_mut(&self.my_guy.unwrap()).unwrap() = save;
                 }
             }
-            geng::Event::KeyDown { key: geng::Key::Z } if self.opt.editor => {
+            // Ctrl combinations are undo and clipboard in the editor
+            geng::Event::KeyDown { key: geng::Key::Z }
+                if self.opt.editor && !self.geng.window().is_key_pressed(geng::Key::LCtrl) =>
+            {
                 self.time_scale = 1.0;
             }
-            geng::Event::KeyDown { key: geng::Key::X } if self.opt.editor => {
+            geng::Event::KeyDown { key: geng::Key::X }
+                if self.opt.editor && !self.geng.window().is_key_pressed(geng::Key::LCtrl) =>
+            {
                 self.time_scale = 0.5;
             }
-            geng::Event::KeyDown { key: geng::Key::C } if self.opt.editor => {
+            geng::Event::KeyDown { key: geng::Key::C }
+                if self.opt.editor && !self.geng.window().is_key_pressed(geng::Key::LCtrl) =>
+            {
                 self.time_scale = 0.25;
             }
             geng::Event::KeyDown { key: geng::Key::Q } if self.opt.editor => {
                 if self.geng.window().is_key_pressed(geng::Key::LCtrl) {
                     if let Some(mut recording) = self.recording.take() {
-                        if let Some(guy) = self.my_guy.and_then(|id| self.guys.get(&id)) {
+                        if let Some(guy) = self.my_guy.and_then(|id| self.simulation.guys.get(&id))
+                        {
                             recording.push(self.simulation_time, guy);
                         }
                         self.replays.push(recording);
                         self.save_replays();
-                    } else if let Some(guy) = self.my_guy.and_then(|id| self.guys.get(&id)) {
+                        if let Some(recording) = self.input_recording.take() {
+                            self.save_input_replay(&recording);
+                        }
+                    } else if let Some(guy) =
+                        self.my_guy.and_then(|id| self.simulation.guys.get(&id))
+                    {
                         self.recording = Some(Replay::new(self.simulation_time, guy));
+                        let mut input_recording = InputReplay::new(
+                            FIXED_DELTA_TIME as f32 * self.time_scale,
+                            self.simulation.platform_time,
+                            guy,
+                        );
+                        let correction = self.simulation.platform_time_correction();
+                        if correction != 0.0 {
+                            input_recording.record_platform_time_correction(correction);
+                        }
+                        self.input_recording = Some(input_recording);
                     }
                 }
             }
@@ -4,8 +4,14 @@ impl Game {
     pub fn draw_guys(&self, framebuffer: &mut ugli::Framebuffer) {
         let assets = self.assets.get();
         for guy in itertools::chain![
-            self.guys.iter().filter(|guy| guy.id != self.client_id),
-            self.guys.iter().filter(|guy| guy.id == self.client_id),
+            self.simulation
+                .guys
+                .iter()
+                .filter(|guy| guy.id != self.client_id),
+            self.simulation
+                .guys
+                .iter()
+                .filter(|guy| guy.id == self.client_id),
         ] {
             let fart_progress = guy.state.fart_pressure / self.config.max_fart_pressure;
             let eyes_color = {
@@ -212,7 +218,7 @@ impl Game {
 
         // Emotes
         for &(_, id, emote) in &self.emotes {
-            if let Some(guy) = self.guys.get(&id) {
+            if let Some(guy) = self.simulation.guys.get(&id) {
                 self.geng.draw2d().draw2d(
                     framebuffer,
                     &self.camera,
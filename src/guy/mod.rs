@@ -36,6 +36,13 @@ pub struct Progress {
     pub current: f32,
     pub best: f32,
     pub best_time: Option<f32>,
+    /// One shot objects this guy has already used,
+    /// saved by type and position so they survive the level being edited
+    #[serde(default)]
+    pub used_objects: Vec<Object>,
+    /// Last activated checkpoint, where the guy respawns
+    #[serde(default)]
+    pub checkpoint: Option<Checkpoint>,
 }
 
 #[derive(Default, Serialize, Deserialize, Clone, Debug)]
@@ -44,7 +51,7 @@ pub struct GuyAnimationState {
     pub next_farticle_time: f32,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct PhysicsState {
     pub radius: f32,
     pub pos: vec2<f32>,
@@ -71,7 +78,7 @@ pub struct Guy {
     pub paused: bool,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct CannonTimer {
     pub cannon_index: usize,
     pub time: f32,
@@ -100,24 +107,15 @@ impl GuyColors {
 }
 
 impl Guy {
-    pub fn new(id: Id, pos: vec2<f32>, rng: bool, config: &Config) -> Self {
+    pub fn new(id: Id, pos: vec2<f32>, config: &Config) -> Self {
         Self {
             id,
             customization: CustomizationOptions::random(),
             state: PhysicsState {
                 radius: config.guy_radius,
-                pos: pos
-                    + if rng {
-                        vec2(thread_rng().gen_range(-1.0..=1.0), 0.0)
-                    } else {
-                        vec2::ZERO
-                    },
+                pos,
                 vel: vec2::ZERO,
-                rot: Angle::from_radians(if rng {
-                    thread_rng().gen_range(-1.0..=1.0)
-                } else {
-                    0.0
-                }),
+                rot: Angle::ZERO,
                 w: Angle::ZERO,
                 snow_layer: 0.0,
                 fart_type: config.default_fart_type.clone(),
@@ -133,6 +131,8 @@ impl Guy {
                 current: 0.0,
                 best: 0.0,
                 best_time: None,
+                used_objects: vec![],
+                checkpoint: None,
             },
             animation: GuyAnimationState {
                 growl_progress: None,
@@ -149,6 +149,10 @@ impl Guy {
     pub fn mass(&self, config: &Config) -> f32 {
         1.0 + self.state.snow_layer * config.snow_density
     }
+
+    pub fn butt(&self) -> vec2<f32> {
+        self.state.pos + vec2(0.0, -self.state.radius * 0.9).rotate(self.state.rot)
+    }
 }
 
 #[derive(geng::asset::Load)]
@@ -8,6 +8,9 @@ impl Id {
     pub fn replay(index: usize) -> Self {
         Self(-(index as i32 + 2))
     }
+    pub fn input_replay(index: usize) -> Self {
+        Self(i32::MIN + index as i32)
+    }
 }
 
 pub struct IdGen {
@@ -5,7 +5,7 @@ impl Game {
         if !self.show_leaderboard {
             return;
         }
-        let mut guys: Vec<&Guy> = self.guys.iter().collect();
+        let mut guys: Vec<&Guy> = self.simulation.guys.iter().collect();
         guys.sort_by(|a, b| match (a.progress.best_time, b.progress.best_time) {
             (Some(a), Some(b)) => a.partial_cmp(&b).unwrap(),
             (Some(_), None) => std::cmp::Ordering::Less,
@@ -27,7 +27,16 @@ impl Game {
             let place = place + 1;
             let name = &guy.customization.name;
             let progress = (guy.progress.current * 100.0).round() as i32;
-            let mut text = format!("#{place}: {name} - {progress}% (");
+            let mut text = format!("#{place}: {name} - {progress}%");
+            if let Some(checkpoint) = guy
+                .progress
+                .checkpoint
+                .as_ref()
+                .and_then(|checkpoint| self.simulation.level.progress_at(checkpoint.pos))
+            {
+                text += &format!(" [checkpoint {}%]", (checkpoint * 100.0).round() as i32);
+            }
+            text += " (";
             if let Some(time) = guy.progress.best_time {
                 let millis = (time * 1000.0).round() as i32;
                 let seconds = millis / 1000;
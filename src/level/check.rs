@@ -0,0 +1,490 @@
+use super::*;
+
+/// Shorter surfaces and smaller tiles are considered degenerate
+const DEGENERATE_EPS: f32 = 1e-4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.severity {
+            Severity::Warning => write!(f, "warning: {}", self.message),
+            Severity::Error => write!(f, "error: {}", self.message),
+        }
+    }
+}
+
+/// Names that a level may refer to
+pub struct CheckTypes<'a> {
+    pub params: &'a PhysicsParams,
+    pub farts: &'a [String],
+    pub prefabs: &'a HashMap<String, LevelInfo>,
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, &b) in b.iter().enumerate() {
+            let next = (prev + usize::from(a != b))
+                .min(row[j] + 1)
+                .min(row[j + 1] + 1);
+            prev = row[j + 1];
+            row[j + 1] = next;
+        }
+    }
+    row[b.len()]
+}
+
+fn pos_str(p: vec2<f32>) -> String {
+    format!("({:.2}, {:.2})", p.x, p.y)
+}
+
+/// Exact position, so that the same geometry can be found with a hash set
+fn pos_key(p: vec2<f32>) -> (u32, u32) {
+    (p.x.to_bits(), p.y.to_bits())
+}
+
+struct Checker<'a> {
+    types: &'a CheckTypes<'a>,
+    diagnostics: Vec<Diagnostic>,
+    /// Prepended to the messages, to tell which prefab is being checked
+    context: String,
+    checked_prefabs: HashSet<String>,
+}
+
+impl Checker<'_> {
+    fn report(&mut self, severity: Severity, message: String) {
+        self.diagnostics.push(Diagnostic {
+            severity,
+            message: format!("{}{message}", self.context),
+        });
+    }
+
+    fn check_type<'b>(
+        &mut self,
+        what: &str,
+        type_name: &str,
+        known: impl Iterator<Item = &'b String>,
+    ) {
+        let mut closest = None;
+        for name in known {
+            if name == type_name {
+                return;
+            }
+            let distance = edit_distance(name, type_name);
+            if closest.map_or(true, |(_, best)| distance < best) {
+                closest = Some((name, distance));
+            }
+        }
+        let hint = match closest {
+            Some((name, distance)) if distance <= 2 => format!(", did you mean {name:?}?"),
+            _ => String::new(),
+        };
+        self.report(
+            Severity::Error,
+            format!("{what} has unknown type {type_name:?}{hint}"),
+        );
+    }
+
+    fn check_level(&mut self, level: &LevelInfo) {
+        let types = self.types;
+        for layer in &level.layers {
+            let layer_name = &layer.name;
+            let mut surfaces = HashSet::new();
+            for (index, surface) in layer.surfaces.iter().enumerate() {
+                let what = format!(
+                    "layer {layer_name:?} surface {index} at {}",
+                    pos_str(surface.p1),
+                );
+                self.check_type(&what, &surface.type_name, types.params.surfaces.keys());
+                if (surface.p2 - surface.p1).len() < DEGENERATE_EPS {
+                    self.report(Severity::Warning, format!("{what} has zero length"));
+                }
+                let (a, b) = (pos_key(surface.p1), pos_key(surface.p2));
+                if !surfaces.insert((a.min(b), a.max(b))) {
+                    self.report(Severity::Warning, format!("{what} is a duplicate"));
+                }
+            }
+            for (index, curve) in layer.curves.iter().enumerate() {
+                let what = format!(
+                    "layer {layer_name:?} curve {index} at {}",
+                    pos_str(curve.points.first().copied().unwrap_or(vec2::ZERO)),
+                );
+                self.check_type(&what, &curve.type_name, types.params.surfaces.keys());
+            }
+            let mut tiles = HashSet::new();
+            for (index, tile) in layer.tiles.iter().enumerate() {
+                let pos = tile.vertices.first().copied().unwrap_or(vec2::ZERO);
+                let what = format!("layer {layer_name:?} tile {index} at {}", pos_str(pos));
+                self.check_type(&what, &tile.type_name, types.params.tiles.keys());
+                if tile.vertices.len() < 3 || polygon_area(&tile.vertices).abs() < DEGENERATE_EPS {
+                    self.report(Severity::Warning, format!("{what} has zero area"));
+                }
+                let mut key: Vec<(u32, u32)> = tile.vertices.iter().copied().map(pos_key).collect();
+                key.sort();
+                if !tiles.insert(key) {
+                    self.report(Severity::Warning, format!("{what} is a duplicate"));
+                }
+            }
+            let mut objects = HashSet::new();
+            for (index, object) in layer.objects.iter().enumerate() {
+                let what = format!(
+                    "layer {layer_name:?} object {index} at {}",
+                    pos_str(object.pos),
+                );
+                self.check_type(&what, &object.type_name, types.params.objects.keys());
+                if !objects.insert((object.type_name.clone(), pos_key(object.pos))) {
+                    self.report(Severity::Warning, format!("{what} is a duplicate"));
+                }
+            }
+            for (index, instance) in layer.prefabs.iter().enumerate() {
+                let what = format!(
+                    "layer {layer_name:?} prefab instance {index} at {}",
+                    pos_str(instance.pos),
+                );
+                match types.prefabs.get(&instance.name) {
+                    Some(prefab) => {
+                        if self.checked_prefabs.insert(instance.name.clone()) {
+                            let context = mem::replace(
+                                &mut self.context,
+                                format!("prefab {:?}: ", instance.name),
+                            );
+                            self.check_level(prefab);
+                            self.context = context;
+                        }
+                    }
+                    None => self.report(
+                        Severity::Error,
+                        format!("{what} refers to unknown prefab {:?}", instance.name),
+                    ),
+                }
+            }
+        }
+        for (index, platform) in level.platform.platforms.iter().enumerate() {
+            for surface in &platform.surfaces {
+                let what = format!("platform {index} surface at {}", pos_str(surface.p1));
+                self.check_type(&what, &surface.type_name, types.params.surfaces.keys());
+            }
+        }
+        let mut portals = HashSet::new();
+        for (index, portal) in level.portals.iter().enumerate() {
+            let what = format!("portal {index} at {}", pos_str(portal.pos));
+            match portal.dest {
+                Some(dest) if dest >= level.portals.len() => self.report(
+                    Severity::Error,
+                    format!(
+                        "{what} leads to portal {dest}, but there are only {}",
+                        level.portals.len(),
+                    ),
+                ),
+                Some(dest) if dest == index => {
+                    self.report(Severity::Warning, format!("{what} leads to itself"))
+                }
+                _ => {}
+            }
+            if !portals.insert(pos_key(portal.pos)) {
+                self.report(Severity::Warning, format!("{what} is a duplicate"));
+            }
+        }
+    }
+
+    fn check_progress(&mut self, level: &LevelInfo) {
+        let segments: Vec<(vec2<f32>, vec2<f32>)> = level
+            .expected_path
+            .iter()
+            .flat_map(|path| path.windows(2).map(|window| (window[0], window[1])))
+            .collect();
+        if segments.is_empty() {
+            self.report(
+                Severity::Error,
+                "expected_path is empty, progress can not be tracked".to_owned(),
+            );
+            return;
+        }
+        for (name, pos) in [("spawn", level.spawn_point), ("finish", level.finish_point)] {
+            let distance = segments
+                .iter()
+                .map(|&(a, b)| distance_to_segment(pos, a, b))
+                .min_by_key(|&distance| r32(distance))
+                .unwrap();
+            if distance > level.max_progress_distance {
+                self.report(
+                    Severity::Warning,
+                    format!(
+                        "{name} point at {} is {distance:.2} away from expected_path, more than max_progress_distance {}",
+                        pos_str(pos),
+                        level.max_progress_distance,
+                    ),
+                );
+            }
+        }
+    }
+
+    fn check_farts(&mut self, config: &Config) {
+        let types = self.types;
+        let mut check = |what: String, fart_type: &str| {
+            self.check_type(&what, fart_type, types.farts.iter());
+        };
+        check("config default fart".to_owned(), &config.default_fart_type);
+        for (name, params) in &types.params.objects {
+            if let Some(fart_type) = &params.fart_type {
+                check(format!("object {name:?} fart"), fart_type);
+            }
+        }
+        for (name, params) in &types.params.tiles {
+            if let Some(fart_type) = &params.fart_type {
+                check(format!("tile {name:?} fart"), fart_type);
+            }
+        }
+    }
+}
+
+impl LevelInfo {
+    /// Problems that would make the level panic or behave wrong
+    pub fn check(&self, types: &CheckTypes, config: &Config) -> Vec<Diagnostic> {
+        let mut checker = Checker {
+            types,
+            diagnostics: vec![],
+            context: String::new(),
+            checked_prefabs: HashSet::new(),
+        };
+        checker.check_level(self);
+        checker.check_progress(self);
+        checker.check_farts(config);
+        checker.diagnostics
+    }
+}
+
+/// Prints all the problems of the level, fails if there are errors
+pub async fn check_level(
+    assets_path: impl AsRef<std::path::Path>,
+    level_path: impl AsRef<std::path::Path>,
+) -> anyhow::Result<()> {
+    let assets_path = assets_path.as_ref();
+    let level_path = level_path.as_ref();
+    let config: Config = file::load_json(assets_path.join("config.json"))
+        .await
+        .context("Failed to load config")?;
+    let params = PhysicsParams::load(assets_path).await?;
+    let farts: Vec<String> = file::load_detect(assets_path.join("farts").join("_list.ron"))
+        .await
+        .context("Failed to load fart list")?;
+    let prefabs = load_prefabs(assets_path.join("prefabs")).await?;
+    let level = LevelInfo::load_file(level_path).await?;
+    let diagnostics = level.check(
+        &CheckTypes {
+            params: &params,
+            farts: &farts,
+            prefabs: &prefabs,
+        },
+        &config,
+    );
+    for diagnostic in &diagnostics {
+        match diagnostic.severity {
+            Severity::Warning => log::warn!("{diagnostic}"),
+            Severity::Error => log::error!("{diagnostic}"),
+        }
+    }
+    let errors = diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.severity == Severity::Error)
+        .count();
+    let warnings = diagnostics.len() - errors;
+    if errors > 0 {
+        anyhow::bail!("{level_path:?} has {errors} errors and {warnings} warnings");
+    }
+    log::info!("{level_path:?} is fine, {warnings} warnings");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(level: &LevelInfo, prefabs: &HashMap<String, LevelInfo>) -> Vec<Diagnostic> {
+        let (config, params) = fixtures::physics();
+        let farts: Vec<String> = futures::executor::block_on(file::load_detect(
+            fixtures::assets_path().join("farts").join("_list.ron"),
+        ))
+        .unwrap();
+        level.check(
+            &CheckTypes {
+                params: &params,
+                farts: &farts,
+                prefabs,
+            },
+            &config,
+        )
+    }
+
+    fn valid_level() -> LevelInfo {
+        let mut level = LevelInfo {
+            expected_path: vec![vec![vec2(0.0, 0.0), vec2(10.0, 0.0)]],
+            finish_point: vec2(10.0, 1.0),
+            ..default()
+        };
+        let layer = &mut level.layers[0];
+        layer
+            .surfaces
+            .push(fixtures::surface(vec2(0.0, 0.0), vec2(10.0, 0.0), "grass"));
+        layer.tiles.push(Tile::new(
+            vec![vec2(0.0, 0.0), vec2(10.0, 0.0), vec2(0.0, -5.0)],
+            "dirt".to_owned(),
+        ));
+        layer.objects.push(Object {
+            type_name: "checkpoint".to_owned(),
+            pos: vec2(5.0, 1.0),
+        });
+        level
+    }
+
+    fn messages(diagnostics: &[Diagnostic], severity: Severity) -> Vec<&str> {
+        diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.severity == severity)
+            .map(|diagnostic| diagnostic.message.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn edit_distances() {
+        assert_eq!(edit_distance("grass", "grass"), 0);
+        assert_eq!(edit_distance("grass", "gras"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "ice"), 3);
+    }
+
+    #[test]
+    fn valid_level_has_no_diagnostics() {
+        let diagnostics = check(&valid_level(), &default());
+        assert!(diagnostics.is_empty(), "{diagnostics:?}");
+    }
+
+    #[test]
+    fn unknown_types() {
+        let mut level = valid_level();
+        level.layers[0].surfaces[0].type_name = "gras".to_owned();
+        level.layers[0].tiles[0].type_name = "no such tile".to_owned();
+        let diagnostics = check(&level, &default());
+        let errors = messages(&diagnostics, Severity::Error);
+        assert_eq!(errors.len(), 2, "{diagnostics:?}");
+        assert!(errors[0].ends_with("unknown type \"gras\", did you mean \"grass\"?"));
+        assert!(errors[1].ends_with("unknown type \"no such tile\""));
+    }
+
+    #[test]
+    fn degenerate_and_duplicate_geometry() {
+        let mut level = valid_level();
+        let layer = &mut level.layers[0];
+        layer
+            .surfaces
+            .push(fixtures::surface(vec2(10.0, 0.0), vec2(0.0, 0.0), "grass"));
+        layer
+            .surfaces
+            .push(fixtures::surface(vec2(3.0, 3.0), vec2(3.0, 3.0), "grass"));
+        layer.tiles.push(Tile::new(
+            vec![vec2(0.0, -5.0), vec2(0.0, 0.0), vec2(10.0, 0.0)],
+            "dirt".to_owned(),
+        ));
+        layer.tiles.push(Tile::new(
+            vec![vec2(0.0, 0.0), vec2(1.0, 1.0), vec2(2.0, 2.0)],
+            "dirt".to_owned(),
+        ));
+        layer.objects.push(layer.objects[0].clone());
+        let diagnostics = check(&level, &default());
+        assert!(messages(&diagnostics, Severity::Error).is_empty());
+        let warnings = messages(&diagnostics, Severity::Warning);
+        assert_eq!(warnings.len(), 5, "{diagnostics:?}");
+        assert!(warnings[0].contains("surface 1") && warnings[0].ends_with("is a duplicate"));
+        assert!(warnings[1].contains("surface 2") && warnings[1].ends_with("has zero length"));
+        assert!(warnings[2].contains("tile 1") && warnings[2].ends_with("is a duplicate"));
+        assert!(warnings[3].contains("tile 2") && warnings[3].ends_with("has zero area"));
+        assert!(warnings[4].contains("object 1") && warnings[4].ends_with("is a duplicate"));
+    }
+
+    #[test]
+    fn portal_destinations() {
+        let mut level = valid_level();
+        let portal = |pos, dest| Portal {
+            pos,
+            dest,
+            color: Rgba::WHITE,
+        };
+        level.portals = vec![
+            portal(vec2(1.0, 1.0), Some(1)),
+            portal(vec2(2.0, 1.0), Some(1)),
+            portal(vec2(3.0, 1.0), Some(5)),
+            portal(vec2(3.0, 1.0), None),
+        ];
+        let diagnostics = check(&level, &default());
+        let errors = messages(&diagnostics, Severity::Error);
+        assert_eq!(
+            errors,
+            ["portal 2 at (3.00, 1.00) leads to portal 5, but there are only 4"]
+        );
+        let warnings = messages(&diagnostics, Severity::Warning);
+        assert_eq!(
+            warnings,
+            [
+                "portal 1 at (2.00, 1.00) leads to itself",
+                "portal 3 at (3.00, 1.00) is a duplicate",
+            ],
+        );
+    }
+
+    #[test]
+    fn progress_path() {
+        let mut level = valid_level();
+        level.spawn_point = vec2(0.0, 20.0);
+        let diagnostics = check(&level, &default());
+        let warnings = messages(&diagnostics, Severity::Warning);
+        assert_eq!(warnings.len(), 1, "{diagnostics:?}");
+        assert!(warnings[0].starts_with("spawn point at (0.00, 20.00) is 20.00 away"));
+
+        level.expected_path = vec![vec![vec2(0.0, 0.0)]];
+        let diagnostics = check(&level, &default());
+        assert_eq!(
+            messages(&diagnostics, Severity::Error),
+            ["expected_path is empty, progress can not be tracked"],
+        );
+    }
+
+    #[test]
+    fn prefabs_are_checked_once() {
+        let mut prefab = LevelInfo::default();
+        prefab.layers[0]
+            .surfaces
+            .push(fixtures::surface(vec2(0.0, 0.0), vec2(1.0, 0.0), "lava"));
+        let prefabs = HashMap::from_iter([("rock".to_owned(), prefab)]);
+        let mut level = valid_level();
+        let layer = &mut level.layers[0];
+        layer
+            .prefabs
+            .push(PrefabInstance::new("rock".to_owned(), vec2(1.0, 1.0)));
+        layer
+            .prefabs
+            .push(PrefabInstance::new("rock".to_owned(), vec2(2.0, 1.0)));
+        layer
+            .prefabs
+            .push(PrefabInstance::new("boulder".to_owned(), vec2(3.0, 1.0)));
+        let diagnostics = check(&level, &prefabs);
+        let errors = messages(&diagnostics, Severity::Error);
+        assert_eq!(errors.len(), 2, "{diagnostics:?}");
+        assert!(errors[0].starts_with("prefab \"rock\": layer \"main\" surface 0"));
+        assert!(errors[0].ends_with("unknown type \"lava\""));
+        assert!(errors[1].ends_with("refers to unknown prefab \"boulder\""));
+    }
+}
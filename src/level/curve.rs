@@ -0,0 +1,191 @@
+use super::*;
+
+/// Subdivision stops here even if the tolerance is not reached, so tiny tolerances stay cheap
+const MAX_SUBDIVISION_DEPTH: usize = 10;
+
+/// Surface following a chain of cubic bezier segments,
+/// expanded into regular surfaces when the level is flattened
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CurveSurface {
+    /// Every third point is an anchor the curve passes through, with two handles in between
+    pub points: Vec<vec2<f32>>,
+    /// Max distance between the curve and the segments approximating it
+    #[serde(default = "default_tolerance")]
+    pub tolerance: f32,
+    #[serde(default)]
+    pub flow: f32,
+    pub type_name: String,
+}
+
+pub fn default_tolerance() -> f32 {
+    0.05
+}
+
+/// Adds points approximating the segment to the result, except for the first one
+fn subdivide(c: [vec2<f32>; 4], tolerance: f32, depth: usize, result: &mut Vec<vec2<f32>>) {
+    let flat = distance_to_segment(c[1], c[0], c[3]) <= tolerance
+        && distance_to_segment(c[2], c[0], c[3]) <= tolerance;
+    if flat || depth >= MAX_SUBDIVISION_DEPTH {
+        result.push(c[3]);
+        return;
+    }
+    // de Casteljau split in the middle
+    let ab = (c[0] + c[1]) / 2.0;
+    let bc = (c[1] + c[2]) / 2.0;
+    let cd = (c[2] + c[3]) / 2.0;
+    let abc = (ab + bc) / 2.0;
+    let bcd = (bc + cd) / 2.0;
+    let mid = (abc + bcd) / 2.0;
+    subdivide([c[0], ab, abc, mid], tolerance, depth + 1, result);
+    subdivide([mid, bcd, cd, c[3]], tolerance, depth + 1, result);
+}
+
+impl CurveSurface {
+    /// Curve going straight through the anchors, with handles at thirds of each segment
+    pub fn new(anchors: &[vec2<f32>], type_name: String) -> Self {
+        let mut points = vec![anchors[0]];
+        for window in anchors.windows(2) {
+            let (a, b) = (window[0], window[1]);
+            points.extend([a + (b - a) / 3.0, a + (b - a) * 2.0 / 3.0, b]);
+        }
+        Self {
+            points,
+            tolerance: default_tolerance(),
+            flow: 0.0,
+            type_name,
+        }
+    }
+
+    pub fn is_anchor(index: usize) -> bool {
+        index % 3 == 0
+    }
+
+    /// Control points of each cubic segment
+    pub fn segments(&self) -> impl Iterator<Item = [vec2<f32>; 4]> + '_ {
+        self.points
+            .windows(4)
+            .step_by(3)
+            .map(|window| [window[0], window[1], window[2], window[3]])
+    }
+
+    /// Polyline approximating the curve
+    pub fn tessellate(&self) -> Vec<vec2<f32>> {
+        let mut result = self.points.first().copied().into_iter().collect();
+        // Zero tolerance would always subdivide to the max depth
+        let tolerance = self.tolerance.max(1e-3);
+        for segment in self.segments() {
+            subdivide(segment, tolerance, 0, &mut result);
+        }
+        result
+    }
+
+    pub fn surfaces(&self) -> Vec<Surface> {
+        self.tessellate()
+            .windows(2)
+            .map(|window| Surface {
+                p1: window[0],
+                p2: window[1],
+                flow: self.flow,
+                type_name: self.type_name.clone(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arc() -> CurveSurface {
+        CurveSurface {
+            points: vec![
+                vec2(0.0, 0.0),
+                vec2(0.0, 2.0),
+                vec2(3.0, 2.0),
+                vec2(3.0, 0.0),
+                vec2(3.0, -2.0),
+                vec2(6.0, -2.0),
+                vec2(6.0, 0.0),
+            ],
+            tolerance: 0.01,
+            flow: 1.5,
+            type_name: "grass".to_owned(),
+        }
+    }
+
+    fn point_at(c: [vec2<f32>; 4], t: f32) -> vec2<f32> {
+        let s = 1.0 - t;
+        c[0] * (s * s * s)
+            + c[1] * (3.0 * s * s * t)
+            + c[2] * (3.0 * s * t * t)
+            + c[3] * (t * t * t)
+    }
+
+    #[test]
+    fn straight_curve_keeps_anchors() {
+        let anchors = [vec2(0.0, 0.0), vec2(3.0, 0.0), vec2(3.0, 3.0)];
+        let curve = CurveSurface::new(&anchors, "grass".to_owned());
+        assert_eq!(curve.points.len(), 7);
+        assert_eq!(curve.segments().count(), 2);
+        assert_eq!(curve.tessellate(), anchors);
+    }
+
+    #[test]
+    fn tessellation_stays_within_tolerance() {
+        let curve = arc();
+        let polyline = curve.tessellate();
+        assert_eq!(polyline.first(), curve.points.first());
+        assert_eq!(polyline.last(), curve.points.last());
+        assert!(polyline.contains(&vec2(3.0, 0.0)));
+        for segment in curve.segments() {
+            for i in 0..=100 {
+                let p = point_at(segment, i as f32 / 100.0);
+                let distance = polyline
+                    .windows(2)
+                    .map(|w| distance_to_segment(p, w[0], w[1]))
+                    .fold(f32::INFINITY, f32::min);
+                assert!(
+                    distance <= curve.tolerance + 1e-4,
+                    "{p:?} is {distance} away"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn finer_tolerance_adds_points() {
+        let mut curve = arc();
+        let coarse = curve.tessellate().len();
+        curve.tolerance = 0.001;
+        let fine = curve.tessellate().len();
+        assert!(coarse > 3);
+        assert!(fine > coarse);
+        // Zero tolerance is clamped, and the depth limit bounds the rest
+        curve.tolerance = 0.0;
+        assert_eq!(curve.tessellate().len(), fine);
+        assert!(fine <= 2 * (1 << MAX_SUBDIVISION_DEPTH) + 1);
+    }
+
+    #[test]
+    fn surfaces_are_connected() {
+        let curve = arc();
+        let surfaces = curve.surfaces();
+        assert_eq!(surfaces.len(), curve.tessellate().len() - 1);
+        for pair in surfaces.windows(2) {
+            assert_eq!(pair[0].p2, pair[1].p1);
+        }
+        assert!(surfaces
+            .iter()
+            .all(|surface| surface.flow == 1.5 && surface.type_name == "grass"));
+    }
+
+    #[test]
+    fn degenerate_curves() {
+        let mut curve = arc();
+        curve.points.truncate(1);
+        assert_eq!(curve.tessellate(), [vec2(0.0, 0.0)]);
+        assert!(curve.surfaces().is_empty());
+        curve.points.clear();
+        assert!(curve.tessellate().is_empty());
+    }
+}
@@ -1,6 +1,13 @@
 use super::*;
 
-#[derive(ugli::Vertex)]
+/// Size of the square areas that the level mesh is built and dropped in
+const CHUNK_SIZE: f32 = 32.0;
+/// Chunks this close to the view are built before they are seen
+const CHUNK_PRELOAD_DISTANCE: f32 = 16.0;
+/// Chunks further than this from the view are dropped
+const CHUNK_EVICT_DISTANCE: f32 = 128.0;
+
+#[derive(ugli::Vertex, Copy, Clone)]
 struct TileVertex {
     a_pos: vec2<f32>,
     a_side_distances: vec3<f32>,
@@ -16,401 +23,727 @@ struct SurfaceVertex {
     a_flow: f32,
 }
 
-pub struct LayerMesh {
+/// Where the texture of a surface goes, depends on all the surfaces connected to it
+#[derive(Clone, Copy)]
+struct SurfaceLayout {
+    start_t: f32,
+    /// Connected surfaces are stretched so that the texture repeats a whole number of times
+    stretch: f32,
+    prev: Option<usize>,
+    next: Option<usize>,
+}
+
+/// Surfaces and tiles of a layer in one chunk
+#[derive(Default)]
+struct ChunkContents {
+    surfaces: Vec<usize>,
+    tiles: Vec<usize>,
+    /// Area covered by the geometry, which may stick out of the chunk
+    bounds: Option<Aabb2<f32>>,
+    /// Changes when anything affecting the geometry of the chunk changes
+    signature: u64,
+}
+
+struct ChunkMesh {
     tiles: HashMap<String, ugli::VertexBuffer<TileVertex>>,
     surfaces: HashMap<String, ugli::VertexBuffer<SurfaceVertex>>,
+    /// Geometry of every tile and surface by its signature,
+    /// so that rebuilding the chunk only regenerates what has changed
+    tile_vertices: HashMap<u64, Vec<TileVertex>>,
+    surface_vertices: HashMap<u64, Vec<SurfaceVertex>>,
+    bounds: Aabb2<f32>,
+    signature: u64,
+}
+
+#[derive(Default)]
+pub struct LayerMesh {
+    layout: Vec<SurfaceLayout>,
+    /// Change when the geometry of the tile or the surface has to be regenerated
+    tile_signatures: Vec<u64>,
+    surface_signatures: Vec<u64>,
+    contents: HashMap<(i32, i32), ChunkContents>,
+    chunks: HashMap<(i32, i32), ChunkMesh>,
 }
 
+/// Level geometry, built lazily in chunks around the camera
 pub struct LevelMesh {
     layers: Vec<LayerMesh>,
+    /// The level was modified since the layout was computed
+    outdated: bool,
+    /// Only these layers were modified since
+    outdated_layers: HashSet<usize>,
 }
 
-impl LevelMesh {
-    pub fn new(geng: &Geng, assets: &AssetsHandle, level: &Level) -> Self {
-        let assets = assets.get();
-        let surface_texture_height = |surface: &Surface| -> f32 {
-            let surface_assets = &assets.surfaces[&surface.type_name];
-            let texture = surface_assets
-                .textures
-                .front
-                .as_ref()
-                .or(surface_assets.textures.back.as_ref())
-                .unwrap();
-            texture.size().y as f32 / texture.size().x as f32
+fn chunk_at(p: vec2<f32>) -> (i32, i32) {
+    let p = p.map(|x| (x / CHUNK_SIZE).floor() as i32);
+    (p.x, p.y)
+}
+
+/// Negative zero is the same point, but has different bits
+fn point_key(p: vec2<f32>) -> (u32, u32) {
+    ((p.x + 0.0).to_bits(), (p.y + 0.0).to_bits())
+}
+
+fn overlaps(a: Aabb2<f32>, b: Aabb2<f32>) -> bool {
+    a.min.x <= b.max.x && b.min.x <= a.max.x && a.min.y <= b.max.y && b.min.y <= a.max.y
+}
+
+fn hash_point(hasher: &mut impl std::hash::Hasher, p: vec2<f32>) {
+    hasher.write_u32(p.x.to_bits());
+    hasher.write_u32(p.y.to_bits());
+}
+
+fn surface_texture_height(assets: &Assets, surface: &Surface) -> f32 {
+    let surface_assets = &assets.surfaces[&surface.type_name];
+    let texture = surface_assets
+        .textures
+        .front
+        .as_ref()
+        .or(surface_assets.textures.back.as_ref())
+        .unwrap();
+    texture.size().y as f32 / texture.size().x as f32
+}
+
+fn arc_len(assets: &Assets, a: &Surface, b: &Surface) -> f32 {
+    // assert_eq!(a.type_name, b.type_name);
+    let n1 = a.normal();
+    let n2 = b.normal();
+    let angle = f32::atan2(vec2::skew(n1, n2), vec2::dot(n1, n2));
+    let r = surface_texture_height(assets, a) / 2.0;
+    (angle * r).abs()
+}
+
+/// Texture coordinates are continued along connected surfaces of the same type
+fn surface_layout(assets: &Assets, surfaces: &[Surface]) -> Vec<SurfaceLayout> {
+    let mut by_point = HashMap::<(u32, u32), Vec<usize>>::new();
+    for (index, surface) in surfaces.iter().enumerate() {
+        by_point
+            .entry(point_key(surface.p1))
+            .or_default()
+            .push(index);
+        by_point
+            .entry(point_key(surface.p2))
+            .or_default()
+            .push(index);
+    }
+    // In level order, so that the result does not depend on the hashing
+    let touching = |p: vec2<f32>| -> Vec<usize> {
+        let mut result = by_point.get(&point_key(p)).cloned().unwrap_or_default();
+        result.sort();
+        result.dedup();
+        result
+    };
+    let find = |p: vec2<f32>, f: &dyn Fn(&Surface) -> bool| {
+        touching(p).into_iter().find(|&index| f(&surfaces[index]))
+    };
+
+    let mut result: Vec<SurfaceLayout> = surfaces
+        .iter()
+        .map(|surface| SurfaceLayout {
+            start_t: 0.0,
+            stretch: 1.0,
+            prev: find(surface.p1, &|other| {
+                other.p2 == surface.p1 && other.type_name == surface.type_name
+            }),
+            next: find(surface.p2, &|other| {
+                other.p1 == surface.p2 && other.type_name == surface.type_name
+            }),
+        })
+        .collect();
+    let mut vertex_ts: Vec<Option<f32>> = vec![None; surfaces.len()];
+    let mut queue = std::collections::VecDeque::<usize>::new();
+    for key in 0..surfaces.len() {
+        if vertex_ts[key].is_some() {
+            continue;
+        }
+        vertex_ts[key] = Some(0.0);
+        queue.push_back(key);
+        let mut this_pass = Vec::new();
+        while let Some(key) = queue.pop_front() {
+            this_pass.push(key);
+            let surface = &surfaces[key];
+            let start_t = vertex_ts[key].unwrap();
+            let end_t = start_t + (surface.p2 - surface.p1).len();
+            let mut neighbours = touching(surface.p1);
+            neighbours.extend(touching(surface.p2));
+            neighbours.sort();
+            neighbours.dedup();
+            for i in neighbours {
+                let other = &surfaces[i];
+                if other.type_name != surface.type_name {
+                    continue;
+                }
+                let mut push = |key: usize, t: f32| {
+                    if vertex_ts[key].is_none() {
+                        vertex_ts[key] = Some(t);
+                        queue.push_back(key);
+                    }
+                };
+                for (t, p) in [(start_t, surface.p1), (end_t, surface.p2)] {
+                    if p == other.p1 {
+                        push(i, t + arc_len(assets, surface, other));
+                    }
+                    if p == other.p2 {
+                        push(
+                            i,
+                            t - (other.p2 - other.p1).len() - arc_len(assets, other, surface),
+                        );
+                    }
+                }
+            }
+        }
+
+        // From the start of the first surface to the end of the last corner
+        let min = this_pass
+            .iter()
+            .map(|&i| r32(vertex_ts[i].unwrap()))
+            .min()
+            .unwrap();
+        let max = this_pass
+            .iter()
+            .map(|&i| {
+                let surface = &surfaces[i];
+                let end_t = vertex_ts[i].unwrap() + (surface.p2 - surface.p1).len();
+                let corner = result[i]
+                    .next
+                    .map_or(0.0, |next| arc_len(assets, surface, &surfaces[next]));
+                r32(end_t + corner)
+            })
+            .max()
+            .unwrap();
+        let total_len = (max - min).raw();
+        let rounded_len = total_len.round().max(1.0);
+        for i in this_pass {
+            result[i].start_t = vertex_ts[i].unwrap();
+            result[i].stretch = rounded_len / total_len;
+        }
+    }
+    result
+}
+
+fn tile_vertices(tile: &Tile, fadeout_distance: f32, vertex_data: &mut Vec<TileVertex>) {
+    for vertices in tile.triangles() {
+        // Diagonals of the triangulation are not faded out
+        let sides: [bool; 3] =
+            std::array::from_fn(|i| tile.is_side(vertices[i], vertices[(i + 1) % 3]));
+        let side_fadeout = |i: usize| if sides[i] { fadeout_distance } else { 0.0 };
+        let data: [vec2<f32>; 3] = std::array::from_fn(|i| {
+            let mut vs = vertices;
+            vs.rotate_left(i);
+            let p1 = vs[1];
+            let v1 = vs[0] - vs[1];
+            let p2 = vs[2];
+            let n2 = (vs[2] - vs[0]).rotate_90().normalize();
+            let p1 = p1 + v1.rotate_90().normalize() * side_fadeout(i);
+            let p2 = p2 + n2 * side_fadeout((i + 2) % 3);
+            let t = ray_hit_time(p1, v1, p2, n2);
+            p1 + v1 * t
+        });
+        vertex_data.extend(data.into_iter().map(|v| TileVertex {
+            a_pos: v,
+            a_side_distances: {
+                let distances: [f32; 3] = std::array::from_fn(|i| {
+                    if !sides[i] {
+                        return -1.0;
+                    }
+                    let n = (vertices[i] - vertices[(i + 1) % 3])
+                        .rotate_90()
+                        .normalize();
+                    (vec2::dot(v, n) - vec2::dot(vertices[i], n)) / fadeout_distance
+                });
+                let [x, y, z] = distances;
+                vec3(x, y, z)
+            },
+            a_corner_distances: {
+                let distances: [f32; 3] = std::array::from_fn(|i| {
+                    if !sides[i] || !sides[(i + 2) % 3] {
+                        return -1.0;
+                    }
+                    let mut vs = vertices;
+                    vs.rotate_left(i);
+                    let n1 = (vs[0] - vs[1]).rotate_90().normalize();
+                    let n2 = (vs[2] - vs[0]).rotate_90().normalize();
+                    let n = (n1 + n2).normalize();
+                    (vec2::dot(v, n) - vec2::dot(vertices[i], n)) / fadeout_distance
+                });
+                let [x, y, z] = distances;
+                vec3(x, y, z)
+            },
+            a_flow: tile.flow,
+        }));
+    }
+}
+
+/// Rect of the surface and the corner to the next one,
+/// texture coordinates start from zero and are moved into place by the layout
+fn surface_vertices(
+    assets: &Assets,
+    surface: &Surface,
+    prev: Option<&Surface>,
+    next: Option<&Surface>,
+) -> Vec<SurfaceVertex> {
+    let surface_texture_height = |surface: &Surface| surface_texture_height(assets, surface);
+    let normal = (surface.p2 - surface.p1).normalize().rotate_90();
+    let start_t = 0.0;
+    let len = (surface.p2 - surface.p1).len();
+    let end_t = start_t + len;
+    let mut vertex_data = Vec::new();
+
+    // Rect
+    vertex_data.extend({
+        let dir = surface.p2 - surface.p1;
+        let start_ratio = prev
+            .map_or(0.0, |prev| {
+                ray_hit_time(
+                    surface.p1 + surface.normal() * surface_texture_height(surface),
+                    dir,
+                    prev.p1 + prev.normal() * surface_texture_height(prev),
+                    prev.normal(),
+                )
+            })
+            .max(0.0);
+        let end_ratio = next
+            .map_or(1.0, |next| {
+                ray_hit_time(
+                    surface.p1 + surface.normal() * surface_texture_height(surface),
+                    dir,
+                    next.p1 + next.normal() * surface_texture_height(next),
+                    next.normal(),
+                )
+            })
+            .min(1.0);
+        let p1 = surface.p1 + dir * start_ratio;
+        let p2 = surface.p1 + dir * end_ratio;
+        let vs = [
+            SurfaceVertex {
+                a_pos: p1,
+                a_normal: normal,
+                a_flow: surface.flow,
+                a_vt: vec2(start_t, 0.0),
+            },
+            SurfaceVertex {
+                a_pos: p2,
+                a_normal: normal,
+                a_flow: surface.flow,
+                a_vt: vec2(end_t, 0.0),
+            },
+            SurfaceVertex {
+                a_pos: p2 + surface_texture_height(surface) * normal,
+                a_normal: normal,
+                a_flow: surface.flow,
+                a_vt: vec2(end_t, 1.0),
+            },
+            SurfaceVertex {
+                a_pos: p1 + surface_texture_height(surface) * normal,
+                a_normal: normal,
+                a_flow: surface.flow,
+                a_vt: vec2(start_t, 1.0),
+            },
+        ];
+        [vs[0], vs[1], vs[2], vs[0], vs[2], vs[3]]
+    });
+
+    // Corner to the next segment
+    if let Some(next) = next {
+        const R: usize = 100;
+        fn lerp(a: vec2<f32>, b: vec2<f32>, t: f32) -> vec2<f32> {
+            a * (1.0 - t) + b * t
+        }
+        let n1 = normal;
+        let n2 = (next.p2 - next.p1).rotate_90().normalize_or_zero();
+        struct Point {
+            pos: vec2<f32>,
+            normal: vec2<f32>,
+            height: f32,
+        }
+        let mut vs = Vec::<Point>::new();
+        if vec2::skew(surface.normal(), next.normal()) > EPS {
+            let ray_start = surface.p1 + surface.normal() * surface_texture_height(surface);
+            let ray_dir = surface.p2 - surface.p1;
+            let t = ray_hit_time(
+                ray_start,
+                ray_dir,
+                next.p1 + next.normal() * surface_texture_height(next),
+                next.normal(),
+            );
+            let mid = ray_start + ray_dir * t;
+            let p1 = mid - surface.normal() * surface_texture_height(surface);
+            let p2 = mid - next.normal() * surface_texture_height(next);
+            for (p1, p2) in [(p1, surface.p2), (surface.p2, p2)] {
+                for j in 0..=R {
+                    let pos = lerp(p1, p2, j as f32 / R as f32);
+                    if vs.last().map(|p| p.pos) == Some(pos) {
+                        // LUL
+                        continue;
+                    }
+                    vs.push(Point {
+                        pos,
+                        normal: (mid - pos).normalize(),
+                        height: (mid - pos).len(),
+                    });
+                }
+            }
+        } else {
+            let a = n1.arg().as_radians();
+            let b = n2.arg().as_radians();
+            let mut angle = b - a;
+            if angle > 0.0 {
+                angle -= 2.0 * f32::PI;
+            }
+            for j in 0..=R {
+                vs.push(Point {
+                    pos: surface.p2,
+                    normal: vec2(1.0, 0.0)
+                        .rotate(Angle::from_radians(a + angle * j as f32 / R as f32)),
+                    height: surface_texture_height(surface),
+                });
+            }
+        }
+        let (start_t, end_t) = {
+            let start_t = end_t;
+            (start_t, start_t + arc_len(assets, surface, next))
         };
-        let surface_texture_radius =
-            |surface: &Surface| -> f32 { surface_texture_height(surface) / 2.0 };
-        let arc_len = |a: &Surface, b: &Surface| -> f32 {
-            // assert_eq!(a.type_name, b.type_name);
-            let n1 = a.normal();
-            let n2 = b.normal();
-            let angle = f32::atan2(vec2::skew(n1, n2), vec2::dot(n1, n2));
-            let r = surface_texture_radius(a);
-            (angle * r).abs()
+        for (i, seg) in vs.windows(2).enumerate() {
+            let p1 = &seg[0];
+            let p2 = &seg[1];
+            let (start_t, end_t) = {
+                (
+                    start_t + (end_t - start_t) * i as f32 / vs.len() as f32,
+                    start_t + (end_t - start_t) * (i + 1) as f32 / vs.len() as f32,
+                )
+            };
+            let vs = [
+                SurfaceVertex {
+                    a_pos: p1.pos,
+                    a_normal: p1.normal,
+                    a_flow: surface.flow,
+                    a_vt: vec2(start_t, 0.0),
+                },
+                SurfaceVertex {
+                    a_pos: p2.pos,
+                    a_normal: p2.normal,
+                    a_flow: surface.flow,
+                    a_vt: vec2(end_t, 0.0),
+                },
+                SurfaceVertex {
+                    a_pos: p2.pos + p2.height * p2.normal,
+                    a_normal: p2.normal,
+                    a_flow: surface.flow,
+                    a_vt: vec2(end_t, 1.0),
+                },
+                SurfaceVertex {
+                    a_pos: p1.pos + p1.height * p1.normal,
+                    a_normal: p1.normal,
+                    a_flow: surface.flow,
+                    a_vt: vec2(start_t, 1.0),
+                },
+            ];
+            vertex_data.extend([vs[0], vs[1], vs[2], vs[0], vs[2], vs[3]]);
+        }
+    } else {
+        log::warn!("Not connected????");
+    }
+
+    // TODO should be handled differently
+    let surface_assets = &assets.surfaces[&surface.type_name];
+    let height = surface_texture_height(surface);
+    for vertex in &mut vertex_data {
+        vertex.a_pos -= vertex.a_normal * height * surface_assets.params.texture_underground;
+    }
+    vertex_data
+}
+
+impl LayerMesh {
+    /// Recomputes what is in which chunk and what has changed,
+    /// changed chunks are rebuilt once they are needed
+    fn update(&mut self, assets: &Assets, layer: &LevelLayer) {
+        use std::hash::{Hash, Hasher};
+        let new_hasher = std::collections::hash_map::DefaultHasher::new;
+        self.layout = surface_layout(assets, &layer.surfaces);
+        self.surface_signatures = layer
+            .surfaces
+            .iter()
+            .enumerate()
+            .map(|(index, surface)| {
+                let layout = &self.layout[index];
+                let mut hasher = new_hasher();
+                // Neighbours change how the ends of the surface look
+                for i in [layout.prev, Some(index), layout.next] {
+                    let Some(i) = i else {
+                        hasher.write_u8(0);
+                        continue;
+                    };
+                    let surface = &layer.surfaces[i];
+                    hasher.write_u8(1);
+                    hash_point(&mut hasher, surface.p1);
+                    hash_point(&mut hasher, surface.p2);
+                    surface.type_name.hash(&mut hasher);
+                }
+                hasher.write_u32(surface.flow.to_bits());
+                hasher.finish()
+            })
+            .collect();
+        self.tile_signatures = layer
+            .tiles
+            .iter()
+            .map(|tile| {
+                let mut hasher = new_hasher();
+                for ring in std::iter::once(&tile.vertices).chain(&tile.holes) {
+                    hasher.write_usize(ring.len());
+                    for &p in ring {
+                        hash_point(&mut hasher, p);
+                    }
+                }
+                hash_point(&mut hasher, tile.flow);
+                tile.type_name.hash(&mut hasher);
+                hasher.write_u32(layer.texture_scale.to_bits());
+                hasher.finish()
+            })
+            .collect();
+
+        self.contents.clear();
+        for (index, surface) in layer.surfaces.iter().enumerate() {
+            let chunk = chunk_at((surface.p1 + surface.p2) / 2.0);
+            let bounds = Aabb2::points_bounding_box([surface.p1, surface.p2])
+                .unwrap()
+                .extend_uniform(surface_texture_height(assets, surface));
+            let contents = self.contents.entry(chunk).or_default();
+            contents.surfaces.push(index);
+            contents.bounds = Some(contents.bounds.map_or(bounds, |b| {
+                Aabb2::points_bounding_box([b.min, b.max, bounds.min, bounds.max]).unwrap()
+            }));
+        }
+        for (index, tile) in layer.tiles.iter().enumerate() {
+            let Some(bounds) = Aabb2::points_bounding_box(tile.points()) else {
+                continue;
+            };
+            let contents = self.contents.entry(chunk_at(bounds.center())).or_default();
+            contents.tiles.push(index);
+            contents.bounds = Some(contents.bounds.map_or(bounds, |b| {
+                Aabb2::points_bounding_box([b.min, b.max, bounds.min, bounds.max]).unwrap()
+            }));
+        }
+        for contents in self.contents.values_mut() {
+            let mut hasher = new_hasher();
+            for &index in &contents.surfaces {
+                let layout = &self.layout[index];
+                hasher.write_u64(self.surface_signatures[index]);
+                hasher.write_u32(layout.start_t.to_bits());
+                hasher.write_u32(layout.stretch.to_bits());
+            }
+            for &index in &contents.tiles {
+                hasher.write_u64(self.tile_signatures[index]);
+            }
+            contents.signature = hasher.finish();
+        }
+        let contents = &self.contents;
+        self.chunks.retain(|chunk, _| contents.contains_key(chunk));
+    }
+
+    /// Geometry of the tiles and surfaces that were already there in `old` is reused
+    fn build_chunk(
+        &self,
+        geng: &Geng,
+        assets: &Assets,
+        layer: &LevelLayer,
+        contents: &ChunkContents,
+        old: Option<ChunkMesh>,
+    ) -> ChunkMesh {
+        let (mut old_tiles, mut old_surfaces) = match old {
+            Some(old) => (old.tile_vertices, old.surface_vertices),
+            None => default(),
         };
-        Self {
-            layers: level
-                .layers
-                .iter()
-                .map(|layer| LayerMesh {
-                    tiles: {
-                        let mut vertex_data: HashMap<String, Vec<TileVertex>> = HashMap::new();
-                        for tile in &layer.tiles {
-                            let fadeout_distance =
-                                assets.tiles[&tile.type_name].params.fadeout_distance
-                                    * layer.texture_scale;
-                            let data: [vec2<f32>; 3] = std::array::from_fn(|i| {
-                                let mut vs = tile.vertices;
-                                vs.rotate_left(i);
-                                let p1 = vs[1];
-                                let v1 = vs[0] - vs[1];
-                                let p2 = vs[2];
-                                let n2 = (vs[2] - vs[0]).rotate_90().normalize();
-                                let p1 = p1 + v1.rotate_90().normalize() * fadeout_distance;
-                                let p2 = p2 + n2 * fadeout_distance;
-                                let t = ray_hit_time(p1, v1, p2, n2);
-                                p1 + v1 * t
-                            });
-                            vertex_data
-                                .entry(tile.type_name.clone())
-                                .or_default()
-                                .extend(data.into_iter().map(|v| TileVertex {
-                                    a_pos: v,
-                                    a_side_distances: {
-                                        let distances: [f32; 3] = std::array::from_fn(|i| {
-                                            let n = (tile.vertices[i] - tile.vertices[(i + 1) % 3])
-                                                .rotate_90()
-                                                .normalize();
-                                            (vec2::dot(v, n) - vec2::dot(tile.vertices[i], n))
-                                                / fadeout_distance
-                                        });
-                                        let [x, y, z] = distances;
-                                        vec3(x, y, z)
-                                    },
-                                    a_corner_distances: {
-                                        let distances: [f32; 3] = std::array::from_fn(|i| {
-                                            let mut vs = tile.vertices;
-                                            vs.rotate_left(i);
-                                            let n1 = (vs[0] - vs[1]).rotate_90().normalize();
-                                            let n2 = (vs[2] - vs[0]).rotate_90().normalize();
-                                            let n = (n1 + n2).normalize();
-                                            (vec2::dot(v, n) - vec2::dot(tile.vertices[i], n))
-                                                / fadeout_distance
-                                        });
-                                        let [x, y, z] = distances;
-                                        vec3(x, y, z)
-                                    },
-                                    a_flow: tile.flow,
-                                }));
-                        }
-                        vertex_data
-                            .into_iter()
-                            .map(|(type_name, data)| {
-                                (type_name, ugli::VertexBuffer::new_static(geng.ugli(), data))
-                            })
-                            .collect()
-                    },
-                    surfaces: {
-                        let mut vertex_data: HashMap<String, Vec<SurfaceVertex>> = HashMap::new();
-
-                        type Key = usize;
-                        let mut vertex_ts: HashMap<Key, f32> = default();
-                        let mut queue = std::collections::VecDeque::<Key>::new();
-                        for key in 0..layer.surfaces.len() {
-                            if vertex_ts.contains_key(&key) {
-                                continue;
-                            }
-                            vertex_ts.insert(key, 0.0);
-                            queue.push_back(key);
-                            let mut this_pass = Vec::new();
-                            while let Some(key) = queue.pop_front() {
-                                this_pass.push(key);
-                                let surface = &layer.surfaces[key];
-                                let start_t = *vertex_ts.get(&key).unwrap();
-                                let end_t = start_t + (surface.p2 - surface.p1).len();
-                                for (i, other) in layer.surfaces.iter().enumerate() {
-                                    if other.type_name != surface.type_name {
-                                        continue;
-                                    }
-                                    let mut push = |key: Key, t: f32| {
-                                        vertex_ts.entry(key).or_insert_with(|| {
-                                            queue.push_back(key);
-                                            t
-                                        });
-                                    };
-                                    for (t, p) in [(start_t, surface.p1), (end_t, surface.p2)] {
-                                        if p == other.p1 {
-                                            push(i, t + arc_len(surface, other));
-                                        }
-                                        if p == other.p2 {
-                                            push(
-                                                i,
-                                                t - (other.p2 - other.p1).len()
-                                                    - arc_len(other, surface),
-                                            );
-                                        }
-                                    }
-                                }
-                            }
-
-                            let mut this_pass_vertex_data =
-                                HashMap::<String, Vec<SurfaceVertex>>::new();
-
-                            for i in this_pass {
-                                let surface = &layer.surfaces[i];
-                                let normal = (surface.p2 - surface.p1).normalize().rotate_90();
-                                let start_t = *vertex_ts.get(&i).unwrap();
-                                let len = (surface.p2 - surface.p1).len();
-                                let end_t = start_t + len;
-
-                                let prev = layer.surfaces.iter().find(|other| {
-                                    other.p2 == surface.p1 && other.type_name == surface.type_name
-                                });
-                                let next = layer.surfaces.iter().find(|other| {
-                                    other.p1 == surface.p2 && other.type_name == surface.type_name
-                                });
-
-                                // Rect
-                                this_pass_vertex_data
-                                    .entry(surface.type_name.clone())
-                                    .or_default()
-                                    .extend({
-                                        let dir = surface.p2 - surface.p1;
-                                        let start_ratio = prev
-                                            .map_or(0.0, |prev| {
-                                                ray_hit_time(
-                                                    surface.p1
-                                                        + surface.normal()
-                                                            * surface_texture_height(surface),
-                                                    dir,
-                                                    prev.p1
-                                                        + prev.normal()
-                                                            * surface_texture_height(prev),
-                                                    prev.normal(),
-                                                )
-                                            })
-                                            .max(0.0);
-                                        let end_ratio = next
-                                            .map_or(1.0, |next| {
-                                                ray_hit_time(
-                                                    surface.p1
-                                                        + surface.normal()
-                                                            * surface_texture_height(surface),
-                                                    dir,
-                                                    next.p1
-                                                        + next.normal()
-                                                            * surface_texture_height(next),
-                                                    next.normal(),
-                                                )
-                                            })
-                                            .min(1.0);
-                                        let p1 = surface.p1 + dir * start_ratio;
-                                        let p2 = surface.p1 + dir * end_ratio;
-                                        let vs = [
-                                            SurfaceVertex {
-                                                a_pos: p1,
-                                                a_normal: normal,
-                                                a_flow: surface.flow,
-                                                a_vt: vec2(start_t, 0.0),
-                                            },
-                                            SurfaceVertex {
-                                                a_pos: p2,
-                                                a_normal: normal,
-                                                a_flow: surface.flow,
-                                                a_vt: vec2(end_t, 0.0),
-                                            },
-                                            SurfaceVertex {
-                                                a_pos: p2
-                                                    + surface_texture_height(surface) * normal,
-                                                a_normal: normal,
-                                                a_flow: surface.flow,
-                                                a_vt: vec2(end_t, 1.0),
-                                            },
-                                            SurfaceVertex {
-                                                a_pos: p1
-                                                    + surface_texture_height(surface) * normal,
-                                                a_normal: normal,
-                                                a_flow: surface.flow,
-                                                a_vt: vec2(start_t, 1.0),
-                                            },
-                                        ];
-                                        [vs[0], vs[1], vs[2], vs[0], vs[2], vs[3]]
-                                    });
-
-                                // Corner to the next segment
-                                if let Some(next) = next {
-                                    const R: usize = 100;
-                                    fn lerp(a: vec2<f32>, b: vec2<f32>, t: f32) -> vec2<f32> {
-                                        a * (1.0 - t) + b * t
-                                    }
-                                    let n1 = normal;
-                                    let n2 = (next.p2 - next.p1).rotate_90().normalize_or_zero();
-                                    struct Point {
-                                        pos: vec2<f32>,
-                                        normal: vec2<f32>,
-                                        height: f32,
-                                    }
-                                    let mut vs = Vec::<Point>::new();
-                                    if vec2::skew(surface.normal(), next.normal()) > EPS {
-                                        let ray_start = surface.p1
-                                            + surface.normal() * surface_texture_height(surface);
-                                        let ray_dir = surface.p2 - surface.p1;
-                                        let t = ray_hit_time(
-                                            ray_start,
-                                            ray_dir,
-                                            next.p1 + next.normal() * surface_texture_height(next),
-                                            next.normal(),
-                                        );
-                                        let mid = ray_start + ray_dir * t;
-                                        let p1 = mid
-                                            - surface.normal() * surface_texture_height(surface);
-                                        let p2 = mid - next.normal() * surface_texture_height(next);
-                                        for (p1, p2) in [(p1, surface.p2), (surface.p2, p2)] {
-                                            for j in 0..=R {
-                                                let pos = lerp(p1, p2, j as f32 / R as f32);
-                                                if vs.last().map(|p| p.pos) == Some(pos) {
-                                                    // LUL
-                                                    continue;
-                                                }
-                                                vs.push(Point {
-                                                    pos,
-                                                    normal: (mid - pos).normalize(),
-                                                    height: (mid - pos).len(),
-                                                });
-                                            }
-                                        }
-                                    } else {
-                                        let a = n1.arg().as_radians();
-                                        let b = n2.arg().as_radians();
-                                        let mut angle = b - a;
-                                        if angle > 0.0 {
-                                            angle -= 2.0 * f32::PI;
-                                        }
-                                        for j in 0..=R {
-                                            vs.push(Point {
-                                                pos: surface.p2,
-                                                normal: vec2(1.0, 0.0).rotate(Angle::from_radians(
-                                                    a + angle * j as f32 / R as f32,
-                                                )),
-                                                height: surface_texture_height(surface),
-                                            });
-                                        }
-                                    }
-                                    let (start_t, end_t) = {
-                                        let start_t = end_t;
-                                        (start_t, start_t + arc_len(surface, next))
-                                    };
-                                    for (i, seg) in vs.windows(2).enumerate() {
-                                        let p1 = &seg[0];
-                                        let p2 = &seg[1];
-                                        let (start_t, end_t) = {
-                                            (
-                                                start_t
-                                                    + (end_t - start_t) * i as f32
-                                                        / vs.len() as f32,
-                                                start_t
-                                                    + (end_t - start_t) * (i + 1) as f32
-                                                        / vs.len() as f32,
-                                            )
-                                        };
-                                        let vs = [
-                                            SurfaceVertex {
-                                                a_pos: p1.pos,
-                                                a_normal: p1.normal,
-                                                a_flow: surface.flow,
-                                                a_vt: vec2(start_t, 0.0),
-                                            },
-                                            SurfaceVertex {
-                                                a_pos: p2.pos,
-                                                a_normal: p2.normal,
-                                                a_flow: surface.flow,
-                                                a_vt: vec2(end_t, 0.0),
-                                            },
-                                            SurfaceVertex {
-                                                a_pos: p2.pos + p2.height * p2.normal,
-                                                a_normal: p2.normal,
-                                                a_flow: surface.flow,
-                                                a_vt: vec2(end_t, 1.0),
-                                            },
-                                            SurfaceVertex {
-                                                a_pos: p1.pos + p1.height * p1.normal,
-                                                a_normal: p1.normal,
-                                                a_flow: surface.flow,
-                                                a_vt: vec2(start_t, 1.0),
-                                            },
-                                        ];
-                                        this_pass_vertex_data
-                                            .entry(surface.type_name.clone())
-                                            .or_default()
-                                            .extend([vs[0], vs[1], vs[2], vs[0], vs[2], vs[3]]);
-                                    }
-                                } else {
-                                    log::warn!("Not connected????");
-                                }
-                            }
-
-                            let max = this_pass_vertex_data
-                                .values()
-                                .flatten()
-                                .map(|v| r32(v.a_vt.x))
-                                .max()
-                                .unwrap();
-                            let min = this_pass_vertex_data
-                                .values()
-                                .flatten()
-                                .map(|v| r32(v.a_vt.x))
-                                .min()
-                                .unwrap();
-                            let total_len = (max - min).raw();
-                            let rounded_len = total_len.round().max(1.0);
-                            for (name, mut data) in this_pass_vertex_data {
-                                for v in &mut data {
-                                    v.a_vt.x *= rounded_len / total_len;
-                                }
-                                vertex_data.entry(name).or_default().extend(data);
-                            }
-                        }
-                        vertex_data
-                            .into_iter()
-                            .map(|(type_name, data)| {
-                                let data = {
-                                    // TODO should be handled differently
-                                    let surface_assets = &assets.surfaces[&type_name];
-                                    let texture = surface_assets
-                                        .textures
-                                        .front
-                                        .as_ref()
-                                        .or(surface_assets.textures.back.as_ref())
-                                        .unwrap();
-                                    let height = texture.size().y as f32 / texture.size().x as f32;
-                                    let mut data = data;
-                                    for vertex in &mut data {
-                                        vertex.a_pos -= vertex.a_normal
-                                            * height
-                                            * surface_assets.params.texture_underground;
-                                    }
-                                    data
-                                };
-                                (type_name, ugli::VertexBuffer::new_static(geng.ugli(), data))
-                            })
-                            .collect()
-                    },
+
+        let mut tile_cache = HashMap::<u64, Vec<TileVertex>>::new();
+        let mut tile_data: HashMap<String, Vec<TileVertex>> = HashMap::new();
+        for &index in &contents.tiles {
+            let tile = &layer.tiles[index];
+            let vertices = tile_cache
+                .entry(self.tile_signatures[index])
+                .or_insert_with_key(|signature| {
+                    old_tiles.remove(signature).unwrap_or_else(|| {
+                        let fadeout_distance =
+                            assets.tiles[&tile.type_name].params.fadeout_distance
+                                * layer.texture_scale;
+                        let mut vertices = Vec::new();
+                        tile_vertices(tile, fadeout_distance, &mut vertices);
+                        vertices
+                    })
+                });
+            tile_data
+                .entry(tile.type_name.clone())
+                .or_default()
+                .extend_from_slice(vertices);
+        }
+
+        let mut surface_cache = HashMap::<u64, Vec<SurfaceVertex>>::new();
+        let mut surface_data: HashMap<String, Vec<SurfaceVertex>> = HashMap::new();
+        for &index in &contents.surfaces {
+            let surface = &layer.surfaces[index];
+            let layout = &self.layout[index];
+            let vertices = surface_cache
+                .entry(self.surface_signatures[index])
+                .or_insert_with_key(|signature| {
+                    old_surfaces.remove(signature).unwrap_or_else(|| {
+                        surface_vertices(
+                            assets,
+                            surface,
+                            layout.prev.map(|prev| &layer.surfaces[prev]),
+                            layout.next.map(|next| &layer.surfaces[next]),
+                        )
+                    })
+                });
+            surface_data
+                .entry(surface.type_name.clone())
+                .or_default()
+                .extend(vertices.iter().map(|&vertex| SurfaceVertex {
+                    a_vt: vec2(
+                        (vertex.a_vt.x + layout.start_t) * layout.stretch,
+                        vertex.a_vt.y,
+                    ),
+                    ..vertex
+                }));
+        }
+
+        ChunkMesh {
+            tiles: tile_data
+                .into_iter()
+                .map(|(type_name, data)| {
+                    (type_name, ugli::VertexBuffer::new_static(geng.ugli(), data))
+                })
+                .collect(),
+            surfaces: surface_data
+                .into_iter()
+                .map(|(type_name, data)| {
+                    (type_name, ugli::VertexBuffer::new_static(geng.ugli(), data))
                 })
                 .collect(),
+            tile_vertices: tile_cache,
+            surface_vertices: surface_cache,
+            bounds: contents.bounds.unwrap(),
+            signature: contents.signature,
+        }
+    }
+
+    fn is_up_to_date(&self, chunk: (i32, i32), mesh: &ChunkMesh) -> bool {
+        self.contents
+            .get(&chunk)
+            .map_or(false, |contents| contents.signature == mesh.signature)
+    }
+
+    /// Builds or rebuilds the chunks around the view and drops the ones far from it
+    fn prepare(&mut self, geng: &Geng, assets: &Assets, layer: &LevelLayer, view: Aabb2<f32>) {
+        let keep_area = view.extend_uniform(CHUNK_EVICT_DISTANCE);
+        self.chunks
+            .retain(|_, chunk| overlaps(chunk.bounds, keep_area));
+        let build_area = view.extend_uniform(CHUNK_PRELOAD_DISTANCE);
+        let outdated: Vec<(i32, i32)> = self
+            .contents
+            .iter()
+            .filter(|&(&chunk, contents)| {
+                contents
+                    .bounds
+                    .map_or(false, |bounds| overlaps(bounds, build_area))
+                    && self
+                        .chunks
+                        .get(&chunk)
+                        .map_or(true, |mesh| !self.is_up_to_date(chunk, mesh))
+            })
+            .map(|(&chunk, _)| chunk)
+            .collect();
+        if !outdated.is_empty() {
+            log::debug!("Building {} level mesh chunks", outdated.len());
+        }
+        for chunk in outdated {
+            let old = self.chunks.remove(&chunk);
+            let mesh = self.build_chunk(geng, assets, layer, &self.contents[&chunk], old);
+            self.chunks.insert(chunk, mesh);
+        }
+    }
+
+    fn visible_chunks(&self, view: Aabb2<f32>) -> impl Iterator<Item = &ChunkMesh> {
+        self.chunks
+            .iter()
+            .filter(move |&(&chunk, mesh)| {
+                overlaps(mesh.bounds, view) && self.is_up_to_date(chunk, mesh)
+            })
+            .map(|(_, mesh)| mesh)
+    }
+}
+
+impl Default for LevelMesh {
+    fn default() -> Self {
+        Self {
+            layers: vec![],
+            outdated: true,
+            outdated_layers: HashSet::new(),
+        }
+    }
+}
+
+impl LevelMesh {
+    /// Changed chunks are rebuilt next time they are drawn, the rest are kept
+    pub fn invalidate(&mut self) {
+        self.outdated = true;
+    }
+
+    /// Same as [LevelMesh::invalidate] when nothing but the contents of the layer changed
+    pub fn invalidate_layer(&mut self, layer: usize) {
+        self.outdated_layers.insert(layer);
+    }
+
+    fn update(&mut self, assets: &Assets, level: &LevelInfo) {
+        let outdated_layers = mem::take(&mut self.outdated_layers);
+        if mem::take(&mut self.outdated) {
+            self.layers.resize_with(level.layers.len(), default);
+            for (mesh, layer) in self.layers.iter_mut().zip(&level.layers) {
+                mesh.update(assets, layer);
+            }
+            return;
+        }
+        for index in outdated_layers {
+            if let Some(mesh) = self.layers.get_mut(index) {
+                mesh.update(assets, &level.layers[index]);
+            }
         }
     }
 }
 
 impl Game {
-    fn get_mesh<'a>(&self, level: &'a Level) -> Ref<'a, LevelMesh> {
+    /// Camera for drawing a layer, and the part of the layer it sees
+    fn layer_camera(&self, level: &Level, layer_index: usize) -> (geng::Camera2d, Aabb2<f32>) {
+        let camera = geng::Camera2d {
+            center: self.camera.center * level.layers[layer_index].parallax,
+            ..self.camera
+        };
+        let view = Aabb2::point(camera.center).extend_symmetric(
+            vec2(
+                camera.fov * self.framebuffer_size.x / self.framebuffer_size.y,
+                camera.fov,
+            ) / 2.0,
+        );
+        (camera, view)
+    }
+
+    fn get_mesh<'a>(
+        &self,
+        level: &'a Level,
+        layer_index: usize,
+        view: Aabb2<f32>,
+    ) -> Ref<'a, LevelMesh> {
         {
+            let assets = self.assets.get();
+            let flattened = level.flattened();
             let mut mesh = level.mesh.borrow_mut();
-            if mesh.is_none() {
-                *mesh = Some(LevelMesh::new(&self.geng, &self.assets, level));
-                log::debug!("Creating level mesh");
-            };
+            mesh.update(&assets, flattened);
+            mesh.layers[layer_index].prepare(
+                &self.geng,
+                &assets,
+                &flattened.layers[layer_index],
+                view,
+            );
         }
-        Ref::map(level.mesh.borrow(), |opt| opt.as_ref().unwrap())
+        level.mesh.borrow()
     }
 
     fn draw_surfaces(
@@ -422,13 +755,13 @@ impl Game {
         texture_move_direction: f32,
     ) {
         let assets = self.assets.get();
-        let camera = geng::Camera2d {
-            center: self.camera.center * level.layers[layer_index].parallax,
-            ..self.camera
-        };
-        let mesh = self.get_mesh(level);
+        let (camera, view) = self.layer_camera(level, layer_index);
+        let mesh = self.get_mesh(level, layer_index, view);
 
-        for (type_name, data) in &mesh.layers[layer_index].surfaces {
+        for (type_name, data) in mesh.layers[layer_index]
+            .visible_chunks(view)
+            .flat_map(|chunk| &chunk.surfaces)
+        {
             let surface_assets = &assets.surfaces[type_name];
             let texture = match texture(surface_assets) {
                 Some(texture) => texture,
@@ -464,13 +797,13 @@ impl Game {
 
     fn draw_tiles(&self, framebuffer: &mut ugli::Framebuffer, level: &Level, layer_index: usize) {
         let assets = self.assets.get();
-        let camera = geng::Camera2d {
-            center: self.camera.center * level.layers[layer_index].parallax,
-            ..self.camera
-        };
-        let mesh = self.get_mesh(level);
+        let (camera, view) = self.layer_camera(level, layer_index);
+        let mesh = self.get_mesh(level, layer_index, view);
 
-        for (type_name, data) in &mesh.layers[layer_index].tiles {
+        for (type_name, data) in mesh.layers[layer_index]
+            .visible_chunks(view)
+            .flat_map(|chunk| &chunk.tiles)
+        {
             let tile_assets = &assets.tiles[type_name];
             let texture_scale = vec2(tile_assets.texture.size().map(|x| x as f32).aspect(), 1.0)
                 * tile_assets.params.texture_scale
@@ -519,7 +852,7 @@ impl Game {
 
     pub fn draw_portals(&self, level: &Level, framebuffer: &mut ugli::Framebuffer) {
         let assets = self.assets.get();
-        for portal in &level.portals {
+        for portal in &level.flattened().portals {
             self.geng.draw2d().draw2d(
                 framebuffer,
                 &self.camera,
@@ -540,13 +873,13 @@ impl Game {
         let assets = self.assets.get();
         self.draw_tiles(framebuffer, level, layer_index);
         {
-            for obj in &level.layers[layer_index].objects {
+            for obj in &level.flattened().layers[layer_index].objects {
                 self.geng.draw2d().draw2d(
                     framebuffer,
                     &self.camera,
-                    &draw2d::TexturedQuad::unit(&assets.objects[&obj.type_name])
+                    &draw2d::TexturedQuad::unit(&assets.objects[&obj.type_name].texture)
                         .transform(mat3::rotate(Angle::from_radians(
-                            if obj.fart_type().is_some() {
+                            if assets.objects[&obj.type_name].params.fart_type.is_some() {
                                 self.real_time
                             } else {
                                 0.0
@@ -581,6 +914,7 @@ impl Game {
             -1.0,
         );
         level
+            .flattened()
             .cannon
             .draw(&self.geng, &self.assets.get(), framebuffer, &self.camera);
     }
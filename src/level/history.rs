@@ -0,0 +1,645 @@
+use super::*;
+
+/// Oldest entries are dropped after this
+const MAX_HISTORY_ENTRIES: usize = 1000;
+
+/// Structural difference between two json values
+#[derive(Serialize, Deserialize, Clone, Debug)]
+enum Diff {
+    Set(serde_json::Value),
+    Object {
+        changed: Vec<(String, Diff)>,
+        removed: Vec<String>,
+    },
+    /// Elements changed in place
+    Array(Vec<(usize, Diff)>),
+    /// Range of elements replaced with other elements
+    Splice {
+        start: usize,
+        remove: usize,
+        insert: Vec<serde_json::Value>,
+    },
+}
+
+fn diff(old: &serde_json::Value, new: &serde_json::Value) -> Option<Diff> {
+    use serde_json::Value;
+    if old == new {
+        return None;
+    }
+    Some(match (old, new) {
+        (Value::Object(old), Value::Object(new)) => Diff::Object {
+            changed: new
+                .iter()
+                .filter_map(|(key, value)| {
+                    let diff = match old.get(key) {
+                        Some(old_value) => diff(old_value, value)?,
+                        None => Diff::Set(value.clone()),
+                    };
+                    Some((key.clone(), diff))
+                })
+                .collect(),
+            removed: old
+                .keys()
+                .filter(|key| !new.contains_key(*key))
+                .cloned()
+                .collect(),
+        },
+        (Value::Array(old), Value::Array(new)) => {
+            let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+            let suffix = old[prefix..]
+                .iter()
+                .rev()
+                .zip(new[prefix..].iter().rev())
+                .take_while(|(a, b)| a == b)
+                .count();
+            let old_middle = &old[prefix..old.len() - suffix];
+            let new_middle = &new[prefix..new.len() - suffix];
+            if old_middle.len() == new_middle.len() {
+                Diff::Array(
+                    old_middle
+                        .iter()
+                        .zip(new_middle)
+                        .enumerate()
+                        .filter_map(|(i, (a, b))| Some((prefix + i, diff(a, b)?)))
+                        .collect(),
+                )
+            } else {
+                Diff::Splice {
+                    start: prefix,
+                    remove: old_middle.len(),
+                    insert: new_middle.to_vec(),
+                }
+            }
+        }
+        _ => Diff::Set(new.clone()),
+    })
+}
+
+fn apply(value: &mut serde_json::Value, diff: &Diff) {
+    match diff {
+        Diff::Set(new) => *value = new.clone(),
+        Diff::Object { changed, removed } => {
+            let object = value.as_object_mut().unwrap();
+            for (key, diff) in changed {
+                apply(
+                    object.entry(key.clone()).or_insert(serde_json::Value::Null),
+                    diff,
+                );
+            }
+            for key in removed {
+                object.remove(key);
+            }
+        }
+        Diff::Array(items) => {
+            let array = value.as_array_mut().unwrap();
+            for (index, diff) in items {
+                apply(&mut array[*index], diff);
+            }
+        }
+        Diff::Splice {
+            start,
+            remove,
+            insert,
+        } => {
+            value
+                .as_array_mut()
+                .unwrap()
+                .splice(*start..*start + *remove, insert.iter().cloned());
+        }
+    }
+}
+
+/// Describes a diff like "add 2 surfaces, remove 1 tile"
+fn summarize(diff: &Diff) -> String {
+    type Counts = std::collections::BTreeMap<(&'static str, String), usize>;
+    fn add(counts: &mut Counts, verb: &'static str, key: &str, n: usize) {
+        if n != 0 {
+            *counts.entry((verb, key.to_owned())).or_default() += n;
+        }
+    }
+    fn count(diff: &Diff, key: &str, counts: &mut Counts) {
+        match diff {
+            Diff::Set(_) => add(counts, "change", key, 1),
+            Diff::Object { changed, removed } => {
+                for (key, diff) in changed {
+                    count(diff, key, counts);
+                }
+                for key in removed {
+                    add(counts, "change", key, 1);
+                }
+            }
+            Diff::Array(items) => {
+                for (_index, diff) in items {
+                    // Items that only contain plain fields count as a single change
+                    let mut inner = Counts::new();
+                    if let Diff::Object { changed, .. } = diff {
+                        for (key, diff) in changed {
+                            if matches!(diff, Diff::Array(_) | Diff::Splice { .. }) {
+                                count(diff, key, &mut inner);
+                            }
+                        }
+                    }
+                    if inner.is_empty() {
+                        add(counts, "change", key, 1);
+                    } else {
+                        for ((verb, key), n) in inner {
+                            add(counts, verb, &key, n);
+                        }
+                    }
+                }
+            }
+            Diff::Splice { remove, insert, .. } => {
+                add(counts, "remove", key, *remove);
+                add(counts, "add", key, insert.len());
+            }
+        }
+    }
+    let mut counts = Counts::new();
+    count(diff, "level", &mut counts);
+    if counts.is_empty() {
+        return "edit".to_owned();
+    }
+    counts
+        .into_iter()
+        .map(|((verb, key), n)| {
+            let key = if n == 1 {
+                key.strip_suffix('s').unwrap_or(&key)
+            } else {
+                &key
+            };
+            format!("{verb} {n} {key}")
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// FNV-1a, to check that saved history still matches the level and prefab files
+fn hash(value: &serde_json::Value) -> u64 {
+    serde_json::to_string(value)
+        .unwrap()
+        .bytes()
+        .fold(0xcbf29ce484222325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        })
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct HistoryEntry {
+    pub label: String,
+    forward: Diff,
+    backward: Diff,
+}
+
+#[derive(Serialize, Deserialize)]
+struct HistoryFile {
+    hash: u64,
+    index: usize,
+    entries: Vec<HistoryEntry>,
+}
+
+/// Part of the level changed by [Level::modify_items],
+/// cannons and portals are not in a layer, the rest are items of the given layer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LevelItem {
+    Surface(usize),
+    Curve(usize),
+    Tile(usize),
+    Object(usize),
+    Prefab(usize),
+    Cannon(usize),
+    Portal(usize),
+}
+
+/// Undo history stored as diffs between versions of the level
+#[derive(Default)]
+pub struct History {
+    entries: Vec<HistoryEntry>,
+    index: usize,
+    /// Level and prefabs as of the current entry, created and loaded on first use
+    snapshot: Option<serde_json::Value>,
+    /// The level was handed out for modification since the last entry
+    pending: bool,
+    pending_label: Option<String>,
+    /// Modifications are collected into a single entry until the batch ends
+    batch: bool,
+    /// Index of the state the level was opened in, shifted as old entries are dropped
+    opened: Option<usize>,
+}
+
+impl History {
+    pub fn entries(&self) -> &[HistoryEntry] {
+        &self.entries
+    }
+
+    /// Number of entries applied to get to the current state
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Index of the state the level was opened in, if it is still in the history
+    pub fn opened(&self) -> Option<usize> {
+        self.opened
+    }
+}
+
+impl Level {
+    fn history_path(&self) -> std::path::PathBuf {
+        self.path.with_extension("history.json")
+    }
+
+    /// Prefabs are part of the history too, so that making one can be undone
+    fn snapshot(&self) -> serde_json::Value {
+        serde_json::json!({
+            "level": self.info,
+            "prefabs": self.prefabs,
+        })
+    }
+
+    /// Sets the level and prefabs to the current snapshot
+    fn restore_snapshot(&mut self) {
+        let snapshot = self.history.snapshot.as_ref().unwrap();
+        let prefabs = serde_json::to_value(&self.prefabs).unwrap();
+        let (old_prefabs, new_prefabs) = (
+            prefabs.as_object().unwrap(),
+            snapshot["prefabs"].as_object().unwrap(),
+        );
+        for name in old_prefabs.keys().chain(new_prefabs.keys()) {
+            if old_prefabs.get(name) != new_prefabs.get(name) {
+                self.unsaved_prefabs.insert(name.clone());
+            }
+        }
+        self.info = serde_json::from_value(snapshot["level"].clone()).unwrap();
+        self.prefabs = serde_json::from_value(snapshot["prefabs"].clone()).unwrap();
+        self.invalidate();
+    }
+
+    fn init_history(&mut self) {
+        if self.history.snapshot.is_some() {
+            return;
+        }
+        let snapshot = self.snapshot();
+        if let Ok(json) = std::fs::read_to_string(self.history_path()) {
+            match serde_json::from_str::<HistoryFile>(&json) {
+                Ok(file) if file.hash == hash(&snapshot) && file.index <= file.entries.len() => {
+                    self.history.entries = file.entries;
+                    self.history.index = file.index;
+                }
+                Ok(_) => log::warn!("Level was changed outside of the editor, history discarded"),
+                Err(e) => log::error!("Failed to load level history: {e}"),
+            }
+        }
+        self.history.snapshot = Some(snapshot);
+        self.history.opened = Some(self.history.index);
+    }
+
+    /// Record the pending modification as a history entry
+    fn commit_history(&mut self) {
+        if self.history.batch || !mem::take(&mut self.history.pending) {
+            return;
+        }
+        let label = self.history.pending_label.take();
+        let new = self.snapshot();
+        let old = self.history.snapshot.take().unwrap();
+        if let Some(forward) = diff(&old, &new) {
+            let backward = diff(&new, &old).unwrap();
+            let history = &mut self.history;
+            if history.opened > Some(history.index) {
+                // The opened state was undone and now can't be redone
+                history.opened = None;
+            }
+            history.entries.truncate(history.index);
+            history.entries.push(HistoryEntry {
+                label: label.unwrap_or_else(|| summarize(&forward)),
+                forward,
+                backward,
+            });
+            history.index += 1;
+            if history.entries.len() > MAX_HISTORY_ENTRIES {
+                history.entries.remove(0);
+                history.index -= 1;
+                history.opened = history.opened.and_then(|index| index.checked_sub(1));
+            }
+        }
+        self.history.snapshot = Some(new);
+    }
+
+    fn invalidate(&mut self) {
+        self.mesh.get_mut().invalidate();
+        *self.index.get_mut() = None;
+        self.flattened.take();
+        self.saved = false;
+    }
+
+    pub fn modify(&mut self) -> &mut LevelInfo {
+        self.init_history();
+        self.commit_history();
+        self.invalidate();
+        self.history.pending = true;
+        &mut self.info
+    }
+
+    /// Same as [Level::modify] for changes that only move the given items around.
+    /// During a batch only these items are regenerated, instead of the whole level
+    pub fn modify_items(
+        &mut self,
+        layer: usize,
+        items: &[LevelItem],
+        f: impl FnOnce(&mut LevelInfo),
+    ) {
+        let expanded = items
+            .iter()
+            .any(|item| matches!(item, LevelItem::Curve(_) | LevelItem::Prefab(_)));
+        if !self.history.batch || expanded {
+            // Outside of a batch the whole level is diffed anyway,
+            // and curves and prefabs change the number of items in the flattened level
+            f(self.modify());
+            return;
+        }
+        self.init_history();
+        self.history.pending = true;
+        self.saved = false;
+        let mut index = self.index.get_mut().take();
+        if let Some(index) = &mut index {
+            index.remove_items(self.flattened(), layer, items);
+        }
+        f(&mut self.info);
+        if let Some(flattened) = self.flattened.get_mut() {
+            // Items of the level itself come first in the flattened level
+            let (target, source) = (&mut flattened.layers[layer], &self.info.layers[layer]);
+            for &item in items {
+                match item {
+                    LevelItem::Surface(i) => target.surfaces[i] = source.surfaces[i].clone(),
+                    LevelItem::Tile(i) => target.tiles[i] = source.tiles[i].clone(),
+                    LevelItem::Object(i) => target.objects[i] = source.objects[i].clone(),
+                    LevelItem::Cannon(i) => {
+                        flattened.cannon.cannons[i] = self.info.cannon.cannons[i].clone()
+                    }
+                    LevelItem::Portal(i) => flattened.portals[i] = self.info.portals[i].clone(),
+                    LevelItem::Curve(_) | LevelItem::Prefab(_) => unreachable!(),
+                }
+            }
+        }
+        if let Some(index) = &mut index {
+            index.insert_items(self.flattened(), layer, items);
+        }
+        *self.index.get_mut() = index;
+        self.mesh.get_mut().invalidate_layer(layer);
+    }
+
+    /// Same as [Level::modify], but with a description for the history instead of a generated one
+    pub fn modify_with_label(&mut self, label: impl Into<String>) -> &mut LevelInfo {
+        self.modify();
+        self.history.pending_label = Some(label.into());
+        &mut self.info
+    }
+
+    /// Adds or replaces a prefab as part of the pending modification,
+    /// it is written to the assets together with the level
+    pub fn set_prefab(&mut self, name: &str, prefab: LevelInfo) {
+        self.init_history();
+        self.history.pending = true;
+        self.prefabs.insert(name.to_owned(), prefab);
+        self.unsaved_prefabs.insert(name.to_owned());
+        self.invalidate();
+    }
+
+    /// Starts collecting all the modifications into a single history entry,
+    /// like every step of dragging something around
+    pub fn begin_history_batch(&mut self, label: impl Into<String>) {
+        self.end_history_batch();
+        self.init_history();
+        self.commit_history();
+        self.history.batch = true;
+        self.history.pending_label = Some(label.into());
+    }
+
+    pub fn in_history_batch(&self) -> bool {
+        self.history.batch
+    }
+
+    pub fn end_history_batch(&mut self) {
+        if mem::take(&mut self.history.batch) {
+            if !self.history.pending {
+                self.history.pending_label = None;
+            }
+            self.commit_history();
+        }
+    }
+
+    /// Reverts all the modifications made since the batch started
+    pub fn cancel_history_batch(&mut self) {
+        if !mem::take(&mut self.history.batch) {
+            return;
+        }
+        self.history.pending_label = None;
+        if mem::take(&mut self.history.pending) {
+            self.restore_snapshot();
+        }
+    }
+
+    pub fn history(&mut self) -> &History {
+        self.init_history();
+        self.commit_history();
+        &self.history
+    }
+
+    /// Undo or redo until the given number of entries is applied
+    pub fn jump_to_history(&mut self, index: usize) {
+        self.end_history_batch();
+        self.init_history();
+        self.commit_history();
+        let history = &mut self.history;
+        let index = index.min(history.entries.len());
+        if index == history.index {
+            return;
+        }
+        let snapshot = history.snapshot.as_mut().unwrap();
+        while history.index > index {
+            history.index -= 1;
+            apply(snapshot, &history.entries[history.index].backward);
+        }
+        while history.index < index {
+            apply(snapshot, &history.entries[history.index].forward);
+            history.index += 1;
+        }
+        self.restore_snapshot();
+    }
+
+    pub fn undo(&mut self) {
+        let index = self.history().index();
+        if index > 0 {
+            self.jump_to_history(index - 1);
+        }
+    }
+
+    pub fn redo(&mut self) {
+        let index = self.history().index();
+        self.jump_to_history(index + 1);
+    }
+
+    pub(super) fn save_history(&mut self) -> anyhow::Result<()> {
+        if self.history.snapshot.is_none() {
+            return Ok(());
+        }
+        self.commit_history();
+        let file = HistoryFile {
+            hash: hash(self.history.snapshot.as_ref().unwrap()),
+            index: self.history.index,
+            entries: self.history.entries.clone(),
+        };
+        let path = self.history_path();
+        serde_json::to_writer(
+            std::io::BufWriter::new(
+                std::fs::File::create(&path).context(format!("Failed to create {path:?}"))?,
+            ),
+            &file,
+        )
+        .context(format!("Failed to save history to {path:?}"))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_apply_revert() {
+        let old = serde_json::json!({
+            "name": "old",
+            "removed": 1,
+            "items": [1, 2, 3, 4],
+            "nested": [{ "x": 1 }, { "x": 2 }],
+        });
+        let new = serde_json::json!({
+            "name": "new",
+            "added": true,
+            "items": [1, 5, 6, 4, 7],
+            "nested": [{ "x": 1 }, { "x": 3, "y": 4 }],
+        });
+        let forward = diff(&old, &new).unwrap();
+        let backward = diff(&new, &old).unwrap();
+        let mut value = old.clone();
+        apply(&mut value, &forward);
+        assert_eq!(value, new);
+        apply(&mut value, &backward);
+        assert_eq!(value, old);
+        assert!(diff(&old, &old).is_none());
+    }
+
+    #[test]
+    fn summary() {
+        let old = serde_json::json!({ "layers": [{ "surfaces": [], "tiles": [1, 2] }] });
+        let new = serde_json::json!({ "layers": [{ "surfaces": [1, 2], "tiles": [1] }] });
+        assert_eq!(
+            summarize(&diff(&old, &new).unwrap()),
+            "add 2 surfaces, remove 1 tile",
+        );
+    }
+
+    #[test]
+    fn undo_redo() {
+        let mut level = fixtures::new_level();
+        let start = level.spawn_point;
+        level.modify().spawn_point = vec2(1.0, 2.0);
+        level.modify_with_label("move spawn").spawn_point = vec2(3.0, 4.0);
+        let history = level.history();
+        assert_eq!(history.entries().len(), 2);
+        assert_eq!(history.entries()[1].label, "move spawn");
+        assert_eq!(history.index(), 2);
+
+        level.undo();
+        assert_eq!(level.spawn_point, vec2(1.0, 2.0));
+        level.undo();
+        assert_eq!(level.spawn_point, start);
+        level.undo();
+        assert_eq!(level.history().index(), 0);
+        level.redo();
+        level.redo();
+        assert_eq!(level.spawn_point, vec2(3.0, 4.0));
+
+        // New modifications drop the undone entries
+        level.jump_to_history(1);
+        level.modify().finish_point = vec2(5.0, 6.0);
+        assert_eq!(level.history().entries().len(), 2);
+        assert_eq!(level.spawn_point, vec2(1.0, 2.0));
+    }
+
+    #[test]
+    fn batches() {
+        let mut level = fixtures::new_level();
+        let start = level.spawn_point;
+        level.begin_history_batch("drag");
+        for i in 1..=3 {
+            level.modify().spawn_point = vec2(i as f32, 0.0);
+        }
+        level.end_history_batch();
+        let history = level.history();
+        assert_eq!(history.entries().len(), 1);
+        assert_eq!(history.entries()[0].label, "drag");
+
+        level.begin_history_batch("cancelled");
+        level.modify().spawn_point = vec2(10.0, 0.0);
+        level.cancel_history_batch();
+        assert_eq!(level.spawn_point, vec2(3.0, 0.0));
+        assert_eq!(level.history().entries().len(), 1);
+
+        level.undo();
+        assert_eq!(level.spawn_point, start);
+    }
+
+    #[test]
+    fn modify_items_updates_index() {
+        let mut level = fixtures::new_level();
+        let layer = &mut level.modify().layers[0];
+        layer
+            .surfaces
+            .push(fixtures::surface(vec2(0.0, 0.0), vec2(1.0, 0.0), "grass"));
+        layer.tiles.push(Tile::new(
+            vec![vec2(0.0, 0.0), vec2(2.0, 0.0), vec2(0.0, 2.0)],
+            "dirt".to_owned(),
+        ));
+        let old = Aabb2::point(vec2(0.5, 0.5));
+        let new = Aabb2::point(vec2(100.5, 0.5));
+        assert_eq!(level.surfaces_in(old).len(), 1);
+        assert_eq!(level.tiles_in(old).len(), 1);
+
+        level.begin_history_batch("move");
+        let items = [LevelItem::Surface(0), LevelItem::Tile(0)];
+        level.modify_items(0, &items, |info| {
+            let layer = &mut info.layers[0];
+            layer.surfaces[0].p1 += vec2(100.0, 0.0);
+            layer.surfaces[0].p2 += vec2(100.0, 0.0);
+            for p in layer.tiles[0].points_mut() {
+                *p += vec2(100.0, 0.0);
+            }
+        });
+        assert!(level.surfaces_in(old).is_empty());
+        assert!(level.tiles_in(old).is_empty());
+        assert_eq!(level.surfaces_in(new).len(), 1);
+        assert!(level.tile_contains(0, 0, new.center()));
+        level.end_history_batch();
+        assert_eq!(level.history().entries().len(), 2);
+
+        level.undo();
+        assert_eq!(level.surfaces_in(old).len(), 1);
+        assert!(level.surfaces_in(new).is_empty());
+    }
+
+    #[test]
+    fn prefabs_are_in_history() {
+        let mut level = fixtures::new_level();
+        level.modify_with_label("make prefab rock").layers[0]
+            .prefabs
+            .push(PrefabInstance::new("rock".to_owned(), vec2(1.0, 1.0)));
+        level.set_prefab("rock", LevelInfo::default());
+        assert_eq!(level.history().entries().len(), 1);
+
+        level.undo();
+        assert!(level.prefabs().is_empty());
+        assert!(level.layers[0].prefabs.is_empty());
+        level.redo();
+        assert!(level.prefabs().contains_key("rock"));
+        assert_eq!(level.layers[0].prefabs.len(), 1);
+    }
+}
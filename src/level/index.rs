@@ -0,0 +1,317 @@
+use super::*;
+
+const CELL_SIZE: f32 = 4.0;
+
+/// Items covering more cells than this are put into a list checked by every query,
+/// and queries covering more cells than this go over all the cells instead
+const MAX_CELLS: f32 = 1024.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct ItemRef {
+    layer: usize,
+    index: usize,
+}
+
+#[derive(Default)]
+struct Cell {
+    surfaces: Vec<ItemRef>,
+    /// Tile and the index of the triangle in it
+    tile_triangles: Vec<(ItemRef, usize)>,
+    objects: Vec<ItemRef>,
+    progress_segments: Vec<usize>,
+}
+
+pub struct ProgressSegment {
+    pub a: vec2<f32>,
+    pub b: vec2<f32>,
+    /// Length of expected path before this segment
+    pub prefix_len: f32,
+}
+
+/// Uniform grid over gameplay geometry, so that queries don't have to scan the whole level
+pub struct LevelIndex {
+    cells: HashMap<(i32, i32), Cell>,
+    /// Items that are too big or not finite to put into the cells
+    oversized: Cell,
+    /// Triangulation of every tile of every layer, by layer and tile index,
+    /// so tiles are triangulated once here instead of on every query
+    tile_triangles: Vec<Vec<Vec<[vec2<f32>; 3]>>>,
+    progress_segments: Vec<ProgressSegment>,
+    progress_total_len: f32,
+}
+
+/// Cells touched by the aabb, or none if there are too many of them
+fn cell_range(aabb: Aabb2<f32>) -> Option<impl Iterator<Item = (i32, i32)>> {
+    let min = aabb.min.map(|x| (x / CELL_SIZE).floor());
+    let max = aabb.max.map(|x| (x / CELL_SIZE).floor());
+    // Also false for NaN and infinite aabbs
+    if !((max.x - min.x + 1.0) * (max.y - min.y + 1.0) <= MAX_CELLS) {
+        return None;
+    }
+    let (min, max) = (min.map(|x| x as i32), max.map(|x| x as i32));
+    Some((min.x..=max.x).flat_map(move |x| (min.y..=max.y).map(move |y| (x, y))))
+}
+
+/// Adds the value to the cells the aabb touches, or removes it from them
+fn update_cells<T: Copy + PartialEq>(
+    cells: &mut HashMap<(i32, i32), Cell>,
+    oversized: &mut Cell,
+    aabb: Aabb2<f32>,
+    list: fn(&mut Cell) -> &mut Vec<T>,
+    value: T,
+    insert: bool,
+) {
+    let Some(range) = cell_range(aabb) else {
+        if insert {
+            list(oversized).push(value);
+        } else {
+            list(oversized).retain(|&other| other != value);
+        }
+        return;
+    };
+    for cell in range {
+        if insert {
+            list(cells.entry(cell).or_default()).push(value);
+        } else if let Some(cell) = cells.get_mut(&cell) {
+            list(cell).retain(|&other| other != value);
+        }
+    }
+}
+
+impl LevelIndex {
+    pub fn new(info: &LevelInfo) -> Self {
+        let mut index = Self {
+            cells: HashMap::new(),
+            oversized: default(),
+            tile_triangles: info
+                .layers
+                .iter()
+                .map(|layer| vec![vec![]; layer.tiles.len()])
+                .collect(),
+            progress_segments: Vec::new(),
+            progress_total_len: 0.0,
+        };
+        for (layer_index, layer) in info.layers.iter().enumerate() {
+            let items: Vec<LevelItem> = itertools::chain![
+                (0..layer.surfaces.len()).map(LevelItem::Surface),
+                (0..layer.tiles.len()).map(LevelItem::Tile),
+                (0..layer.objects.len()).map(LevelItem::Object),
+            ]
+            .collect();
+            index.insert_items(info, layer_index, &items);
+        }
+
+        let mut prefix_len = 0.0;
+        for path in &info.expected_path {
+            for window in path.windows(2) {
+                let a = window[0];
+                let b = window[1];
+                let segment = index.progress_segments.len();
+                let aabb = Aabb2::points_bounding_box([a, b]).unwrap();
+                update_cells(
+                    &mut index.cells,
+                    &mut index.oversized,
+                    aabb,
+                    |cell| &mut cell.progress_segments,
+                    segment,
+                    true,
+                );
+                index
+                    .progress_segments
+                    .push(ProgressSegment { a, b, prefix_len });
+                prefix_len += (b - a).len();
+            }
+        }
+        index.progress_total_len = prefix_len;
+        index
+    }
+
+    /// Adds the item to the cells it touches, or removes it from them
+    fn update_item(&mut self, info: &LevelInfo, layer_index: usize, item: LevelItem, insert: bool) {
+        let layer = &info.layers[layer_index];
+        if let LevelItem::Tile(index) = item {
+            if insert {
+                self.tile_triangles[layer_index][index] = layer.tiles[index].triangles();
+            }
+        }
+        if !layer.gameplay {
+            return;
+        }
+        let item_ref = |index| ItemRef {
+            layer: layer_index,
+            index,
+        };
+        match item {
+            LevelItem::Surface(index) => {
+                let surface = &layer.surfaces[index];
+                let aabb = Aabb2::points_bounding_box([surface.p1, surface.p2]).unwrap();
+                update_cells(
+                    &mut self.cells,
+                    &mut self.oversized,
+                    aabb,
+                    |cell| &mut cell.surfaces,
+                    item_ref(index),
+                    insert,
+                );
+            }
+            LevelItem::Tile(index) => {
+                for (triangle_index, &triangle) in
+                    self.tile_triangles[layer_index][index].iter().enumerate()
+                {
+                    let aabb = Aabb2::points_bounding_box(triangle).unwrap();
+                    update_cells(
+                        &mut self.cells,
+                        &mut self.oversized,
+                        aabb,
+                        |cell| &mut cell.tile_triangles,
+                        (item_ref(index), triangle_index),
+                        insert,
+                    );
+                }
+            }
+            LevelItem::Object(index) => {
+                let aabb = Aabb2::point(layer.objects[index].pos);
+                update_cells(
+                    &mut self.cells,
+                    &mut self.oversized,
+                    aabb,
+                    |cell| &mut cell.objects,
+                    item_ref(index),
+                    insert,
+                );
+            }
+            // Not indexed
+            LevelItem::Curve(_)
+            | LevelItem::Prefab(_)
+            | LevelItem::Cannon(_)
+            | LevelItem::Portal(_) => {}
+        }
+    }
+
+    /// Takes the items out before they are changed, `info` is the flattened level
+    pub(super) fn remove_items(&mut self, info: &LevelInfo, layer: usize, items: &[LevelItem]) {
+        for &item in items {
+            self.update_item(info, layer, item, false);
+        }
+    }
+
+    /// Puts the items back in after they are changed, `info` is the flattened level
+    pub(super) fn insert_items(&mut self, info: &LevelInfo, layer: usize, items: &[LevelItem]) {
+        for &item in items {
+            self.update_item(info, layer, item, true);
+        }
+    }
+
+    /// Items from all cells touching the aabb, in level order and without duplicates
+    fn query<T: Copy + Ord>(&self, aabb: Aabb2<f32>, f: impl Fn(&Cell) -> &[T]) -> Vec<T> {
+        let cells: Vec<&Cell> = match cell_range(aabb) {
+            Some(range) => range.filter_map(|cell| self.cells.get(&cell)).collect(),
+            None => self.cells.values().collect(),
+        };
+        let mut result: Vec<T> = cells
+            .into_iter()
+            .chain([&self.oversized])
+            .flat_map(|cell| f(cell).iter().copied())
+            .collect();
+        result.sort();
+        result.dedup();
+        result
+    }
+
+    pub fn progress_total_len(&self) -> f32 {
+        self.progress_total_len
+    }
+
+    pub fn progress_segments(&self, aabb: Aabb2<f32>) -> Vec<&ProgressSegment> {
+        self.query(aabb, |cell| &cell.progress_segments)
+            .into_iter()
+            .map(|index| &self.progress_segments[index])
+            .collect()
+    }
+}
+
+impl Level {
+    pub fn index(&self) -> std::cell::Ref<LevelIndex> {
+        if self.index.borrow().is_none() {
+            *self.index.borrow_mut() = Some(LevelIndex::new(self.flattened()));
+        }
+        std::cell::Ref::map(self.index.borrow(), |index| index.as_ref().unwrap())
+    }
+
+    /// Gameplay surfaces that may intersect the aabb
+    pub fn surfaces_in(&self, aabb: Aabb2<f32>) -> Vec<&Surface> {
+        self.index()
+            .query(aabb, |cell| &cell.surfaces)
+            .into_iter()
+            .map(|item| &self.flattened().layers[item.layer].surfaces[item.index])
+            .collect()
+    }
+
+    /// Triangles of gameplay tiles that may intersect the aabb
+    pub fn tiles_in(&self, aabb: Aabb2<f32>) -> Vec<(&Tile, [vec2<f32>; 3])> {
+        let index = self.index();
+        index
+            .query(aabb, |cell| &cell.tile_triangles)
+            .into_iter()
+            .map(|(item, triangle)| {
+                (
+                    &self.flattened().layers[item.layer].tiles[item.index],
+                    index.tile_triangles[item.layer][item.index][triangle],
+                )
+            })
+            .collect()
+    }
+
+    /// Triangles of a tile in any layer, triangulated once until the level is modified
+    pub fn tile_triangles(&self, layer: usize, index: usize) -> std::cell::Ref<[[vec2<f32>; 3]]> {
+        std::cell::Ref::map(self.index(), |level_index| {
+            level_index.tile_triangles[layer][index].as_slice()
+        })
+    }
+
+    pub fn tile_contains(&self, layer: usize, index: usize, p: vec2<f32>) -> bool {
+        self.tile_triangles(layer, index)
+            .iter()
+            .any(|&triangle| inside_triangle(p, triangle))
+    }
+
+    /// Gameplay objects that may be inside the aabb
+    pub fn objects_in(&self, aabb: Aabb2<f32>) -> Vec<&Object> {
+        self.index()
+            .query(aabb, |cell| &cell.objects)
+            .into_iter()
+            .map(|item| &self.flattened().layers[item.layer].objects[item.index])
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn huge_items_and_queries() {
+        let mut info = LevelInfo::default();
+        for (p1, p2) in [
+            (vec2(0.0, 0.0), vec2(1.0, 0.0)),
+            (vec2(-1e30, 0.0), vec2(1e30, 0.0)),
+            (vec2(0.0, 0.0), vec2(f32::INFINITY, 0.0)),
+        ] {
+            info.layers[0]
+                .surfaces
+                .push(fixtures::surface(p1, p2, "stone"));
+        }
+        let index = LevelIndex::new(&info);
+        let all = (0..3)
+            .map(|index| ItemRef { layer: 0, index })
+            .collect::<Vec<_>>();
+        let query = |aabb| index.query(aabb, |cell| &cell.surfaces);
+        assert_eq!(query(Aabb2::point(vec2(0.5, 0.0))), all);
+        assert_eq!(query(Aabb2::point(vec2(0.0, 1e6))), &all[1..]);
+        assert_eq!(
+            query(Aabb2::point(vec2(0.0, 0.0)).extend_uniform(1e30)),
+            all
+        );
+        assert_eq!(query(Aabb2::point(vec2(f32::NAN, 0.0))), all);
+    }
+}
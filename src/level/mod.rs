@@ -1,34 +1,68 @@
 use super::*;
 
+mod check;
+mod curve;
 mod draw;
+mod history;
+mod index;
 mod object;
 mod portal;
+mod prefab;
 mod progress;
+mod stats;
 mod surface;
+mod svg_export;
+mod svg_import;
 mod tile;
+mod version;
 
+pub use check::*;
+pub use curve::*;
+pub use history::*;
+pub use index::*;
 pub use object::*;
 pub use portal::*;
+pub use prefab::*;
+pub use stats::*;
 pub use surface::*;
+pub use svg_export::*;
+pub use svg_import::*;
 pub use tile::*;
+pub use version::*;
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct LevelLayer {
     pub name: String,
     pub gameplay: bool,
     pub surfaces: Vec<Surface>,
+    pub curves: Vec<CurveSurface>,
     pub tiles: Vec<Tile>,
     pub objects: Vec<Object>,
-    #[serde(default = "default_parallax")]
+    pub prefabs: Vec<PrefabInstance>,
     pub parallax: vec2<f32>,
-    #[serde(default)]
     pub reveal_radius: f32,
-    #[serde(default = "default_layer_color")]
     pub color: Rgba<f32>,
-    #[serde(default = "default_texture_scale")]
     pub texture_scale: f32,
 }
 
+impl LevelLayer {
+    pub fn new(name: String, gameplay: bool) -> Self {
+        Self {
+            name,
+            gameplay,
+            surfaces: vec![],
+            curves: vec![],
+            tiles: vec![],
+            objects: vec![],
+            prefabs: vec![],
+            parallax: default_parallax(),
+            reveal_radius: 0.0,
+            color: default_layer_color(),
+            texture_scale: default_texture_scale(),
+        }
+    }
+}
+
 fn default_layer_color() -> Rgba<f32> {
     Rgba::new(0.0, 0.0, 0.0, 0.0)
 }
@@ -48,6 +82,8 @@ pub struct LevelInfo {
     pub expected_path: Vec<Vec<vec2<f32>>>,
     pub layers: Vec<LevelLayer>,
     pub cannon: features::cannon::LevelInfo,
+    pub platform: features::platform::LevelInfo,
+    pub sections: features::sections::LevelInfo,
     pub portals: Vec<Portal>,
     pub max_progress_distance: f32,
 }
@@ -58,18 +94,10 @@ impl Default for LevelInfo {
             spawn_point: vec2::ZERO,
             finish_point: vec2::ZERO,
             expected_path: vec![],
-            layers: vec![LevelLayer {
-                name: "main".to_owned(),
-                gameplay: true,
-                surfaces: vec![],
-                tiles: vec![],
-                objects: vec![],
-                parallax: default_parallax(),
-                reveal_radius: 0.0,
-                color: default_layer_color(),
-                texture_scale: default_texture_scale(),
-            }],
+            layers: vec![LevelLayer::new("main".to_owned(), true)],
             cannon: default(),
+            platform: default(),
+            sections: default(),
             portals: vec![],
             max_progress_distance: 10.0,
         }
@@ -116,75 +144,64 @@ pub struct Level {
     path: std::path::PathBuf,
     #[deref]
     info: LevelInfo,
-    mesh: RefCell<Option<draw::LevelMesh>>,
-    history: Vec<LevelInfo>,
-    history_index: usize,
+    mesh: RefCell<draw::LevelMesh>,
+    index: RefCell<Option<LevelIndex>>,
+    prefabs_path: std::path::PathBuf,
+    prefabs: HashMap<String, LevelInfo>,
+    /// Prefabs that were made in the editor and are not written yet
+    unsaved_prefabs: HashSet<String>,
+    flattened: once_cell::unsync::OnceCell<LevelInfo>,
+    history: History,
     saved: bool,
 }
 
 impl Level {
-    pub async fn load(path: impl AsRef<std::path::Path>, create_if_not_exist: bool) -> Self {
+    pub async fn load(
+        path: impl AsRef<std::path::Path>,
+        prefabs_path: impl AsRef<std::path::Path>,
+        create_if_not_exist: bool,
+    ) -> anyhow::Result<Self> {
         let path = path.as_ref();
+        let prefabs_path = prefabs_path.as_ref();
         let mut saved = true;
-        let info: LevelInfo = match file::load_json(path).await {
+        let info: LevelInfo = match LevelInfo::load_file(path).await {
             Ok(info) => info,
-            Err(e) => {
-                if !path.exists() && create_if_not_exist {
-                    let info: LevelInfo = default();
-                    saved = false;
-                    info
-                } else {
-                    panic!("{e}");
-                }
+            Err(_) if !path.exists() && create_if_not_exist => {
+                saved = false;
+                default()
             }
+            Err(e) => return Err(e),
         };
-        Self {
+        let prefabs = load_prefabs(prefabs_path).await?;
+        Ok(Self {
             path: path.to_owned(),
             info,
-            mesh: RefCell::new(None),
-            history: vec![],
-            history_index: 0,
+            mesh: default(),
+            index: RefCell::new(None),
+            prefabs_path: prefabs_path.to_owned(),
+            prefabs,
+            unsaved_prefabs: default(),
+            flattened: default(),
+            history: default(),
             saved,
-        }
+        })
     }
     pub fn info(&self) -> &LevelInfo {
         &self.info
     }
-    pub fn modify(&mut self) -> &mut LevelInfo {
-        *self.mesh.get_mut() = None;
-        self.saved = false;
-        self.history.truncate(self.history_index);
-        self.history.push(self.info.clone());
-        self.history_index += 1;
-        &mut self.info
-    }
-    pub fn undo(&mut self) {
-        if self.history_index > 0 {
-            *self.mesh.get_mut() = None;
-            self.saved = false;
-            if self.history_index >= self.history.len() {
-                assert!(self.history_index == self.history.len());
-                self.history.push(self.info.clone());
-            }
-            self.history_index -= 1;
-            self.info = self.history[self.history_index].clone();
-        }
-    }
-    pub fn redo(&mut self) {
-        if self.history_index + 1 < self.history.len() {
-            *self.mesh.get_mut() = None;
-            self.saved = false;
-            self.history_index += 1;
-            self.info = self.history[self.history_index].clone();
-        }
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
     }
-    pub fn save(&mut self) {
-        if !mem::replace(&mut self.saved, true) {
-            serde_json::to_writer_pretty(
-                std::io::BufWriter::new(std::fs::File::create(&self.path).unwrap()),
-                self.info(),
-            )
-            .unwrap();
+    pub fn save(&mut self) -> anyhow::Result<()> {
+        if self.saved {
+            return Ok(());
         }
+        self.save_prefabs()?;
+        self.info()
+            .save_file(&self.path)
+            .context(format!("Failed to save {:?}", self.path))?;
+        self.save_history()?;
+        self.saved = true;
+        Ok(())
     }
 }
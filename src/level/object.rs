@@ -1,45 +1,60 @@
 use super::*;
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Object {
     pub type_name: String,
     pub pos: vec2<f32>,
 }
 
-impl Object {
-    // TODO: this should go into config I guess
-    pub fn fart_type(&self) -> Option<&str> {
-        Some(match self.type_name.as_str() {
-            "unicorn" => "rainbow",
-            "hot-pepper" => "fire",
-            "guitar" => "melody",
-            _ => return None,
-        })
-    }
+/// Where a guy respawns, saved by position so it survives the level being edited
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Checkpoint {
+    pub pos: vec2<f32>,
+    /// Fart type the guy had when reaching the checkpoint
+    pub fart_type: String,
 }
 
-pub fn load_objects_assets(
-    manager: &geng::asset::Manager,
-    path: &std::path::Path,
-) -> geng::asset::Future<HashMap<String, Texture>> {
-    let manager = manager.clone();
-    let path = path.to_owned();
-    async move {
-        let json = <String as geng::asset::Load>::load(&manager, &path.join("_list.json")).await?;
-        let list: Vec<String> = serde_json::from_str(&json).unwrap();
-        future::join_all(list.into_iter().map(|name| {
-            let manager = manager.clone();
-            let path = path.clone();
-            async move {
-                Ok((
-                    name.clone(),
-                    geng::asset::Load::load(&manager, &path.join(format!("{}.png", name))).await?,
-                ))
-            }
-        }))
-        .await
-        .into_iter()
-        .collect::<Result<_, anyhow::Error>>()
+/// What an object does to a guy that touches it, loaded from `objects/<name>.json`
+#[derive(Deserialize, Clone, Debug)]
+pub struct ObjectParams {
+    #[serde(default = "default_pickup_radius")]
+    pub pickup_radius: f32,
+    /// Fart type given to the guy
+    #[serde(default)]
+    pub fart_type: Option<String>,
+    /// Put the guy in a bubble
+    #[serde(default)]
+    pub bubble: bool,
+    /// Only works once for every guy
+    #[serde(default)]
+    pub one_shot: bool,
+    /// Becomes the respawn point of the guy
+    #[serde(default)]
+    pub checkpoint: bool,
+}
+
+fn default_pickup_radius() -> f32 {
+    1.5
+}
+
+pub struct ObjectAssets {
+    pub texture: Texture,
+    pub params: ObjectParams,
+}
+
+impl geng::asset::Load for ObjectAssets {
+    fn load(manager: &geng::asset::Manager, path: &std::path::Path) -> geng::asset::Future<Self> {
+        let manager = manager.clone();
+        let path = path.to_owned();
+        async move {
+            let texture = manager.load(path.with_extension("svg")).await?;
+            let params = file::load_json(path.with_extension("json"))
+                .await
+                .context(format!("Failed to load {path:?} params"))?;
+            Ok(Self { texture, params })
+        }
+        .boxed_local()
     }
-    .boxed_local()
+
+    const DEFAULT_EXT: Option<&'static str> = None;
 }
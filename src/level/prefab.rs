@@ -0,0 +1,214 @@
+use super::*;
+
+/// Prefabs inside prefabs are only expanded up to this depth, in case they include each other
+const MAX_PREFAB_DEPTH: usize = 8;
+
+/// Placed copy of a prefab, with its contents put into the layer it is in
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PrefabInstance {
+    pub name: String,
+    pub pos: vec2<f32>,
+    #[serde(default = "default_rot")]
+    pub rot: Angle<f32>,
+    #[serde(default = "default_scale")]
+    pub scale: f32,
+}
+
+fn default_rot() -> Angle<f32> {
+    Angle::ZERO
+}
+
+fn default_scale() -> f32 {
+    1.0
+}
+
+impl PrefabInstance {
+    pub fn new(name: String, pos: vec2<f32>) -> Self {
+        Self {
+            name,
+            pos,
+            rot: default_rot(),
+            scale: default_scale(),
+        }
+    }
+
+    pub fn transform(&self) -> mat3<f32> {
+        mat3::translate(self.pos) * mat3::rotate(self.rot) * mat3::scale_uniform(self.scale)
+    }
+}
+
+/// Prefabs are level fragments stored as `prefabs/<name>.json` in the assets
+pub async fn load_prefabs(
+    path: impl AsRef<std::path::Path>,
+) -> anyhow::Result<HashMap<String, LevelInfo>> {
+    let path = path.as_ref();
+    let Ok(list) = file::load_detect::<Vec<String>>(path.join("_list.ron")).await else {
+        return Ok(default());
+    };
+    future::try_join_all(list.into_iter().map(|name| {
+        let path = path.join(format!("{name}.json"));
+        async move {
+            let prefab = LevelInfo::load_file(&path)
+                .await
+                .context(format!("Failed to load prefab {path:?}"))?;
+            Ok::<_, anyhow::Error>((name, prefab))
+        }
+    }))
+    .await
+    .map(|prefabs| prefabs.into_iter().collect())
+}
+
+impl LevelInfo {
+    /// Adds a fragment to the layer, returns all the points that were added
+    pub fn insert_fragment(
+        &mut self,
+        layer: usize,
+        fragment: &LevelInfo,
+        transform: mat3<f32>,
+    ) -> Vec<vec2<f32>> {
+        let mut points = vec![];
+        let mut apply = |p: vec2<f32>| {
+            let p = (transform * p.extend(1.0)).into_2d();
+            points.push(p);
+            p
+        };
+        let rotate = |rot: Angle<f32>| {
+            (transform * vec2(1.0, 0.0).rotate(rot).extend(0.0))
+                .into_2d()
+                .arg()
+        };
+        let target = &mut self.layers[layer];
+        for fragment_layer in &fragment.layers {
+            for surface in &fragment_layer.surfaces {
+                target.surfaces.push(Surface {
+                    p1: apply(surface.p1),
+                    p2: apply(surface.p2),
+                    ..surface.clone()
+                });
+            }
+            for curve in &fragment_layer.curves {
+                target.curves.push(CurveSurface {
+                    points: curve.points.iter().map(|&p| apply(p)).collect(),
+                    ..curve.clone()
+                });
+            }
+            for tile in &fragment_layer.tiles {
+                target.tiles.push(tile.map_points(&mut apply));
+            }
+            for object in &fragment_layer.objects {
+                target.objects.push(Object {
+                    pos: apply(object.pos),
+                    ..object.clone()
+                });
+            }
+            for instance in &fragment_layer.prefabs {
+                let scale = (transform * vec2(1.0, 0.0).extend(0.0)).into_2d().len();
+                target.prefabs.push(PrefabInstance {
+                    name: instance.name.clone(),
+                    pos: apply(instance.pos),
+                    rot: rotate(instance.rot),
+                    scale: instance.scale * scale,
+                });
+            }
+        }
+        for cannon in &fragment.cannon.cannons {
+            self.cannon.cannons.push(features::cannon::Cannon {
+                pos: apply(cannon.pos),
+                rot: rotate(cannon.rot),
+            });
+        }
+        let first_portal = self.portals.len();
+        for portal in &fragment.portals {
+            self.portals.push(Portal {
+                pos: apply(portal.pos),
+                dest: portal.dest.map(|dest| first_portal + dest),
+                ..portal.clone()
+            });
+        }
+        points
+    }
+
+    fn needs_flattening(&self) -> bool {
+        self.layers
+            .iter()
+            .any(|layer| !layer.prefabs.is_empty() || !layer.curves.is_empty())
+    }
+
+    /// Level with all prefab instances replaced by their contents
+    /// and curves expanded into surfaces
+    pub fn flatten(&self, prefabs: &HashMap<String, LevelInfo>) -> LevelInfo {
+        self.flatten_impl(prefabs, 0)
+    }
+
+    fn flatten_impl(&self, prefabs: &HashMap<String, LevelInfo>, depth: usize) -> LevelInfo {
+        let mut result = self.clone();
+        for layer in &mut result.layers {
+            layer.prefabs.clear();
+            for curve in mem::take(&mut layer.curves) {
+                layer.surfaces.extend(curve.surfaces());
+            }
+        }
+        for (layer_index, layer) in self.layers.iter().enumerate() {
+            for instance in &layer.prefabs {
+                let Some(prefab) = prefabs.get(&instance.name) else {
+                    log::warn!("Prefab {:?} not found", instance.name);
+                    continue;
+                };
+                if depth >= MAX_PREFAB_DEPTH {
+                    log::warn!("Prefab {:?} is nested too deep", instance.name);
+                    continue;
+                }
+                let prefab = prefab.flatten_impl(prefabs, depth + 1);
+                result.insert_fragment(layer_index, &prefab, instance.transform());
+            }
+        }
+        result
+    }
+}
+
+impl Level {
+    pub fn prefabs(&self) -> &HashMap<String, LevelInfo> {
+        &self.prefabs
+    }
+
+    /// Level as it is played, see [LevelInfo::flatten]
+    pub fn flattened(&self) -> &LevelInfo {
+        if !self.info.needs_flattening() {
+            return &self.info;
+        }
+        self.flattened
+            .get_or_init(|| self.info.flatten(&self.prefabs))
+    }
+
+    /// Writes the prefabs changed since the last save, and the list of all of them,
+    /// prefabs that are gone because of an undo are deleted
+    pub(super) fn save_prefabs(&mut self) -> anyhow::Result<()> {
+        if self.unsaved_prefabs.is_empty() {
+            return Ok(());
+        }
+        std::fs::create_dir_all(&self.prefabs_path)
+            .context(format!("Failed to create {:?}", self.prefabs_path))?;
+        for name in &self.unsaved_prefabs {
+            let path = self.prefabs_path.join(format!("{name}.json"));
+            match self.prefabs.get(name) {
+                Some(prefab) => prefab
+                    .save_file(&path)
+                    .context(format!("Failed to save prefab {name:?}"))?,
+                None if path.exists() => std::fs::remove_file(&path)
+                    .context(format!("Failed to delete prefab {name:?}"))?,
+                None => {}
+            }
+        }
+        let mut names: Vec<&String> = self.prefabs.keys().collect();
+        names.sort();
+        let mut list = "[\n".to_owned();
+        for name in names {
+            list += &format!("    {},\n", serde_json::to_string(name).unwrap());
+        }
+        list += "]\n";
+        let list_path = self.prefabs_path.join("_list.ron");
+        std::fs::write(&list_path, list).context(format!("Failed to write {list_path:?}"))?;
+        self.unsaved_prefabs.clear();
+        Ok(())
+    }
+}
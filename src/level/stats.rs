@@ -0,0 +1,382 @@
+use super::*;
+
+use std::collections::BTreeMap;
+
+/// Upper bounds of the surface length histogram buckets, the last bucket has no bound
+const SURFACE_LENGTH_BUCKETS: [f32; 7] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0, 16.0];
+
+#[derive(Serialize, Debug)]
+pub struct LayerStats {
+    pub name: String,
+    pub gameplay: bool,
+    pub surfaces: usize,
+    pub tiles: usize,
+    pub objects: usize,
+}
+
+impl LayerStats {
+    pub fn is_empty(&self) -> bool {
+        self.surfaces == 0 && self.tiles == 0 && self.objects == 0
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct LengthBucket {
+    /// None for the last bucket
+    pub max: Option<f32>,
+    pub count: usize,
+}
+
+#[derive(Serialize, Debug)]
+pub struct LengthStats {
+    pub total: f32,
+    pub min: f32,
+    pub median: f32,
+    pub mean: f32,
+    pub max: f32,
+    pub histogram: Vec<LengthBucket>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PortalStats {
+    pub count: usize,
+    /// Portals that lead somewhere
+    pub linked: usize,
+    /// Pairs of portals leading to each other
+    pub two_way: usize,
+    /// Portals that lead nowhere and that nothing leads to
+    pub isolated: usize,
+    /// Portals leading to indices out of range
+    pub dangling: usize,
+    /// Groups of portals connected to each other in any direction
+    pub components: usize,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CannonStats {
+    pub pos: vec2<f32>,
+    pub aim_degrees: f32,
+}
+
+#[derive(Serialize, Debug)]
+pub struct LevelStats {
+    pub layers: Vec<LayerStats>,
+    pub surface_types: BTreeMap<String, usize>,
+    pub tile_types: BTreeMap<String, usize>,
+    pub object_types: BTreeMap<String, usize>,
+    /// Min and max corners, none for an empty level
+    pub bounds: Option<[vec2<f32>; 2]>,
+    pub expected_path_length: f32,
+    pub surface_lengths: Option<LengthStats>,
+    pub portals: PortalStats,
+    pub cannons: Vec<CannonStats>,
+}
+
+fn count_types<'a>(names: impl Iterator<Item = &'a String>) -> BTreeMap<String, usize> {
+    let mut result = BTreeMap::new();
+    for name in names {
+        *result.entry(name.clone()).or_default() += 1;
+    }
+    result
+}
+
+fn length_stats(mut lengths: Vec<f32>) -> Option<LengthStats> {
+    if lengths.is_empty() {
+        return None;
+    }
+    lengths.sort_by_key(|&len| r32(len));
+    let total: f32 = lengths.iter().sum();
+    let mut histogram: Vec<LengthBucket> = SURFACE_LENGTH_BUCKETS
+        .iter()
+        .map(|&max| Some(max))
+        .chain([None])
+        .map(|max| LengthBucket { max, count: 0 })
+        .collect();
+    for &len in &lengths {
+        let bucket = SURFACE_LENGTH_BUCKETS
+            .iter()
+            .position(|&max| len < max)
+            .unwrap_or(SURFACE_LENGTH_BUCKETS.len());
+        histogram[bucket].count += 1;
+    }
+    Some(LengthStats {
+        total,
+        min: lengths[0],
+        median: lengths[lengths.len() / 2],
+        mean: total / lengths.len() as f32,
+        max: *lengths.last().unwrap(),
+        histogram,
+    })
+}
+
+fn portal_stats(portals: &[Portal]) -> PortalStats {
+    let n = portals.len();
+    let dest = |index: usize| portals[index].dest.filter(|&dest| dest < n);
+    // Union find over the links
+    let mut parent: Vec<usize> = (0..n).collect();
+    fn root(parent: &mut [usize], mut index: usize) -> usize {
+        while parent[index] != index {
+            parent[index] = parent[parent[index]];
+            index = parent[index];
+        }
+        index
+    }
+    let mut incoming = vec![0; n];
+    for index in 0..n {
+        if let Some(dest) = dest(index) {
+            incoming[dest] += 1;
+            let (a, b) = (root(&mut parent, index), root(&mut parent, dest));
+            parent[a] = b;
+        }
+    }
+    PortalStats {
+        count: n,
+        linked: (0..n).filter(|&index| dest(index).is_some()).count(),
+        two_way: (0..n)
+            .filter(|&index| {
+                dest(index).map_or(false, |other| index < other && dest(other) == Some(index))
+            })
+            .count(),
+        isolated: (0..n)
+            .filter(|&index| dest(index).is_none() && incoming[index] == 0)
+            .count(),
+        dangling: portals
+            .iter()
+            .filter(|portal| portal.dest.map_or(false, |dest| dest >= n))
+            .count(),
+        components: (0..n)
+            .filter(|&index| root(&mut parent, index) == index)
+            .count(),
+    }
+}
+
+impl LevelInfo {
+    pub fn stats(&self) -> LevelStats {
+        let points = itertools::chain![
+            self.all_surfaces()
+                .flat_map(|surface| [surface.p1, surface.p2]),
+            self.all_tiles().flat_map(|tile| tile.points()),
+            self.all_objects().map(|object| object.pos),
+            self.portals.iter().map(|portal| portal.pos),
+            self.cannon.cannons.iter().map(|cannon| cannon.pos),
+        ];
+        LevelStats {
+            layers: self
+                .layers
+                .iter()
+                .map(|layer| LayerStats {
+                    name: layer.name.clone(),
+                    gameplay: layer.gameplay,
+                    surfaces: layer.surfaces.len(),
+                    tiles: layer.tiles.len(),
+                    objects: layer.objects.len(),
+                })
+                .collect(),
+            surface_types: count_types(self.all_surfaces().map(|surface| &surface.type_name)),
+            tile_types: count_types(self.all_tiles().map(|tile| &tile.type_name)),
+            object_types: count_types(self.all_objects().map(|object| &object.type_name)),
+            bounds: Aabb2::points_bounding_box(points).map(|aabb| [aabb.min, aabb.max]),
+            expected_path_length: self
+                .expected_path
+                .iter()
+                .flat_map(|path| path.windows(2))
+                .map(|window| (window[1] - window[0]).len())
+                .sum(),
+            surface_lengths: length_stats(
+                self.all_surfaces()
+                    .map(|surface| (surface.p2 - surface.p1).len())
+                    .collect(),
+            ),
+            portals: portal_stats(&self.portals),
+            cannons: self
+                .cannon
+                .cannons
+                .iter()
+                .map(|cannon| CannonStats {
+                    pos: cannon.pos,
+                    aim_degrees: cannon.rot.as_radians().to_degrees(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl std::fmt::Display for LevelStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Layers:")?;
+        for layer in &self.layers {
+            let kind = if layer.gameplay {
+                "gameplay"
+            } else {
+                "decoration"
+            };
+            if layer.is_empty() {
+                writeln!(f, "  {:?} ({kind}): EMPTY", layer.name)?;
+            } else {
+                writeln!(
+                    f,
+                    "  {:?} ({kind}): {} surfaces, {} tiles, {} objects",
+                    layer.name, layer.surfaces, layer.tiles, layer.objects,
+                )?;
+            }
+        }
+        for (title, types) in [
+            ("Surface types", &self.surface_types),
+            ("Tile types", &self.tile_types),
+            ("Object types", &self.object_types),
+        ] {
+            writeln!(f, "{title}:")?;
+            for (name, count) in types {
+                writeln!(f, "  {name}: {count}")?;
+            }
+        }
+        match self.bounds {
+            Some([min, max]) => writeln!(
+                f,
+                "Bounds: ({:.2}, {:.2}) to ({:.2}, {:.2}), {:.2} x {:.2}",
+                min.x,
+                min.y,
+                max.x,
+                max.y,
+                max.x - min.x,
+                max.y - min.y,
+            )?,
+            None => writeln!(f, "Bounds: empty level")?,
+        }
+        writeln!(f, "Expected path length: {:.2}", self.expected_path_length)?;
+        if let Some(lengths) = &self.surface_lengths {
+            writeln!(
+                f,
+                "Surface lengths: total {:.2}, min {:.2}, median {:.2}, mean {:.2}, max {:.2}",
+                lengths.total, lengths.min, lengths.median, lengths.mean, lengths.max,
+            )?;
+            let mut min = 0.0;
+            for bucket in &lengths.histogram {
+                match bucket.max {
+                    Some(max) => {
+                        writeln!(f, "  {min:>5} - {max:<5} {}", bucket.count)?;
+                        min = max;
+                    }
+                    None => writeln!(f, "  {min:>5} +       {}", bucket.count)?,
+                }
+            }
+        }
+        let portals = &self.portals;
+        writeln!(
+            f,
+            "Portals: {} total, {} linked, {} two way pairs, {} isolated, {} dangling, {} groups",
+            portals.count,
+            portals.linked,
+            portals.two_way,
+            portals.isolated,
+            portals.dangling,
+            portals.components,
+        )?;
+        writeln!(f, "Cannons: {}", self.cannons.len())?;
+        for cannon in &self.cannons {
+            writeln!(
+                f,
+                "  at ({:.2}, {:.2}) aiming {:.0} degrees",
+                cannon.pos.x, cannon.pos.y, cannon.aim_degrees,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Prints stats of the level as it is played, with prefabs and curves expanded
+pub async fn print_level_stats(
+    assets_path: impl AsRef<std::path::Path>,
+    level_path: impl AsRef<std::path::Path>,
+    json: bool,
+) -> anyhow::Result<()> {
+    let level = Level::load(level_path, assets_path.as_ref().join("prefabs"), false).await?;
+    let stats = level.flattened().stats();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+    } else {
+        print!("{stats}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn portals(dests: &[Option<usize>]) -> Vec<Portal> {
+        dests
+            .iter()
+            .enumerate()
+            .map(|(index, &dest)| Portal {
+                pos: vec2(index as f32, 0.0),
+                dest,
+                color: Rgba::WHITE,
+            })
+            .collect()
+    }
+
+    /// count, linked, two_way, isolated, dangling, components
+    fn summary(stats: PortalStats) -> [usize; 6] {
+        [
+            stats.count,
+            stats.linked,
+            stats.two_way,
+            stats.isolated,
+            stats.dangling,
+            stats.components,
+        ]
+    }
+
+    #[test]
+    fn no_portals() {
+        assert_eq!(summary(portal_stats(&[])), [0; 6]);
+    }
+
+    #[test]
+    fn portal_links() {
+        let portals = portals(&[
+            Some(1),
+            Some(0),
+            Some(3),
+            Some(4),
+            None,
+            None,
+            Some(99),
+            Some(7),
+        ]);
+        // {0, 1}, {2, 3, 4}, {5}, {6}, {7}
+        assert_eq!(summary(portal_stats(&portals)), [8, 5, 1, 2, 1, 5]);
+    }
+
+    #[test]
+    fn long_chain_is_one_component() {
+        let dests: Vec<Option<usize>> = (1..20).map(Some).chain([None]).collect();
+        assert_eq!(
+            summary(portal_stats(&portals(&dests))),
+            [20, 19, 0, 0, 0, 1]
+        );
+        // Linking backwards joins the same way
+        let dests: Vec<Option<usize>> = [None].into_iter().chain((0..19).map(Some)).collect();
+        assert_eq!(
+            summary(portal_stats(&portals(&dests))),
+            [20, 19, 0, 0, 0, 1]
+        );
+    }
+
+    #[test]
+    fn separate_cycles() {
+        let portals = portals(&[Some(1), Some(2), Some(0), Some(4), Some(3)]);
+        assert_eq!(summary(portal_stats(&portals)), [5, 5, 1, 0, 0, 2]);
+    }
+
+    #[test]
+    fn surface_lengths() {
+        assert!(length_stats(vec![]).is_none());
+        let stats = length_stats(vec![3.0, 0.1, 20.0, 1.0]).unwrap();
+        assert_eq!((stats.min, stats.median, stats.max), (0.1, 3.0, 20.0));
+        assert!((stats.total - 24.1).abs() < 1e-5);
+        let counts: Vec<usize> = stats.histogram.iter().map(|bucket| bucket.count).collect();
+        assert_eq!(counts, [1, 0, 0, 1, 1, 0, 0, 1]);
+    }
+}
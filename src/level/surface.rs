@@ -4,7 +4,6 @@ use super::*;
 pub struct Surface {
     pub p1: vec2<f32>,
     pub p2: vec2<f32>,
-    #[serde(default)]
     pub flow: f32,
     pub type_name: String,
 }
@@ -28,7 +27,7 @@ impl Surface {
     }
 }
 
-#[derive(geng::asset::Load, Deserialize, Debug)]
+#[derive(geng::asset::Load, Deserialize, Debug, Clone)]
 #[load(serde = "json")]
 pub struct SurfaceParams {
     #[serde(default)]
@@ -57,6 +56,12 @@ pub struct SurfaceParams {
     pub fallthrough_speed: Option<f32>,
     #[serde(default = "default_snow_falloff")]
     pub snow_falloff: f32,
+    /// How fast snow sticks to a guy rolling on this surface
+    #[serde(default)]
+    pub snow_accumulation: f32,
+    /// Splash when a guy falls in
+    #[serde(default)]
+    pub splash: bool,
     #[serde(default)]
     pub svg: bool,
     #[serde(default)]
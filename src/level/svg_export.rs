@@ -0,0 +1,240 @@
+use super::*;
+
+/// Empty space around the level on the map
+const MAP_PADDING: f32 = 5.0;
+/// Pixmaps bigger than this are likely to fail to allocate
+const MAX_MAP_SIZE: u32 = 16384;
+
+/// Same color for the same type in every map
+fn type_color(type_name: &str) -> Rgba<f32> {
+    let hash = type_name.bytes().fold(2166136261u32, |hash, byte| {
+        (hash ^ byte as u32).wrapping_mul(16777619)
+    });
+    Hsva::new((hash % 360) as f32 / 360.0, 0.7, 0.8, 1.0).into()
+}
+
+fn hex(color: Rgba<f32>) -> String {
+    let channel = |x: f32| (x.clamp(0.0, 1.0) * 255.0).round() as u8;
+    format!(
+        "#{:02x}{:02x}{:02x}",
+        channel(color.r),
+        channel(color.g),
+        channel(color.b),
+    )
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Svg y axis goes down
+fn coords(p: vec2<f32>) -> String {
+    format!("{:.3},{:.3}", p.x, -p.y)
+}
+
+fn path_data(rings: impl IntoIterator<Item = impl AsRef<[vec2<f32>]>>, closed: bool) -> String {
+    let mut d = String::new();
+    for ring in rings {
+        for (index, &p) in ring.as_ref().iter().enumerate() {
+            d += if index == 0 { "M " } else { " L " };
+            d += &coords(p);
+        }
+        if closed {
+            d += " Z ";
+        }
+    }
+    d
+}
+
+impl LevelInfo {
+    /// Map of the flattened level with `pixels_per_unit` resolution,
+    /// `paths` are drawn on top of it, like guy positions from replays
+    pub fn to_svg(&self, pixels_per_unit: f32, paths: &[Vec<vec2<f32>>]) -> String {
+        let points = itertools::chain![
+            self.layers.iter().flat_map(|layer| {
+                itertools::chain![
+                    layer
+                        .surfaces
+                        .iter()
+                        .flat_map(|surface| [surface.p1, surface.p2]),
+                    layer.tiles.iter().flat_map(|tile| tile.points()),
+                    layer.objects.iter().map(|object| object.pos),
+                ]
+            }),
+            self.portals.iter().map(|portal| portal.pos),
+            self.cannon.cannons.iter().map(|cannon| cannon.pos),
+            self.expected_path.iter().flatten().copied(),
+            [self.spawn_point, self.finish_point],
+        ];
+        let bounds = Aabb2::points_bounding_box(points)
+            .unwrap()
+            .extend_uniform(MAP_PADDING);
+        let size = bounds.size() * pixels_per_unit;
+
+        let mut svg = String::new();
+        let mut line = |s: String| {
+            svg += &s;
+            svg.push('\n');
+        };
+        line(format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:inkscape="http://www.inkscape.org/namespaces/inkscape" width="{:.0}" height="{:.0}" viewBox="{:.3} {:.3} {:.3} {:.3}">"#,
+            size.x,
+            size.y,
+            bounds.min.x,
+            -bounds.max.y,
+            bounds.width(),
+            bounds.height(),
+        ));
+        line(format!(
+            r#"<rect x="{:.3}" y="{:.3}" width="{:.3}" height="{:.3}" fill="white"/>"#,
+            bounds.min.x,
+            -bounds.max.y,
+            bounds.width(),
+            bounds.height(),
+        ));
+        line(r##"<defs><marker id="arrow" viewBox="0 0 10 10" refX="10" refY="5" markerWidth="6" markerHeight="6" orient="auto"><path d="M 0 0 L 10 5 L 0 10 Z" fill="#404040"/></marker></defs>"##.to_owned());
+
+        for layer in &self.layers {
+            let opacity = if layer.gameplay { 1.0 } else { 0.4 };
+            line(format!(
+                r#"<g inkscape:groupmode="layer" inkscape:label="{}" opacity="{opacity}">"#,
+                escape(&layer.name),
+            ));
+            for tile in &layer.tiles {
+                line(format!(
+                    r#"<path d="{}" fill="{}" fill-opacity="0.5" fill-rule="evenodd"/>"#,
+                    path_data(std::iter::once(&tile.vertices).chain(&tile.holes), true),
+                    hex(type_color(&tile.type_name)),
+                ));
+            }
+            for surface in &layer.surfaces {
+                line(format!(
+                    r#"<path d="{}" fill="none" stroke="{}" stroke-width="0.2" stroke-linecap="round"/>"#,
+                    path_data([[surface.p1, surface.p2]], false),
+                    hex(type_color(&surface.type_name)),
+                ));
+            }
+            for object in &layer.objects {
+                let pos = vec2(object.pos.x, -object.pos.y);
+                line(format!(
+                    r#"<circle cx="{:.3}" cy="{:.3}" r="0.5" fill="{}"/>"#,
+                    pos.x,
+                    pos.y,
+                    hex(type_color(&object.type_name)),
+                ));
+                line(format!(
+                    r#"<text x="{:.3}" y="{:.3}" font-size="0.8" text-anchor="middle">{}</text>"#,
+                    pos.x,
+                    pos.y - 0.8,
+                    escape(&object.type_name),
+                ));
+            }
+            line("</g>".to_owned());
+        }
+
+        line(r#"<g inkscape:groupmode="layer" inkscape:label="markers">"#.to_owned());
+        for path in &self.expected_path {
+            line(format!(
+                r##"<path d="{}" fill="none" stroke="#808080" stroke-width="0.1" stroke-dasharray="0.5 0.5"/>"##,
+                path_data([path], false),
+            ));
+        }
+        for portal in &self.portals {
+            let color = hex(portal.color);
+            line(format!(
+                r#"<circle cx="{:.3}" cy="{:.3}" r="0.8" fill="none" stroke="{color}" stroke-width="0.2"/>"#,
+                portal.pos.x, -portal.pos.y,
+            ));
+            if let Some(dest) = portal.dest.and_then(|dest| self.portals.get(dest)) {
+                line(format!(
+                    r#"<path d="{}" fill="none" stroke="{color}" stroke-width="0.1" stroke-dasharray="0.3 0.3" marker-end="url(#arrow)"/>"#,
+                    path_data([[portal.pos, dest.pos]], false),
+                ));
+            }
+        }
+        for cannon in &self.cannon.cannons {
+            let aim = cannon.pos + vec2(2.0, 0.0).rotate(cannon.rot);
+            line(format!(
+                r##"<circle cx="{:.3}" cy="{:.3}" r="0.6" fill="#404040"/>"##,
+                cannon.pos.x, -cannon.pos.y,
+            ));
+            line(format!(
+                r##"<path d="{}" fill="none" stroke="#404040" stroke-width="0.2" marker-end="url(#arrow)"/>"##,
+                path_data([[cannon.pos, aim]], false),
+            ));
+        }
+        for (pos, color, text) in [
+            (self.spawn_point, "#00c000", "spawn"),
+            (self.finish_point, "#c00000", "finish"),
+        ] {
+            line(format!(
+                r#"<circle cx="{:.3}" cy="{:.3}" r="0.7" fill="{color}"/>"#,
+                pos.x, -pos.y,
+            ));
+            line(format!(
+                r#"<text x="{:.3}" y="{:.3}" font-size="1" text-anchor="middle">{text}</text>"#,
+                pos.x,
+                -pos.y - 1.0,
+            ));
+        }
+        line("</g>".to_owned());
+
+        if !paths.is_empty() {
+            line(r#"<g inkscape:groupmode="layer" inkscape:label="paths">"#.to_owned());
+            for (index, path) in paths.iter().enumerate() {
+                line(format!(
+                    r#"<path d="{}" fill="none" stroke="{}" stroke-width="0.1" stroke-opacity="0.7"/>"#,
+                    path_data([path], false),
+                    hex(type_color(&index.to_string())),
+                ));
+            }
+            line("</g>".to_owned());
+        }
+        line("</svg>".to_owned());
+        svg
+    }
+}
+
+/// Writes the map of the level as svg and as png next to it
+pub async fn export_map(
+    level_path: impl AsRef<std::path::Path>,
+    assets_path: impl AsRef<std::path::Path>,
+    replays_path: Option<impl AsRef<std::path::Path>>,
+    output_path: impl AsRef<std::path::Path>,
+    pixels_per_unit: f32,
+) -> anyhow::Result<()> {
+    let output_path = output_path.as_ref();
+    let level = Level::load(level_path, assets_path.as_ref().join("prefabs"), false).await?;
+    let paths: Vec<Vec<vec2<f32>>> = match replays_path {
+        Some(path) => replay::load_histories(path)
+            .await
+            .context("Failed to load replays")?
+            .iter()
+            .map(|history| history.positions().collect())
+            .collect(),
+        None => vec![],
+    };
+    let map = level.flattened().to_svg(pixels_per_unit, &paths);
+    std::fs::write(output_path, &map)?;
+
+    // Text is only there in the svg, since no fonts are loaded for rendering
+    let xml = roxmltree::Document::parse(&map)?;
+    let tree = svg::Tree::from_xmltree(&xml, &resvg::usvg::Options::default())?;
+    let size = tree.size.to_screen_size();
+    if size.width() > MAX_MAP_SIZE || size.height() > MAX_MAP_SIZE {
+        anyhow::bail!(
+            "Map would be {}x{} pixels, use a smaller scale",
+            size.width(),
+            size.height(),
+        );
+    }
+    let png_path = output_path.with_extension("png");
+    svg::render_image(&tree, None)
+        .save(&png_path)
+        .context(format!("Failed to save {png_path:?}"))?;
+    log::info!("Map saved to {output_path:?} and {png_path:?}");
+    Ok(())
+}
@@ -0,0 +1,416 @@
+use super::*;
+
+const INKSCAPE: &str = "http://www.inkscape.org/namespaces/inkscape";
+
+/// Names of the types that imported items may have
+pub struct ImportTypes {
+    pub surfaces: Vec<String>,
+    pub tiles: Vec<String>,
+    pub objects: Vec<String>,
+}
+
+impl ImportTypes {
+    pub async fn load(assets_path: impl AsRef<std::path::Path>) -> anyhow::Result<Self> {
+        let assets_path = assets_path.as_ref();
+        let list = |dir: &str| {
+            let path = assets_path.join(dir).join("_list.ron");
+            async move {
+                let mut list: Vec<String> = file::load_detect(&path)
+                    .await
+                    .context(format!("Failed to load {path:?}"))?;
+                list.sort();
+                Ok::<_, anyhow::Error>(list)
+            }
+        };
+        Ok(Self {
+            surfaces: list("surfaces").await?,
+            tiles: list("tiles").await?,
+            objects: list("objects").await?,
+        })
+    }
+}
+
+/// Continuous part of a path
+#[derive(Debug)]
+struct SubPath {
+    /// Start point and then three points for every cubic segment, same as in [CurveSurface]
+    points: Vec<vec2<f32>>,
+    /// Whether any of the segments is not a straight line
+    curved: bool,
+    closed: bool,
+}
+
+impl SubPath {
+    fn new(start: vec2<f32>) -> Self {
+        Self {
+            points: vec![start],
+            curved: false,
+            closed: false,
+        }
+    }
+
+    fn line_to(&mut self, p: vec2<f32>) {
+        let a = *self.points.last().unwrap();
+        self.points
+            .extend([a + (p - a) / 3.0, a + (p - a) * 2.0 / 3.0, p]);
+    }
+
+    fn cubic_to(&mut self, c1: vec2<f32>, c2: vec2<f32>, p: vec2<f32>) {
+        self.points.extend([c1, c2, p]);
+        self.curved = true;
+    }
+
+    fn anchors(&self) -> impl Iterator<Item = vec2<f32>> + '_ {
+        self.points.iter().copied().step_by(3)
+    }
+
+    /// Polyline approximating the path, without repeating the start point when closed
+    fn tessellate(&self) -> Vec<vec2<f32>> {
+        let mut points = if self.curved {
+            CurveSurface {
+                points: self.points.clone(),
+                tolerance: default_tolerance(),
+                flow: 0.0,
+                type_name: String::new(),
+            }
+            .tessellate()
+        } else {
+            self.anchors().collect()
+        };
+        if self.closed && points.len() > 1 && points.first() == points.last() {
+            points.pop();
+        }
+        points
+    }
+}
+
+/// Words of the Inkscape label, or the id without the number Inkscape adds to it
+fn label_words<'a>(node: roxmltree::Node<'a, '_>) -> (Vec<&'a str>, bool) {
+    match node.attribute((INKSCAPE, "label")) {
+        Some(label) => (label.split_whitespace().collect(), true),
+        None => {
+            let id = node.attribute("id").unwrap_or("");
+            let id = id.trim_end_matches(|c: char| c.is_ascii_digit() || c == '-' || c == '_');
+            (vec![id], false)
+        }
+    }
+}
+
+struct SvgImporter<'a, 'input> {
+    types: &'a ImportTypes,
+    scale: f32,
+    /// Svg elements by their id, since the resolved tree does not keep Inkscape attributes
+    elements: HashMap<&'a str, roxmltree::Node<'a, 'input>>,
+    level: LevelInfo,
+    /// Portals by their name, with the name of the portal they lead to
+    portals: Vec<(String, Option<String>)>,
+    errors: Vec<String>,
+}
+
+impl<'a, 'input> SvgImporter<'a, 'input> {
+    /// Level layer for the Inkscape layer that the element is in
+    fn layer(&mut self, element: roxmltree::Node) -> anyhow::Result<usize> {
+        let Some(group) = element
+            .ancestors()
+            .skip(1)
+            .find(|node| node.attribute((INKSCAPE, "groupmode")) == Some("layer"))
+        else {
+            return Ok(0);
+        };
+        let name = group
+            .attribute((INKSCAPE, "label"))
+            .or(group.attribute("id"))
+            .unwrap_or("layer");
+        if let Some(index) = self
+            .level
+            .layers
+            .iter()
+            .position(|layer| layer.name == name)
+        {
+            return Ok(index);
+        }
+        let gameplay = match group.attribute("gameplay") {
+            Some(gameplay) => gameplay
+                .parse()
+                .context(format!("Failed to parse gameplay of layer {name:?}"))?,
+            None => true,
+        };
+        self.level
+            .layers
+            .push(LevelLayer::new(name.to_owned(), gameplay));
+        Ok(self.level.layers.len() - 1)
+    }
+
+    /// Type given by the label, if it is one of the known ones
+    fn type_name(
+        &mut self,
+        id: &str,
+        name: &str,
+        labelled: bool,
+        types: &[String],
+        kind: &str,
+    ) -> Option<String> {
+        if types.iter().any(|type_name| type_name == name) {
+            return Some(name.to_owned());
+        }
+        self.errors.push(if labelled {
+            format!("{id:?} is labelled {name:?}, which is not a known {kind} type")
+        } else {
+            format!("{id:?} has no label with its {kind} type")
+        });
+        None
+    }
+
+    /// Splits the path into parts in level coordinates
+    fn sub_paths(&self, path: &svg::Path, transform: svg::Transform) -> Vec<SubPath> {
+        let point = |x: f64, y: f64| {
+            let (x, y) = transform.apply(x, y);
+            // Svg y axis goes down
+            vec2(x as f32, -y as f32) * self.scale
+        };
+        let mut result: Vec<SubPath> = vec![];
+        let mut current = vec2::ZERO;
+        for segment in path.data.segments() {
+            if !matches!(segment, svg::PathSegment::MoveTo { .. })
+                && result.last().map_or(true, |path| path.closed)
+            {
+                // Drawing after closing a path continues from its start
+                result.push(SubPath::new(current));
+            }
+            match segment {
+                svg::PathSegment::MoveTo { x, y } => {
+                    current = point(x, y);
+                    result.push(SubPath::new(current));
+                }
+                svg::PathSegment::LineTo { x, y } => {
+                    current = point(x, y);
+                    result.last_mut().unwrap().line_to(current);
+                }
+                svg::PathSegment::CurveTo {
+                    x1,
+                    y1,
+                    x2,
+                    y2,
+                    x,
+                    y,
+                } => {
+                    current = point(x, y);
+                    result
+                        .last_mut()
+                        .unwrap()
+                        .cubic_to(point(x1, y1), point(x2, y2), current);
+                }
+                svg::PathSegment::ClosePath => {
+                    let path = result.last_mut().unwrap();
+                    let start = path.points[0];
+                    if current != start {
+                        path.line_to(start);
+                    }
+                    path.closed = true;
+                    current = start;
+                }
+            }
+        }
+        result
+    }
+
+    fn import_path(&mut self, path: &svg::Path, transform: svg::Transform) -> anyhow::Result<()> {
+        if path.visibility != svg::Visibility::Visible {
+            return Ok(());
+        }
+        let Some(&element) = self.elements.get(path.id.as_str()) else {
+            self.errors
+                .push("Found a shape with no id or label, can't tell what it is".to_owned());
+            return Ok(());
+        };
+        let layer = self.layer(element)?;
+        let paths = self.sub_paths(path, transform);
+        let (words, labelled) = label_words(element);
+        let name = words.first().copied().unwrap_or("");
+        let is_point = matches!(name, "spawn" | "finish" | "portal" | "cannon")
+            || self.types.objects.iter().any(|object| object == name);
+        if is_point {
+            // Center of a circle, whatever transform it has
+            let Some(bounds) = Aabb2::points_bounding_box(
+                paths.iter().flat_map(|path| path.points.iter().copied()),
+            ) else {
+                return Ok(());
+            };
+            let color = match path.fill.as_ref().map(|fill| &fill.paint) {
+                Some(svg::Paint::Color(color)) => Rgba::new(
+                    color.red as f32 / 255.0,
+                    color.green as f32 / 255.0,
+                    color.blue as f32 / 255.0,
+                    1.0,
+                ),
+                _ => random_hue(),
+            };
+            self.import_point(&words, bounds.center(), color, layer);
+        } else if path.fill.is_some() {
+            // Combined paths are tiles with holes
+            let mut rings = paths
+                .iter()
+                .map(|path| path.tessellate())
+                .filter(|ring| ring.len() >= 3);
+            let Some(vertices) = rings.next() else {
+                return Ok(());
+            };
+            let types = self.types;
+            let tiles = &types.tiles;
+            let Some(type_name) = self.type_name(&path.id, name, labelled, tiles, "tile") else {
+                return Ok(());
+            };
+            self.level.layers[layer].tiles.push(Tile {
+                holes: rings.collect(),
+                ..Tile::new(vertices, type_name)
+            });
+        } else {
+            let types = self.types;
+            let surfaces = &types.surfaces;
+            let Some(type_name) = self.type_name(&path.id, name, labelled, surfaces, "surface")
+            else {
+                return Ok(());
+            };
+            self.import_surfaces(paths, type_name, layer);
+        }
+        Ok(())
+    }
+
+    fn import_point(&mut self, words: &[&str], pos: vec2<f32>, color: Rgba<f32>, layer: usize) {
+        match words[0] {
+            "spawn" => self.level.spawn_point = pos,
+            "finish" => self.level.finish_point = pos,
+            "portal" => {
+                self.level.portals.push(Portal {
+                    pos,
+                    dest: None,
+                    color,
+                });
+                let name = words.get(1).map_or(String::new(), |name| name.to_string());
+                let dest = words.get(2).map(|dest| dest.to_string());
+                self.portals.push((name, dest));
+            }
+            "cannon" => {
+                let rot = words.get(1).and_then(|rot| rot.parse().ok()).unwrap_or(0.0);
+                self.level.cannon.cannons.push(features::cannon::Cannon {
+                    pos,
+                    rot: Angle::from_degrees(rot),
+                });
+            }
+            name => {
+                self.level.layers[layer].objects.push(Object {
+                    type_name: name.to_owned(),
+                    pos,
+                });
+            }
+        }
+    }
+
+    fn import_surfaces(&mut self, paths: Vec<SubPath>, type_name: String, layer: usize) {
+        let target = &mut self.level.layers[layer];
+        for path in paths {
+            if path.curved {
+                target.curves.push(CurveSurface {
+                    points: path.points,
+                    tolerance: default_tolerance(),
+                    flow: 0.0,
+                    type_name: type_name.clone(),
+                });
+            } else {
+                let anchors: Vec<vec2<f32>> = path.anchors().collect();
+                for window in anchors.windows(2) {
+                    if window[0] != window[1] {
+                        target.surfaces.push(Surface {
+                            p1: window[0],
+                            p2: window[1],
+                            flow: 0.0,
+                            type_name: type_name.clone(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    fn connect_portals(&mut self) {
+        for (index, (_, dest)) in self.portals.iter().enumerate() {
+            let Some(dest) = dest else { continue };
+            let target = self.portals.iter().position(|(name, _)| name == dest);
+            if target.is_none() {
+                self.errors.push(format!("Portal {dest:?} not found"));
+            }
+            self.level.portals[index].dest = target;
+        }
+    }
+}
+
+impl LevelInfo {
+    /// Level drawn in Inkscape, with `scale` world units per svg unit.
+    ///
+    /// Inkscape layers become level layers, gameplay unless they have `gameplay="false"`.
+    /// Filled shapes are tiles and the rest of the paths are surfaces,
+    /// with the type given by the first word of the label.
+    /// Shapes labelled `spawn`, `finish`, `cannon <degrees>`, `portal <name> <dest name>`
+    /// or with an object type are placed as those at their center.
+    /// Items without a known type are reported as errors.
+    pub fn import_svg(raw_xml: &str, types: &ImportTypes, scale: f32) -> anyhow::Result<Self> {
+        use svg::NodeExt;
+        let xml = roxmltree::Document::parse(raw_xml)?;
+        // Resolves styles, transforms, arcs and basic shapes into plain paths
+        let tree = svg::Tree::from_xmltree(&xml, &resvg::usvg::Options::default())?;
+        let mut importer = SvgImporter {
+            types,
+            scale,
+            elements: xml
+                .descendants()
+                .filter_map(|node| Some((node.attribute("id")?, node)))
+                .collect(),
+            level: default(),
+            portals: vec![],
+            errors: vec![],
+        };
+        for node in tree.root.descendants() {
+            if let svg::NodeKind::Path(ref path) = *node.borrow() {
+                importer.import_path(path, node.abs_transform())?;
+            }
+        }
+        importer.connect_portals();
+        if !importer.errors.is_empty() {
+            anyhow::bail!("{}", importer.errors.join("\n"));
+        }
+        let mut level = importer.level;
+        let main = &level.layers[0];
+        if level.layers.len() > 1
+            && main.surfaces.is_empty()
+            && main.curves.is_empty()
+            && main.tiles.is_empty()
+            && main.objects.is_empty()
+        {
+            level.layers.remove(0);
+        }
+        Ok(level)
+    }
+}
+
+/// Saves the level drawn in Inkscape to a new level file
+pub async fn import_svg(
+    svg_path: impl AsRef<std::path::Path>,
+    assets_path: impl AsRef<std::path::Path>,
+    level_path: impl AsRef<std::path::Path>,
+    scale: f32,
+) -> anyhow::Result<()> {
+    let svg_path = svg_path.as_ref();
+    let level_path = level_path.as_ref();
+    if level_path.exists() {
+        anyhow::bail!("{level_path:?} already exists, import into a new level file");
+    }
+    let types = ImportTypes::load(assets_path).await?;
+    let raw_xml = file::load_string(svg_path)
+        .await
+        .context(format!("Failed to load {svg_path:?}"))?;
+    let level = LevelInfo::import_svg(&raw_xml, &types, scale)
+        .context(format!("Failed to import {svg_path:?}"))?;
+    level.save_file(level_path)?;
+    log::info!("Imported {} layers into {level_path:?}", level.layers.len());
+    Ok(())
+}
@@ -1,14 +1,59 @@
 use super::*;
 
+/// Polygon region, possibly concave and with holes
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Tile {
-    pub vertices: [vec2<f32>; 3],
-    #[serde(default = "zero_vec")]
+    pub vertices: Vec<vec2<f32>>,
+    pub holes: Vec<Vec<vec2<f32>>>,
     pub flow: vec2<f32>,
     pub type_name: String,
 }
 
-#[derive(geng::asset::Load, Deserialize)]
+impl Tile {
+    pub fn new(vertices: Vec<vec2<f32>>, type_name: String) -> Self {
+        Self {
+            vertices,
+            holes: vec![],
+            flow: vec2::ZERO,
+            type_name,
+        }
+    }
+
+    /// All the vertices, including the ones of the holes
+    pub fn points(&self) -> impl Iterator<Item = vec2<f32>> + '_ {
+        itertools::chain![&self.vertices, self.holes.iter().flatten()].copied()
+    }
+
+    pub fn points_mut(&mut self) -> impl Iterator<Item = &mut vec2<f32>> {
+        itertools::chain![&mut self.vertices, self.holes.iter_mut().flatten()]
+    }
+
+    pub fn map_points(&self, mut f: impl FnMut(vec2<f32>) -> vec2<f32>) -> Self {
+        let mut result = self.clone();
+        for p in result.points_mut() {
+            *p = f(*p);
+        }
+        result
+    }
+
+    pub fn triangles(&self) -> Vec<[vec2<f32>; 3]> {
+        triangulate_with_holes(&self.vertices, &self.holes)
+    }
+
+    /// Whether the segment is a side of the polygon or of a hole, and not a diagonal
+    pub fn is_side(&self, a: vec2<f32>, b: vec2<f32>) -> bool {
+        std::iter::once(&self.vertices)
+            .chain(&self.holes)
+            .any(|ring| {
+                (0..ring.len()).any(|i| {
+                    let (p, q) = (ring[i], ring[(i + 1) % ring.len()]);
+                    (p, q) == (a, b) || (p, q) == (b, a)
+                })
+            })
+    }
+}
+
+#[derive(geng::asset::Load, Deserialize, Clone)]
 #[load(serde = "json")]
 pub struct TileParams {
     #[serde(default)]
@@ -26,6 +71,12 @@ pub struct TileParams {
     #[serde(default = "zero_vec")]
     pub additional_force: vec2<f32>,
     pub time_scale: Option<f32>,
+    /// Replaces the usual downwards gravity inside the tile
+    #[serde(default)]
+    pub gravity: Option<vec2<f32>>,
+    /// Fart type used while the guy's butt is inside the tile
+    #[serde(default)]
+    pub fart_type: Option<String>,
     #[serde(default = "default_draw_times")]
     pub draw_times: usize,
     #[serde(default)]
@@ -0,0 +1,233 @@
+use super::*;
+
+use serde_json::{json, Map, Value};
+
+/// Version of the level files written by this build
+pub const LEVEL_VERSION: u64 = 1;
+
+/// Step at index `i` upgrades a level file from version `i` to `i + 1`
+const MIGRATIONS: [fn(&mut Map<String, Value>) -> anyhow::Result<()>; LEVEL_VERSION as usize] =
+    [migrate_v0];
+
+/// Level file as it is stored, files from before versioning have no version
+#[derive(Serialize)]
+struct LevelFile<'a> {
+    version: u64,
+    #[serde(flatten)]
+    info: &'a LevelInfo,
+}
+
+fn insert_missing(object: &mut Map<String, Value>, key: &str, value: impl Serialize) {
+    if !object.contains_key(key) {
+        object.insert(key.to_owned(), serde_json::to_value(value).unwrap());
+    }
+}
+
+fn objects_mut<'a>(
+    object: &'a mut Map<String, Value>,
+    key: &str,
+) -> anyhow::Result<impl Iterator<Item = &'a mut Map<String, Value>>> {
+    let array = object
+        .get_mut(key)
+        .and_then(Value::as_array_mut)
+        .context(format!("{key:?} is not an array"))?;
+    Ok(array.iter_mut().filter_map(Value::as_object_mut))
+}
+
+/// Fields added later used to be filled in by serde defaults
+fn migrate_v0(level: &mut Map<String, Value>) -> anyhow::Result<()> {
+    insert_missing(level, "platform", features::platform::LevelInfo::default());
+    insert_missing(level, "sections", features::sections::LevelInfo::default());
+    for layer in objects_mut(level, "layers")? {
+        insert_missing(layer, "curves", json!([]));
+        insert_missing(layer, "prefabs", json!([]));
+        insert_missing(layer, "parallax", default_parallax());
+        insert_missing(layer, "reveal_radius", 0.0);
+        insert_missing(layer, "color", default_layer_color());
+        insert_missing(layer, "texture_scale", default_texture_scale());
+        for surface in objects_mut(layer, "surfaces")? {
+            insert_missing(surface, "flow", 0.0);
+        }
+        for tile in objects_mut(layer, "tiles")? {
+            insert_missing(tile, "holes", json!([]));
+            insert_missing(tile, "flow", zero_vec());
+        }
+    }
+    Ok(())
+}
+
+impl LevelInfo {
+    /// Reads a level file of any version, returns the level and the version it had
+    pub fn from_versioned_json(json: &str) -> anyhow::Result<(Self, u64)> {
+        let mut value: Value = serde_json::from_str(json)?;
+        let level = value
+            .as_object_mut()
+            .context("Level file is not an object")?;
+        let version = match level.remove("version") {
+            Some(version) => version.as_u64().context("Failed to parse level version")?,
+            None => 0,
+        };
+        if version > LEVEL_VERSION {
+            anyhow::bail!(
+                "Level version is {version}, but only up to {LEVEL_VERSION} is supported, update the game"
+            );
+        }
+        for (from, migrate) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+            migrate(level).context(format!("Failed to upgrade level from version {from}"))?;
+        }
+        let info = serde_json::from_value(value).context("Failed to parse level")?;
+        Ok((info, version))
+    }
+
+    pub async fn load_file(path: impl AsRef<std::path::Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let json = file::load_string(path)
+            .await
+            .context(format!("Failed to load {path:?}"))?;
+        let (info, _version) =
+            Self::from_versioned_json(&json).context(format!("Failed to load {path:?}"))?;
+        Ok(info)
+    }
+
+    /// Same format as [LevelInfo::save_file], for levels stored elsewhere
+    pub fn to_versioned_json(&self) -> String {
+        serde_json::to_string(&LevelFile {
+            version: LEVEL_VERSION,
+            info: self,
+        })
+        .unwrap()
+    }
+
+    /// Saves with the current version
+    pub fn save_file(&self, path: impl AsRef<std::path::Path>) -> anyhow::Result<()> {
+        serde_json::to_writer_pretty(
+            std::io::BufWriter::new(std::fs::File::create(path)?),
+            &LevelFile {
+                version: LEVEL_VERSION,
+                info: self,
+            },
+        )?;
+        Ok(())
+    }
+}
+
+/// Rewrites level files with the current version
+pub async fn upgrade_level_files(paths: &[std::path::PathBuf]) -> anyhow::Result<()> {
+    for path in paths {
+        let json = file::load_string(path)
+            .await
+            .context(format!("Failed to load {path:?}"))?;
+        let (info, version) =
+            LevelInfo::from_versioned_json(&json).context(format!("Failed to load {path:?}"))?;
+        if version == LEVEL_VERSION {
+            log::info!("{path:?} is up to date");
+            continue;
+        }
+        info.save_file(path)?;
+        log::info!("Upgraded {path:?} from version {version} to {LEVEL_VERSION}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn level() -> LevelInfo {
+        let mut level = LevelInfo::default();
+        let layer = &mut level.layers[0];
+        layer
+            .surfaces
+            .push(fixtures::surface(vec2(0.0, 0.0), vec2(1.0, 0.0), "grass"));
+        layer.tiles.push(Tile::new(
+            vec![vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(0.0, 1.0)],
+            "dirt".to_owned(),
+        ));
+        level.cannon.cannons.push(features::cannon::Cannon {
+            pos: vec2(2.0, 3.0),
+            rot: Angle::ZERO,
+        });
+        level
+    }
+
+    /// The same level the way it was stored before versioning
+    fn v0_json(level: &LevelInfo) -> Value {
+        let mut value = serde_json::to_value(level).unwrap();
+        let object = value.as_object_mut().unwrap();
+        object.remove("platform");
+        object.remove("sections");
+        for layer in objects_mut(object, "layers").unwrap() {
+            for key in [
+                "curves",
+                "prefabs",
+                "parallax",
+                "reveal_radius",
+                "color",
+                "texture_scale",
+            ] {
+                layer.remove(key);
+            }
+            for surface in objects_mut(layer, "surfaces").unwrap() {
+                surface.remove("flow");
+            }
+            for tile in objects_mut(layer, "tiles").unwrap() {
+                tile.remove("holes");
+                tile.remove("flow");
+            }
+        }
+        value
+    }
+
+    #[test]
+    fn migrate_v0_fills_defaults() {
+        let level = level();
+        let (migrated, version) =
+            LevelInfo::from_versioned_json(&v0_json(&level).to_string()).unwrap();
+        assert_eq!(version, 0);
+        assert_eq!(migrated.cannon.cannons.len(), 1);
+        assert_eq!(
+            serde_json::to_value(&migrated).unwrap(),
+            serde_json::to_value(&level).unwrap(),
+        );
+    }
+
+    #[test]
+    fn migrate_v0_keeps_existing_fields() {
+        let level = level();
+        let mut json = v0_json(&level);
+        json["layers"][0]["reveal_radius"] = json!(3.0);
+        json["layers"][0]["surfaces"][0]["flow"] = json!(2.0);
+        let (migrated, _) = LevelInfo::from_versioned_json(&json.to_string()).unwrap();
+        assert_eq!(migrated.layers[0].reveal_radius, 3.0);
+        assert_eq!(migrated.layers[0].surfaces[0].flow, 2.0);
+    }
+
+    #[test]
+    fn migrate_v0_rejects_malformed_layers() {
+        let mut json = v0_json(&level());
+        json["layers"] = json!({});
+        assert!(LevelInfo::from_versioned_json(&json.to_string()).is_err());
+    }
+
+    #[test]
+    fn current_version_round_trip() {
+        let level = level();
+        let path = fixtures::temp_dir("version").with_extension("json");
+        level.save_file(&path).unwrap();
+        let json = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let (loaded, version) = LevelInfo::from_versioned_json(&json).unwrap();
+        assert_eq!(version, LEVEL_VERSION);
+        assert_eq!(
+            serde_json::to_value(&loaded).unwrap(),
+            serde_json::to_value(&level).unwrap(),
+        );
+    }
+
+    #[test]
+    fn future_version_is_rejected() {
+        let mut json = serde_json::to_value(level()).unwrap();
+        json["version"] = json!(LEVEL_VERSION + 1);
+        assert!(LevelInfo::from_versioned_json(&json.to_string()).is_err());
+    }
+}
@@ -2,7 +2,10 @@ use super::*;
 
 impl Game {
     pub fn update_my_guy_input(&mut self) {
-        let my_guy = match self.my_guy.map(|id| self.guys.get_mut(&id).unwrap()) {
+        let my_guy = match self
+            .my_guy
+            .map(|id| self.simulation.guys.get_mut(&id).unwrap())
+        {
             Some(guy) => guy,
             None => return,
         };
@@ -104,481 +107,181 @@ impl Game {
         }
     }
 
-    pub fn update_guys(&mut self, delta_time: f32) {
-        let assets = self.assets.get();
-        let is_colliding = |guy: &Guy, surface_type: &str| -> bool {
-            for surface in self.level.gameplay_surfaces() {
-                let v = surface.vector_from(guy.state.pos);
-                let penetration = guy.radius() - v.len();
-                if penetration > EPS && surface.type_name == surface_type {
-                    return true;
-                }
-            }
-            false
-        };
-        for guy in &mut self.guys {
-            if guy.paused {
-                continue;
-            }
-            let mut time_scale = 1.0;
-            for tile in self.level.gameplay_tiles() {
-                if !Aabb2::points_bounding_box(tile.vertices)
-                    .unwrap()
-                    .extend_uniform(self.config.guy_radius)
-                    .contains(guy.state.pos)
-                {
-                    continue;
-                }
-                let params = &assets.tiles[&tile.type_name].params;
-                if let Some(this_time_scale) = params.time_scale {
-                    let percentage = circle_triangle_intersect_percentage(
-                        guy.state.pos,
-                        self.config.guy_radius,
-                        tile.vertices,
-                    );
-                    time_scale *= this_time_scale.powf(percentage);
-                }
-            }
-            let delta_time = delta_time * time_scale;
-
-            let sfx_speed =
-                (self.time_scale as f64 * time_scale as f64).powf(self.config.sfx_time_scale_power);
-            if self.my_guy == Some(guy.id) {
-                self.music.set_speed(sfx_speed);
-            }
-
-            let prev_state = guy.state.clone();
-            let was_colliding_water = is_colliding(guy, "water");
-            if (guy.state.pos - self.level.finish_point).len() < 1.5 {
-                guy.progress.finished = true;
-            }
-            {
-                let mut new_fart_type = None;
-                for object in self.level.gameplay_objects() {
-                    if (guy.state.pos - object.pos).len() < 1.5 {
-                        if let Some(fart_type) = object.fart_type() {
-                            new_fart_type = Some(fart_type.to_owned());
-                        }
-                    }
-                }
-                if let Some(new_fart_type) = new_fart_type {
-                    if new_fart_type != guy.state.fart_type {
-                        guy.state.fart_type = new_fart_type;
-                        guy.state.fart_pressure = self.config.max_fart_pressure;
-                    }
-                }
-            }
+    pub fn sfx_speed(&self, pos: vec2<f32>) -> f64 {
+        (self.time_scale as f64 * self.simulation.time_scale_at(pos) as f64)
+            .powf(self.config.sfx_time_scale_power)
+    }
 
-            // Bubble
-            if let Some(time) = &mut guy.state.bubble_timer {
-                *time -= delta_time;
-                if *time < 0.0 {
-                    guy.state.bubble_timer = None;
-                }
-                guy.state.vel += (guy.state.vel.normalize_or_zero()
-                    * self.config.bubble_target_speed
-                    - guy.state.vel)
-                    .clamp_len(..=self.config.bubble_acceleration * delta_time);
-            }
-            for object in self.level.gameplay_objects() {
-                if (guy.state.pos - object.pos).len() < 1.0 && object.type_name == "bubbler" {
-                    guy.state.bubble_timer = Some(self.config.bubble_time);
-                }
-            }
+    fn sfx_volume(&self, pos: vec2<f32>) -> f64 {
+        (self.sound.volume * (1.0 - (pos - self.camera.center).len() / self.camera.fov))
+            .clamp(0.0, 1.0) as f64
+    }
 
-            if let std::ops::ControlFlow::Break(()) = features::cannon::update_guy(
-                &mut guy.state,
-                delta_time,
-                &self.level,
-                &self.config,
-                &assets,
-                &self.sound,
-                &mut self.farticles,
+    fn start_long_fart_sfx(&mut self, guy_id: Id, fart_type: &str, volume: f64, speed: f64) {
+        let assets = self.assets.get();
+        if let Some(sound) = &assets.farts[fart_type].long_sfx {
+            let mut sfx = sound.effect();
+            sfx.set_volume(volume);
+            sfx.set_speed(speed);
+            sfx.play();
+            if let Some(mut sfx) = self.long_fart_sfx.insert(
+                guy_id,
+                LongFartSfx {
+                    type_name: fart_type.to_owned(),
+                    finish_time: None,
+                    sfx,
+                },
             ) {
-                return;
-            }
-
-            if guy.progress.finished {
-                guy.state.fart_pressure = 0.0;
-                guy.state.rot -= Angle::from_radians(delta_time);
-                guy.state.pos = self.level.finish_point
-                    + (guy.state.pos - self.level.finish_point)
-                        .normalize_or_zero()
-                        .rotate(Angle::from_radians(delta_time))
-                        * 1.0;
-                continue;
-            }
-
-            guy.state.w += Angle::from_radians(
-                (guy.input.roll_direction().clamp(-1.0, 1.0) * self.config.angular_acceleration
-                    / guy.mass(&self.config)
-                    * delta_time)
-                    .clamp(
-                        -(guy.state.w.as_radians() + self.config.max_angular_speed).max(0.0),
-                        (self.config.max_angular_speed - guy.state.w.as_radians()).max(0.0),
-                    ),
-            );
-
-            if guy.state.bubble_timer.is_none() {
-                guy.state.vel.y -= self.config.gravity * delta_time;
+                sfx.sfx.stop();
             }
+        }
+    }
 
-            let mut in_water = false;
-            let butt = guy.state.pos + vec2(0.0, -guy.state.radius * 0.9).rotate(guy.state.rot);
-            for tile in self.level.gameplay_tiles() {
-                if !Aabb2::points_bounding_box(tile.vertices)
-                    .unwrap()
-                    .extend_uniform(self.config.guy_radius)
-                    .contains(guy.state.pos)
-                {
-                    continue;
-                }
-                let percentage = circle_triangle_intersect_percentage(
+    pub fn update_long_fart_sfx(&mut self) {
+        let guys: Vec<(Id, vec2<f32>, bool, String)> = self
+            .simulation
+            .guys
+            .iter()
+            .filter(|guy| !guy.paused)
+            .map(|guy| {
+                (
+                    guy.id,
                     guy.state.pos,
-                    self.config.guy_radius,
-                    tile.vertices,
-                );
-                let relative_vel = guy.state.vel - tile.flow;
-                let flow_direction = tile.flow.normalize_or_zero();
-                let relative_vel_along_flow = vec2::dot(flow_direction, relative_vel);
-                let params = &assets.tiles[&tile.type_name].params;
-                let force_along_flow =
-                    -flow_direction * relative_vel_along_flow * params.friction_along_flow;
-                let friction_force = -relative_vel * params.friction;
-                guy.state.vel += (force_along_flow + params.additional_force + friction_force)
-                    * delta_time
-                    / guy.mass(&self.config)
-                    * percentage;
-                guy.state.w -= guy.state.w * params.friction * delta_time / guy.mass(&self.config)
-                    * percentage;
-                // TODO inertia?
-            }
-            'tile_loop: for tile in self.level.gameplay_tiles() {
-                for i in 0..3 {
-                    let p1 = tile.vertices[i];
-                    let p2 = tile.vertices[(i + 1) % 3];
-                    if vec2::skew(p2 - p1, butt - p1) < 0.0 {
-                        continue 'tile_loop;
-                    }
-                }
-                if tile.type_name == "water" {
-                    in_water = true;
-                }
-            }
-
-            let fart_type = if in_water {
-                "bubble"
-            } else {
-                guy.state.fart_type.as_str()
-            };
-            let fart_assets = &assets.farts[fart_type];
-
-            let could_fart = guy.state.fart_pressure >= self.config.fart_pressure_released;
-            if self.config.fart_continued_force == 0.0 {
-                guy.state.long_farting = false;
-            }
-            if guy.input.force_fart {
-                if guy.state.long_farting {
-                    guy.state.fart_pressure -=
-                        delta_time * self.config.fart_continuation_pressure_speed;
-                    if guy.state.fart_pressure < 0.0 {
-                        guy.state.fart_pressure = 0.0;
-                        guy.state.long_farting = false;
-                    }
-                } else {
-                    guy.state.fart_pressure +=
-                        delta_time * self.config.force_fart_pressure_multiplier;
-                }
-            } else {
-                guy.state.long_farting = false;
-                guy.state.fart_pressure += delta_time;
-            };
-
-            if !guy.state.long_farting {
-                if let Some(sfx) = self.long_fart_sfx.get_mut(&guy.id) {
+                    guy.state.long_farting,
+                    self.simulation.fart_type(guy).to_owned(),
+                )
+            })
+            .collect();
+        for (id, pos, long_farting, fart_type) in guys {
+            let volume = self.sfx_volume(pos);
+            let speed = self.sfx_speed(pos);
+            if !long_farting {
+                if let Some(sfx) = self.long_fart_sfx.get_mut(&id) {
                     if sfx.finish_time.is_none() {
                         sfx.finish_time = Some(self.real_time);
                     }
                     let fadeout = (self.real_time - sfx.finish_time.unwrap()) / 0.2;
                     if fadeout >= 1.0 {
                         sfx.sfx.stop();
-                        self.long_fart_sfx.remove(&guy.id);
+                        self.long_fart_sfx.remove(&id);
                     } else {
-                        sfx.sfx.set_volume(
-                            (self.sound.volume
-                                * (1.0
-                                    - (guy.state.pos - self.camera.center).len() / self.camera.fov))
-                                .clamp(0.0, 1.0) as f64
-                                * (1.0 - fadeout) as f64,
-                        );
+                        sfx.sfx.set_volume(volume * (1.0 - fadeout) as f64);
                     }
                 }
-            } else if let Some(sfx) = self.long_fart_sfx.get_mut(&guy.id) {
-                let volume = (self.sound.volume
-                    * (1.0 - (guy.state.pos - self.camera.center).len() / self.camera.fov))
-                    .clamp(0.0, 1.0) as f64;
+            } else if let Some(sfx) = self.long_fart_sfx.get_mut(&id) {
                 if fart_type != sfx.type_name {
-                    // TODO: this is copypasta
-                    if let Some(sound) = &fart_assets.long_sfx {
-                        let mut sfx = sound.effect();
-                        sfx.set_volume(volume);
-                        sfx.set_speed(sfx_speed);
-                        sfx.play();
-                        if let Some(mut sfx) = self.long_fart_sfx.insert(
-                            guy.id,
-                            LongFartSfx {
-                                type_name: fart_type.to_owned(),
-                                finish_time: None,
-                                sfx,
-                            },
-                        ) {
-                            sfx.sfx.stop();
-                        }
-                    }
+                    self.start_long_fart_sfx(id, &fart_type, volume, speed);
                 } else {
                     sfx.sfx.set_volume(volume);
-                    sfx.sfx.set_speed(sfx_speed);
+                    sfx.sfx.set_speed(speed);
                 }
             } else {
                 log::warn!("No sfx for long fart?");
             }
+        }
+    }
 
-            if guy.state.long_farting {
-                guy.animation.next_farticle_time -= delta_time;
-                while guy.animation.next_farticle_time < 0.0 {
-                    guy.animation.next_farticle_time +=
-                        1.0 / fart_assets.config.long_fart_farticles_per_second;
-                    self.farticles.spawn_single(
-                        fart_assets,
-                        butt,
-                        guy.state.vel
-                            - vec2(0.0, fart_assets.config.long_fart_farticle_speed)
-                                .rotate(guy.state.rot),
-                    );
-                }
-                guy.state.vel += vec2(0.0, self.config.fart_continued_force * delta_time)
-                    .rotate(guy.state.rot)
-                    / guy.mass(&self.config);
-            } else if (guy.state.fart_pressure >= self.config.fart_pressure_released
-                && guy.input.force_fart)
-                || guy.state.fart_pressure >= self.config.max_fart_pressure
-            {
-                guy.state.bubble_timer = None;
-                guy.state.fart_pressure -= self.config.fart_pressure_released;
-                guy.state.long_farting = true;
-                if let Some(sound) = &fart_assets.long_sfx {
-                    // TODO: copypasta??
-                    let mut sfx = sound.effect();
-                    sfx.set_volume(0.0);
-                    sfx.set_speed(sfx_speed);
-                    sfx.play();
-                    if let Some(mut sfx) = self.long_fart_sfx.insert(
-                        guy.id,
-                        LongFartSfx {
-                            type_name: fart_type.to_owned(),
-                            finish_time: None,
-                            sfx,
-                        },
-                    ) {
-                        sfx.sfx.stop();
-                    }
-                }
-                self.farticles.spawn(fart_assets, butt, guy.state.vel);
-                guy.state.vel += vec2(0.0, self.config.fart_strength).rotate(guy.state.rot)
-                    / guy.mass(&self.config);
-                let mut effect = fart_assets.sfx.choose(&mut thread_rng()).unwrap().effect();
-                effect.set_volume(
-                    (self.sound.volume
-                        * (1.0 - (guy.state.pos - self.camera.center).len() / self.camera.fov))
-                        .clamp(0.0, 1.0) as f64,
-                );
-                effect.set_speed(sfx_speed);
-                effect.play();
-            } else if !could_fart && guy.state.fart_pressure >= self.config.fart_pressure_released {
-                // Growling stomach recharge
-                if Some(guy.id) == self.my_guy {
-                    let mut effect = assets.sfx.fart_recharge.effect();
-                    effect.set_volume(self.sound.volume as f64 * 0.5);
+    pub fn handle_simulation_events(&mut self) {
+        for event in self.simulation.take_events() {
+            match event {
+                simulation::Event::Fart {
+                    guy_id,
+                    fart_type,
+                    pos,
+                    vel,
+                } => {
+                    let speed = self.sfx_speed(pos);
+                    self.start_long_fart_sfx(guy_id, &fart_type, 0.0, speed);
+                    let assets = self.assets.get();
+                    let fart_assets = &assets.farts[&fart_type];
+                    self.farticles.spawn(fart_assets, pos, vel);
+                    let mut effect = fart_assets.sfx.choose(&mut thread_rng()).unwrap().effect();
+                    effect.set_volume(self.sfx_volume(pos));
+                    effect.set_speed(speed);
                     effect.play();
                 }
-                guy.animation.growl_progress = Some(0.0);
-            }
-
-            if let Some(growl) = &mut guy.animation.growl_progress {
-                *growl += delta_time / self.config.growl_time;
-                if *growl >= 1.0 {
-                    guy.animation.growl_progress = None;
-                }
-            }
-
-            guy.state.vel += guy.state.stick_force / guy.mass(&self.config) * delta_time;
-            guy.state.stick_force -= guy
-                .state
-                .stick_force
-                .clamp_len(..=self.config.stick_force_fadeout_speed * delta_time);
-
-            guy.state.pos += guy.state.vel * delta_time;
-            guy.state.rot += guy.state.w * delta_time;
-
-            struct Collision<'a> {
-                penetration: f32,
-                normal: vec2<f32>,
-                surface: &'a Surface,
-                assets: &'a SurfaceAssets,
-            }
-
-            let mut collision_to_resolve = None;
-            let mut was_colliding_water = was_colliding_water;
-            for surface in self.level.gameplay_surfaces() {
-                let from_surface = -surface.vector_from(guy.state.pos);
-                let penetration = guy.radius() - from_surface.len();
-                if penetration > 0.0 {
-                    let surface_assets = &assets.surfaces[&surface.type_name];
-
-                    if surface.type_name == "water" && !was_colliding_water {
-                        was_colliding_water = true;
-                        if vec2::dot(from_surface, guy.state.vel).abs() > 0.5 {
-                            self.sound
-                                .play(&assets.sfx.water_splash, 1.0, guy.state.pos);
-                            let fart_type = "bubble";
-                            let fart_assets = &assets.farts[fart_type];
-                            // TODO
-                            for _ in 0..30 {
-                                self.farticles.push(
-                                    fart_assets,
-                                    farticle::Farticle {
-                                        size: 0.6 * fart_assets.config.farticle_size,
-                                        pos: guy.state.pos - from_surface
-                                            + vec2(
-                                                thread_rng()
-                                                    .gen_range(-guy.radius()..=guy.radius()),
-                                                0.0,
-                                            ),
-                                        vel: {
-                                            let mut v =
-                                                vec2(0.0, thread_rng().gen_range(0.0..=1.0))
-                                                    .rotate(Angle::from_radians(
-                                                        thread_rng().gen_range(
-                                                            -f32::PI / 4.0..=f32::PI / 4.0,
-                                                        ),
-                                                    ));
-                                            v.y *= 0.3;
-                                            v * 2.0
-                                        },
-                                        rot: Angle::from_radians(
-                                            thread_rng().gen_range(0.0..2.0 * f32::PI),
-                                        ),
-                                        w: Angle::from_radians(thread_rng().gen_range(
-                                            -fart_assets.config.farticle_w
-                                                ..=fart_assets.config.farticle_w,
-                                        )),
-                                        colors: fart_assets.config.colors.get(),
-                                        t: 0.5,
-                                    },
-                                );
-                            }
-                        }
-                    }
-
-                    if surface_assets.params.non_collidable {
+                simulation::Event::LongFart {
+                    guy_id,
+                    fart_type,
+                    pos,
+                    vel,
+                    rot,
+                    delta_time,
+                } => {
+                    let assets = self.assets.get();
+                    let fart_assets = &assets.farts[&fart_type];
+                    let Some(guy) = self.simulation.guys.get_mut(&guy_id) else {
                         continue;
+                    };
+                    guy.animation.next_farticle_time -= delta_time;
+                    while guy.animation.next_farticle_time < 0.0 {
+                        guy.animation.next_farticle_time +=
+                            1.0 / fart_assets.config.long_fart_farticles_per_second;
+                        self.farticles.spawn_single(
+                            fart_assets,
+                            pos,
+                            vel - vec2(0.0, fart_assets.config.long_fart_farticle_speed)
+                                .rotate(rot),
+                        );
                     }
-                    let normal = from_surface.normalize_or_zero();
-                    let normal_vel = vec2::dot(normal, guy.state.vel);
-                    if normal_vel < -EPS
-                        && normal_vel > -surface_assets.params.fallthrough_speed.unwrap_or(1e9)
-                        && vec2::skew(surface.p2 - surface.p1, normal) > 0.0
-                        && penetration < self.config.max_penetration
-                    {
-                        let collision = Collision {
-                            penetration,
-                            surface,
-                            normal,
-                            assets: surface_assets,
-                        };
-                        collision_to_resolve = std::cmp::max_by_key(
-                            collision_to_resolve,
-                            Some(collision),
-                            |collision| match collision {
-                                Some(collision) => (
-                                    r32(collision.penetration),
-                                    r32(vec2::skew(
-                                        (collision.surface.p2 - collision.surface.p1)
-                                            .normalize_or_zero(),
-                                        collision.normal,
-                                    )),
+                }
+                simulation::Event::Recharge { guy_id } => {
+                    if Some(guy_id) == self.my_guy {
+                        let mut effect = self.assets.get().sfx.fart_recharge.effect();
+                        effect.set_volume(self.sound.volume as f64 * 0.5);
+                        effect.play();
+                    }
+                }
+                simulation::Event::Splash { pos, radius } => {
+                    let assets = self.assets.get();
+                    self.sound.play(&assets.sfx.water_splash, 1.0, pos);
+                    let fart_assets = &assets.farts["bubble"];
+                    // TODO
+                    for _ in 0..30 {
+                        self.farticles.push(
+                            fart_assets,
+                            farticle::Farticle {
+                                size: 0.6 * fart_assets.config.farticle_size,
+                                pos: pos + vec2(thread_rng().gen_range(-radius..=radius), 0.0),
+                                vel: {
+                                    let mut v = vec2(0.0, thread_rng().gen_range(0.0..=1.0))
+                                        .rotate(Angle::from_radians(
+                                            thread_rng().gen_range(-f32::PI / 4.0..=f32::PI / 4.0),
+                                        ));
+                                    v.y *= 0.3;
+                                    v * 2.0
+                                },
+                                rot: Angle::from_radians(
+                                    thread_rng().gen_range(0.0..2.0 * f32::PI),
                                 ),
-                                None => (r32(-1.0), r32(0.0)),
+                                w: Angle::from_radians(thread_rng().gen_range(
+                                    -fart_assets.config.farticle_w..=fart_assets.config.farticle_w,
+                                )),
+                                colors: fart_assets.config.colors.get(),
+                                t: 0.5,
                             },
                         );
                     }
                 }
-            }
-
-            if let Some(collision) = collision_to_resolve {
-                guy.state.bubble_timer = None;
-
-                let before = guy.state.clone();
-
-                let normal_vel = vec2::dot(guy.state.vel, collision.normal);
-                let tangent = collision.normal.rotate_90();
-                let tangent_vel = vec2::dot(guy.state.vel, tangent)
-                    - guy.state.w.as_radians() * guy.radius()
-                    + collision.surface.flow;
-                let bounce_impulse = -normal_vel * (1.0 + collision.assets.params.bounciness);
-                let impulse =
-                    bounce_impulse.max(-normal_vel + collision.assets.params.min_bounce_vel);
-                guy.state.vel += collision.normal * impulse / guy.mass(&self.config);
-                let max_friction_impulse = normal_vel.abs() * collision.assets.params.friction;
-                let friction_impulse = -tangent_vel.clamp_abs(max_friction_impulse);
-
-                guy.state.pos += collision.normal * collision.penetration;
-                guy.state.vel += tangent * friction_impulse / guy.mass(&self.config);
-                guy.state.w -=
-                    Angle::from_radians(friction_impulse / guy.radius() / guy.mass(&self.config));
-
-                guy.state.vel -=
-                    guy.state.vel * (delta_time * collision.assets.params.speed_friction).min(1.0);
-                guy.state.w -=
-                    guy.state.w * (delta_time * collision.assets.params.rotation_friction).min(1.0);
-
-                // Stickiness
-                guy.state.stick_force = std::cmp::max_by_key(
-                    guy.state.stick_force,
-                    (normal_vel * collision.assets.params.stick_strength)
-                        .clamp_abs(collision.assets.params.max_stick_force)
-                        * collision.normal,
-                    |force| r32(force.len()),
-                );
-
-                // Snow layer
-                if collision.surface.type_name == "snow" {
-                    guy.state.snow_layer += guy.state.w.as_radians().abs() * delta_time * 1e-2;
-                    // TODO magic constant??
-                }
-
-                {
-                    let snow_falloff = ((bounce_impulse.abs()
-                        - self.config.snow_falloff_impulse_min)
-                        / (self.config.snow_falloff_impulse_max
-                            - self.config.snow_falloff_impulse_min))
-                        .clamp(0.0, 1.0)
-                        * self.config.max_snow_layer
-                        * collision.assets.params.snow_falloff;
-                    let snow_falloff = snow_falloff.min(guy.state.snow_layer);
-                    guy.state.snow_layer -= snow_falloff;
+                simulation::Event::SnowFalloff {
+                    pos,
+                    radius,
+                    vel,
+                    amount,
+                } => {
+                    let assets = self.assets.get();
                     let fart_type = "normal"; // TODO: not normal?
                     let fart_assets = &assets.farts[fart_type];
-                    for _ in 0..(100.0 * snow_falloff / self.config.max_snow_layer) as i32 {
+                    for _ in 0..(100.0 * amount) as i32 {
                         self.farticles.push(
                             fart_assets,
                             farticle::Farticle {
                                 size: 0.6 * fart_assets.config.farticle_size,
-                                pos: guy.state.pos
-                                    + vec2(guy.radius(), 0.0).rotate(Angle::from_radians(
+                                pos: pos
+                                    + vec2(radius, 0.0).rotate(Angle::from_radians(
                                         thread_rng().gen_range(0.0..2.0 * f32::PI),
                                     )),
-                                vel: thread_rng().gen_circle(before.vel, 1.0),
+                                vel: thread_rng().gen_circle(vel, 1.0),
                                 rot: Angle::from_radians(
                                     thread_rng().gen_range(0.0..2.0 * f32::PI),
                                 ),
@@ -591,32 +294,54 @@ impl Game {
                         );
                     }
                 }
-                guy.state.snow_layer = guy.state.snow_layer.clamp(0.0, self.config.max_snow_layer);
-
-                if let Some(sound) = &collision.assets.sound {
-                    let volume = ((-0.5 + impulse / 2.0) / 2.0).clamp(0.0, 1.0);
-                    if volume > 0.0 {
-                        self.sound.play(sound, volume, guy.state.pos);
+                simulation::Event::Bounce {
+                    surface_type,
+                    volume,
+                    pos,
+                } => {
+                    let assets = self.assets.get();
+                    if let Some(sound) = &assets.surfaces[&surface_type].sound {
+                        self.sound.play(sound, volume, pos);
                     }
                 }
-            } else {
-                guy.state.stick_force = vec2::ZERO;
-            }
-
-            // Portals
-            for portal in &self.level.portals {
-                let is_colliding =
-                    |pos: vec2<f32>| -> bool { (pos - portal.pos).len() < self.config.portal.size };
-                if !is_colliding(prev_state.pos) && is_colliding(guy.state.pos) {
-                    if let Some(dest) = portal.dest {
-                        guy.state.pos = self.level.portals[dest].pos;
-                        break;
-                    }
+                simulation::Event::CannonShot { pos, dir } => {
+                    let assets = self.assets.get();
+                    let assets = &assets.cannon;
+                    self.sound.play(
+                        assets.farticles.sfx.choose(&mut thread_rng()).unwrap(),
+                        1.0,
+                        pos,
+                    );
+                    self.farticles.spawn(
+                        &assets.farticles,
+                        pos,
+                        dir * self.config.cannon.particle_speed,
+                    );
                 }
             }
         }
     }
 
+    /// Records the time when my guy gets into the next section
+    pub fn update_splits(&mut self) {
+        let Some(guy) = self.my_guy.and_then(|id| self.simulation.guys.get(&id)) else {
+            return;
+        };
+        let level = &self.simulation.level;
+        // Finishing is the split after the last section
+        let split = if guy.progress.finished {
+            Some(level.sections.sections.len())
+        } else {
+            let progress = level
+                .progress_at(guy.state.pos)
+                .unwrap_or(guy.progress.current);
+            level.section_at(progress)
+        };
+        if let Some(split) = split {
+            self.splits.enter(split, self.simulation_time);
+        }
+    }
+
     pub fn handle_connection(&mut self) {
         let messages: Vec<ServerMessage> = match &mut self.connection {
             Some(con) => con.new_messages().collect::<anyhow::Result<_>>().unwrap(),
@@ -625,13 +350,21 @@ impl Game {
         for message in messages {
             match message {
                 ServerMessage::ForceReset => {
+                    if let Some(guy) = self.my_guy.and_then(|id| self.simulation.guys.get_mut(&id))
+                    {
+                        guy.progress.checkpoint = None;
+                    }
                     self.respawn_my_guy();
                 }
                 ServerMessage::Pong => {
+                    if let Some(sent) = self.ping_sent {
+                        self.round_trip_time = self.real_time - sent;
+                    }
                     if let Some(con) = &mut self.connection {
                         con.send(ClientMessage::Ping);
+                        self.ping_sent = Some(self.real_time);
                         if let Some(id) = self.my_guy {
-                            let guy = self.guys.get(&id).unwrap();
+                            let guy = self.simulation.guys.get(&id).unwrap();
                             con.send(ClientMessage::Update(self.simulation_time, guy.clone()));
                         }
                     }
@@ -646,30 +379,65 @@ impl Game {
                             e.insert(Replay::new(t, &guy));
                         }
                     }
-                    if let Some(current) = self.guys.get_mut(&guy.id) {
+                    if let Some(current) = self.simulation.guys.get_mut(&guy.id) {
                         current.progress = guy.progress;
                     }
                 }
                 ServerMessage::Despawn(id) => {
-                    self.guys.remove(&id);
+                    self.simulation.guys.remove(&id);
                     self.remote_updates.remove(&id);
                 }
                 ServerMessage::Emote(id, emote) => {
                     self.emotes.retain(|&(_, x, _)| x != id);
                     self.emotes.push((self.real_time, id, emote));
                 }
+                ServerMessage::PlatformTime(time) => {
+                    // The server clock has moved on while the message was on its way
+                    let offset =
+                        time + self.round_trip_time as f64 / 2.0 - self.simulation.platform_time;
+                    self.simulation.correct_platform_time(offset);
+                    if let Some(recording) = &mut self.input_recording {
+                        recording.record_platform_time_correction(offset);
+                    }
+                }
             }
         }
     }
 
+    /// Start the run over from the spawn point, forgetting the checkpoint
+    pub fn restart_my_guy(&mut self) {
+        if let Some(guy) = self.my_guy.and_then(|id| self.simulation.guys.get_mut(&id)) {
+            guy.progress.checkpoint = None;
+        }
+        self.respawn_my_guy();
+    }
+
+    /// Respawn at the last activated checkpoint, or at the start if there is none
     pub fn respawn_my_guy(&mut self) {
         // COPYPASTA MMMMM 🍝 or is it anymore?
-        let new_guy = Guy::new(self.client_id, self.level.spawn_point, true, &self.config);
+        let checkpoint = self
+            .my_guy
+            .and_then(|id| self.simulation.guys.get(&id))
+            .and_then(|guy| guy.progress.checkpoint.clone())
+            .filter(|checkpoint| self.simulation.is_checkpoint(checkpoint.pos));
+        let pos = match &checkpoint {
+            Some(checkpoint) => checkpoint.pos,
+            None => self.simulation.level.spawn_point,
+        };
+        self.simulation.spawn_guy(self.client_id, pos, true);
         if self.my_guy.is_none() {
             self.my_guy = Some(self.client_id);
         }
-        self.guys.insert(new_guy);
-        self.simulation_time = 0.0;
+        let guy = self.simulation.guys.get_mut(&self.client_id).unwrap();
+        if let Some(checkpoint) = &checkpoint {
+            guy.state.fart_type = checkpoint.fart_type.clone();
+        }
+        guy.progress.checkpoint = checkpoint;
+        if checkpoint.is_none() {
+            // The clock keeps running when going back to a checkpoint
+            self.simulation_time = 0.0;
+            self.splits.reset();
+        }
         if let Some(con) = &mut self.connection {
             con.send(ClientMessage::Despawn);
         }
@@ -15,6 +15,8 @@ mod assets;
 mod customizer;
 mod editor;
 mod farticle;
+#[cfg(test)]
+mod fixtures;
 mod game;
 mod guy;
 mod id;
@@ -24,6 +26,7 @@ mod logic;
 mod net;
 mod remote;
 mod replay;
+mod simulation;
 mod svg;
 mod util;
 mod video_editor;
@@ -40,8 +43,11 @@ pub use logic::*;
 pub use net::*;
 pub use remote::*;
 pub use replay::*;
+pub use simulation::{PhysicsParams, Simulation};
 pub use util::*;
 
+pub const FIXED_DELTA_TIME: f64 = 1.0 / 200.0;
+
 #[derive(clap::Parser, Clone)]
 pub struct Opt {
     #[clap(long)]
@@ -60,8 +66,30 @@ pub struct Opt {
     pub accessibility: Option<f32>,
     #[clap(long)]
     pub mouse_aim: bool,
+    #[clap(long)]
+    pub input_replay: Vec<std::path::PathBuf>,
+    #[clap(long)]
+    pub verify_replay: Option<std::path::PathBuf>,
     #[clap(long, default_value = "0.0")]
     pub add_flow: f32,
+    #[clap(long)]
+    pub import_svg: Option<std::path::PathBuf>,
+    #[clap(long, default_value = "0.1")]
+    pub import_scale: f32,
+    #[clap(long)]
+    pub export_map: Option<std::path::PathBuf>,
+    #[clap(long, default_value = "10.0")]
+    pub export_scale: f32,
+    #[clap(long)]
+    pub export_replays: Option<std::path::PathBuf>,
+    #[clap(long)]
+    pub upgrade_level: Vec<std::path::PathBuf>,
+    #[clap(long)]
+    pub check_level: bool,
+    #[clap(long)]
+    pub level_stats: bool,
+    #[clap(long)]
+    pub stats_json: bool,
     #[clap(flatten)]
     pub geng: geng::CliArgs,
 }
@@ -87,6 +115,86 @@ fn main() {
 
     logger::init();
 
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(path) = &opt.verify_replay {
+        let level_path = opt.level.clone().unwrap_or(assets_dir.join("level.json"));
+        if let Err(e) =
+            futures::executor::block_on(replay::verify_input_replay(path, &assets_dir, level_path))
+        {
+            log::error!("{e:#}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    if opt.level_stats {
+        let level_path = opt.level.clone().unwrap_or(assets_dir.join("level.json"));
+        if let Err(e) = futures::executor::block_on(level::print_level_stats(
+            &assets_dir,
+            level_path,
+            opt.stats_json,
+        )) {
+            log::error!("{e:#}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    if opt.check_level {
+        let level_path = opt.level.clone().unwrap_or(assets_dir.join("level.json"));
+        if let Err(e) = futures::executor::block_on(level::check_level(&assets_dir, level_path)) {
+            log::error!("{e:#}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    if !opt.upgrade_level.is_empty() {
+        if let Err(e) = futures::executor::block_on(level::upgrade_level_files(&opt.upgrade_level))
+        {
+            log::error!("{e:#}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(path) = &opt.import_svg {
+        let Some(level_path) = &opt.level else {
+            log::error!("Specify the --level to import into");
+            std::process::exit(1);
+        };
+        if let Err(e) = futures::executor::block_on(level::import_svg(
+            path,
+            &assets_dir,
+            level_path,
+            opt.import_scale,
+        )) {
+            log::error!("{e:#}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(path) = &opt.export_map {
+        let level_path = opt.level.clone().unwrap_or(assets_dir.join("level.json"));
+        if let Err(e) = futures::executor::block_on(level::export_map(
+            level_path,
+            &assets_dir,
+            opt.export_replays.as_ref(),
+            path,
+            opt.export_scale,
+        )) {
+            log::error!("{e:#}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
     if opt.server.is_some() && opt.connect.is_none() {
         #[cfg(not(target_arch = "wasm32"))]
         net::Server::new(opt.server.as_deref().unwrap()).run();
@@ -105,7 +213,7 @@ fn main() {
 
         let geng = Geng::new_with(geng::ContextOptions {
             title: "Beans of Power".to_owned(),
-            fixed_delta_time: 1.0 / 200.0,
+            fixed_delta_time: FIXED_DELTA_TIME,
             ..geng::ContextOptions::from_args(&opt.geng)
         });
         let connection = future::OptionFuture::<_>::from(match opt.connect.as_deref().unwrap() {
@@ -132,6 +240,7 @@ fn main() {
                     <AssetsHandle as geng::asset::Load>::load(geng.asset_manager(), &assets_dir),
                     Level::load(
                         opt.level.clone().unwrap_or(assets_dir.join("level.json")),
+                        assets_dir.join("prefabs"),
                         opt.editor,
                     ),
                 ),
@@ -140,7 +249,13 @@ fn main() {
             .await;
             let assets = assets.expect("Failed to load assets");
             let assets = Rc::new(assets);
-            let mut level = level;
+            let mut level = match level {
+                Ok(level) => level,
+                Err(e) => {
+                    log::error!("{e:#}");
+                    std::process::exit(1);
+                }
+            };
             for layer in &mut level.modify().layers {
                 for surface in &mut layer.surfaces {
                     surface.flow += opt.add_flow;
@@ -25,4 +25,5 @@ pub enum ServerMessage {
     UpdateGuy(f32, Guy),
     Despawn(Id),
     Emote(Id, usize),
+    PlatformTime(f64),
 }
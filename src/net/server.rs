@@ -4,6 +4,9 @@ use geng::net;
 
 const TICKS_PER_SECOND: f32 = 1.0;
 
+/// Clients slew their platform clocks towards the server one, so it is only sent once in a while
+const PLATFORM_SYNC_INTERVAL: f64 = 10.0;
+
 struct ClientState {
     sender: Box<dyn net::Sender<ServerMessage>>,
 }
@@ -12,10 +15,18 @@ struct ServerState {
     id_gen: IdGen,
     messages: Vec<ServerMessage>,
     clients: HashMap<Id, ClientState>,
+    platform_timer: Timer,
+    next_platform_sync: f64,
 }
 
 impl ServerState {
     fn send_updates(&mut self) {
+        // Client clocks drift apart because of lag and time scale, so platforms are kept in sync
+        let platform_time = self.platform_timer.elapsed().as_secs_f64();
+        if platform_time >= self.next_platform_sync {
+            self.next_platform_sync = platform_time + PLATFORM_SYNC_INTERVAL;
+            self.messages.push(ServerMessage::PlatformTime(platform_time));
+        }
         let messages = mem::replace(&mut self.messages, Vec::new());
         for (&client_id, client) in &mut self.clients {
             for message in &messages {
@@ -26,6 +37,7 @@ impl ServerState {
                     ServerMessage::Despawn(id) => *id != client_id,
                     ServerMessage::Emote(..) => true,
                     ServerMessage::ForceReset => true,
+                    ServerMessage::PlatformTime(_) => true,
                 } {
                     client.sender.send(message.clone());
                 }
@@ -108,6 +120,8 @@ impl Server {
             messages: Vec::new(),
             id_gen: IdGen::new(),
             clients: HashMap::new(),
+            platform_timer: Timer::new(),
+            next_platform_sync: 0.0,
         }));
         Self {
             state: state.clone(),
@@ -158,6 +172,9 @@ impl net::server::App for ServerApp {
         let state: &mut ServerState = &mut state;
         let client_id = state.id_gen.gen();
         sender.send(ServerMessage::ClientId(client_id));
+        sender.send(ServerMessage::PlatformTime(
+            state.platform_timer.elapsed().as_secs_f64(),
+        ));
         state.clients.insert(client_id, ClientState { sender });
         Client {
             client_id,
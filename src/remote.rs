@@ -27,7 +27,7 @@ impl Game {
     pub fn update_remote(&mut self, delta_time: f32) {
         let mut to_remove = Vec::new();
         for (&id, replay) in &mut self.remote_updates {
-            Self::update_replay(id, replay, delta_time, &mut self.guys);
+            Self::update_replay(id, replay, delta_time, &mut self.simulation.guys);
             // TODO speedup replay instead?
             if replay.time_left() > 5.0 {
                 to_remove.push(id);
@@ -35,17 +35,49 @@ impl Game {
             replay.trim_beginning();
         }
         for id in to_remove {
-            self.guys.remove(&id);
+            self.simulation.guys.remove(&id);
             self.remote_updates.remove(&id);
         }
     }
 
     pub fn update_replays(&mut self, delta_time: f32) {
         for (i, replay) in self.replays.iter_mut().enumerate() {
-            Self::update_replay(Id::replay(i), replay, delta_time, &mut self.guys);
+            Self::update_replay(Id::replay(i), replay, delta_time, &mut self.simulation.guys);
             if replay.time_left() < 0.0 {
                 replay.reset();
             }
         }
     }
+
+    pub fn update_input_replays(&mut self) {
+        for (i, replayer) in self.input_replays.iter_mut().enumerate() {
+            let id = Id::input_replay(i);
+            if self.simulation.guys.get(&id).is_none() {
+                self.simulation
+                    .spawn_guy(id, replayer.replay.start.pos, false);
+                replayer.reset(self.simulation.guys.get_mut(&id).unwrap());
+            }
+            let guy = self.simulation.guys.get_mut(&id).unwrap();
+            if replayer.finished() {
+                replayer.reset(guy);
+            }
+            replayer.before_update(guy);
+        }
+    }
+
+    pub fn check_input_replays(&mut self, delta_time: f32) {
+        for (i, replayer) in self.input_replays.iter_mut().enumerate() {
+            let guy = self.simulation.guys.get(&Id::input_replay(i)).unwrap();
+            if let Err(e) = replayer.after_update(guy) {
+                log::warn!("Input replay #{i}: {e}");
+            }
+        }
+        if let Some(recording) = &mut self.input_recording {
+            if let Some(guy) = self.my_guy.and_then(|id| self.simulation.guys.get(&id)) {
+                if !guy.paused {
+                    recording.record(delta_time, guy);
+                }
+            }
+        }
+    }
 }
@@ -35,6 +35,9 @@ impl History {
     pub fn customization(&self) -> &CustomizationOptions {
         &self.customization
     }
+    pub fn positions(&self) -> impl Iterator<Item = vec2<f32>> + '_ {
+        self.log.iter().map(|entry| entry.snapshot.pos)
+    }
     pub fn push(&mut self, timestamp: f32, guy: &Guy) {
         self.customization = guy.customization.clone();
         self.log.push_back(HistoryEntry {
@@ -80,6 +83,14 @@ mod v0 {
         }
     }
 
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    pub struct Progress {
+        pub finished: bool,
+        pub current: f32,
+        pub best: f32,
+        pub best_time: Option<f32>,
+    }
+
     #[derive(Serialize, Deserialize, Clone, Debug, HasId)]
     pub struct Guy {
         pub id: Id,
@@ -248,3 +259,261 @@ impl Replay {
         }
     }
 }
+
+/// How often [InputReplay] stores a full snapshot for verification
+pub const INPUT_REPLAY_CHECKPOINT_INTERVAL: u64 = 200;
+
+/// Compact replay that only stores input changes, physics is re-simulated on playback
+#[derive(Serialize, Deserialize, Clone)]
+pub struct InputReplay {
+    pub customization: CustomizationOptions,
+    pub delta_time: f32,
+    pub platform_time: f64,
+    pub start: PhysicsState,
+    /// Progress at the start, like the checkpoint the guy respawns at
+    pub progress: Progress,
+    /// Tick at which the input changed, and the new input
+    pub inputs: Vec<(u64, Input)>,
+    /// Tick before which the platform clock was corrected, and the offset,
+    /// see [Simulation::correct_platform_time]
+    pub platform_time_corrections: Vec<(u64, f64)>,
+    /// State after the given number of ticks, used to detect desyncs
+    pub checkpoints: Vec<(u64, PhysicsState)>,
+    pub ticks: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum VersionedInputReplay {
+    V0(InputReplay),
+}
+
+impl From<VersionedInputReplay> for InputReplay {
+    fn from(value: VersionedInputReplay) -> Self {
+        match value {
+            VersionedInputReplay::V0(replay) => replay,
+        }
+    }
+}
+
+impl InputReplay {
+    pub fn new(delta_time: f32, platform_time: f64, guy: &Guy) -> Self {
+        Self {
+            customization: guy.customization.clone(),
+            delta_time,
+            platform_time,
+            start: guy.state.clone(),
+            progress: guy.progress.clone(),
+            inputs: vec![],
+            platform_time_corrections: vec![],
+            checkpoints: vec![],
+            ticks: 0,
+        }
+    }
+
+    /// Call after every simulation tick the guy took part in
+    pub fn record(&mut self, delta_time: f32, guy: &Guy) {
+        if delta_time != self.delta_time {
+            log::warn!("Delta time changed while recording, replay is going to desync");
+        }
+        if self.inputs.last().map(|(_, input)| input) != Some(&guy.input) {
+            self.inputs.push((self.ticks, guy.input.clone()));
+        }
+        self.ticks += 1;
+        if self.ticks % INPUT_REPLAY_CHECKPOINT_INTERVAL == 0 {
+            self.checkpoints.push((self.ticks, guy.state.clone()));
+        }
+    }
+
+    /// Call when the platform clock is corrected, before the upcoming tick
+    pub fn record_platform_time_correction(&mut self, offset: f64) {
+        self.platform_time_corrections.push((self.ticks, offset));
+    }
+
+    pub fn save(&self, path: impl AsRef<std::path::Path>) -> anyhow::Result<()> {
+        let file = std::fs::File::create(path)?;
+        let writer = std::io::BufWriter::new(file);
+        bincode::serialize_into(writer, &VersionedInputReplay::V0(self.clone()))?;
+        Ok(())
+    }
+
+    pub async fn load(path: impl AsRef<std::path::Path>) -> anyhow::Result<Self> {
+        let bytes = file::load_bytes(path).await?;
+        let replay: VersionedInputReplay =
+            bincode::deserialize(&bytes).context("Failed to deserialize input replay")?;
+        Ok(replay.into())
+    }
+}
+
+#[derive(Debug)]
+pub struct Divergence {
+    pub tick: u64,
+    pub expected: PhysicsState,
+    pub actual: PhysicsState,
+}
+
+impl std::fmt::Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Diverged at tick {}: expected {:?}, got {:?}",
+            self.tick, self.expected, self.actual,
+        )
+    }
+}
+
+impl std::error::Error for Divergence {}
+
+/// Plays back an [InputReplay] by feeding its inputs into a simulated guy
+pub struct InputReplayer {
+    pub replay: InputReplay,
+    tick: u64,
+    next_input: usize,
+    next_checkpoint: usize,
+    next_correction: usize,
+}
+
+impl InputReplayer {
+    pub fn new(replay: InputReplay) -> Self {
+        Self {
+            replay,
+            tick: 0,
+            next_input: 0,
+            next_checkpoint: 0,
+            next_correction: 0,
+        }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.tick >= self.replay.ticks
+    }
+
+    pub fn reset(&mut self, guy: &mut Guy) {
+        self.tick = 0;
+        self.next_input = 0;
+        self.next_checkpoint = 0;
+        self.next_correction = 0;
+        guy.customization = self.replay.customization.clone();
+        guy.state = self.replay.start.clone();
+        guy.input = default();
+        guy.progress = self.replay.progress.clone();
+        guy.paused = false;
+    }
+
+    /// Apply the input for the upcoming tick
+    pub fn before_update(&mut self, guy: &mut Guy) {
+        while let Some((tick, input)) = self.replay.inputs.get(self.next_input) {
+            if *tick > self.tick {
+                break;
+            }
+            guy.input = input.clone();
+            self.next_input += 1;
+        }
+    }
+
+    /// Apply the platform clock corrections for the upcoming tick,
+    /// only when the simulation is not shared with anything else
+    pub fn correct_platform_time(&mut self, simulation: &mut Simulation) {
+        while let Some(&(tick, offset)) = self
+            .replay
+            .platform_time_corrections
+            .get(self.next_correction)
+        {
+            if tick > self.tick {
+                break;
+            }
+            simulation.correct_platform_time(offset);
+            self.next_correction += 1;
+        }
+    }
+
+    /// Advance to the next tick, checking the guy against a checkpoint if there is one
+    pub fn after_update(&mut self, guy: &Guy) -> Result<(), Divergence> {
+        self.tick += 1;
+        while let Some((tick, snapshot)) = self.replay.checkpoints.get(self.next_checkpoint) {
+            if *tick > self.tick {
+                break;
+            }
+            self.next_checkpoint += 1;
+            if *tick == self.tick && *snapshot != guy.state {
+                return Err(Divergence {
+                    tick: self.tick,
+                    expected: snapshot.clone(),
+                    actual: guy.state.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Re-simulate the whole replay, stopping at the first checkpoint that does not match
+    pub fn verify(&mut self, simulation: &mut Simulation) -> Result<(), Divergence> {
+        let id = Id::replay(0);
+        simulation.platform_time = self.replay.platform_time;
+        simulation.spawn_guy(id, self.replay.start.pos, false);
+        self.reset(simulation.guys.get_mut(&id).unwrap());
+        while !self.finished() {
+            self.before_update(simulation.guys.get_mut(&id).unwrap());
+            self.correct_platform_time(simulation);
+            simulation.update(self.replay.delta_time);
+            self.after_update(simulation.guys.get(&id).unwrap())?;
+        }
+        Ok(())
+    }
+}
+
+pub async fn verify_input_replay(
+    path: impl AsRef<std::path::Path>,
+    assets_path: impl AsRef<std::path::Path>,
+    level_path: impl AsRef<std::path::Path>,
+) -> anyhow::Result<()> {
+    let replay = InputReplay::load(path)
+        .await
+        .context("Failed to load input replay")?;
+    let mut simulation = Simulation::load(assets_path, level_path, 0).await?;
+    let mut replayer = InputReplayer::new(replay);
+    replayer.verify(&mut simulation)?;
+    log::info!(
+        "Replay is fine, {} ticks and {} checkpoints verified",
+        replayer.replay.ticks,
+        replayer.replay.checkpoints.len(),
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recorded_input_replay_verifies() {
+        let assets_path = fixtures::assets_path();
+        let level_path = assets_path.join("level.json");
+        let mut simulation =
+            futures::executor::block_on(Simulation::load(&assets_path, &level_path, 0)).unwrap();
+        let id = Id::replay(0);
+        simulation.spawn_guy(id, simulation.level.spawn_point, false);
+        let delta_time = FIXED_DELTA_TIME as f32;
+        let mut replay = InputReplay::new(
+            delta_time,
+            simulation.platform_time,
+            simulation.guys.get(&id).unwrap(),
+        );
+        for tick in 0..INPUT_REPLAY_CHECKPOINT_INTERVAL * 2 + 10 {
+            simulation.guys.get_mut(&id).unwrap().input = Input {
+                roll_left: if tick % 100 < 50 { 1.0 } else { 0.0 },
+                roll_right: 0.0,
+                force_fart: tick % 150 < 20,
+            };
+            simulation.update(delta_time);
+            replay.record(delta_time, simulation.guys.get(&id).unwrap());
+        }
+        assert_eq!(replay.checkpoints.len(), 2);
+
+        let path = fixtures::temp_dir("input-replay").with_extension("bincode");
+        replay.save(&path).unwrap();
+        let result =
+            futures::executor::block_on(verify_input_replay(&path, &assets_path, &level_path));
+        std::fs::remove_file(&path).unwrap();
+        result.unwrap();
+    }
+}
@@ -0,0 +1,1000 @@
+use super::*;
+
+use rand::{rngs::StdRng, SeedableRng};
+
+mod params;
+
+pub use params::*;
+
+/// Limit on how many impacts are resolved in a single tick
+const MAX_COLLISION_SUBSTEPS: usize = 4;
+
+/// Number of sequential impulse passes over all contacts of a guy
+const CONTACT_SOLVER_ITERATIONS: usize = 4;
+
+/// How much faster or slower the platform clock may run while it catches up with a correction
+const PLATFORM_CLOCK_SLEW: f64 = 0.1;
+
+/// Corrections bigger than this are applied at once, like right after joining a server
+const MAX_PLATFORM_CLOCK_SLEW_OFFSET: f64 = 1.0;
+
+/// Things that happened during a tick that the presentation layer may want to show or play
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// Regular fart, starts long farting
+    Fart {
+        guy_id: Id,
+        fart_type: String,
+        pos: vec2<f32>,
+        vel: vec2<f32>,
+    },
+    /// Emitted every tick while long farting
+    LongFart {
+        guy_id: Id,
+        fart_type: String,
+        pos: vec2<f32>,
+        vel: vec2<f32>,
+        rot: Angle<f32>,
+        delta_time: f32,
+    },
+    /// Stomach growls when fart is ready again
+    Recharge {
+        guy_id: Id,
+    },
+    Splash {
+        pos: vec2<f32>,
+        radius: f32,
+    },
+    SnowFalloff {
+        pos: vec2<f32>,
+        radius: f32,
+        vel: vec2<f32>,
+        amount: f32,
+    },
+    Bounce {
+        surface_type: String,
+        volume: f32,
+        pos: vec2<f32>,
+    },
+    CannonShot {
+        pos: vec2<f32>,
+        dir: vec2<f32>,
+    },
+}
+
+/// Guy physics, decoupled from rendering and audio
+pub struct Simulation {
+    pub config: Rc<Config>,
+    pub params: Rc<PhysicsParams>,
+    pub level: Level,
+    pub guys: Collection<Guy>,
+    /// Shared clock that moving platforms are evaluated at
+    pub platform_time: f64,
+    /// Part of the last correction of the platform clock that is not applied yet
+    platform_time_correction: f64,
+    rng: StdRng,
+    events: Vec<Event>,
+}
+
+impl Simulation {
+    pub fn new(config: Rc<Config>, params: Rc<PhysicsParams>, level: Level, seed: u64) -> Self {
+        Self {
+            config,
+            params,
+            level,
+            guys: Collection::new(),
+            platform_time: 0.0,
+            platform_time_correction: 0.0,
+            rng: StdRng::seed_from_u64(seed),
+            events: vec![],
+        }
+    }
+
+    /// Load only what is needed for physics, so this works with no window or audio
+    pub async fn load(
+        assets_path: impl AsRef<std::path::Path>,
+        level_path: impl AsRef<std::path::Path>,
+        seed: u64,
+    ) -> anyhow::Result<Self> {
+        let assets_path = assets_path.as_ref();
+        let config: Config = file::load_json(assets_path.join("config.json"))
+            .await
+            .context("Failed to load config")?;
+        let params = PhysicsParams::load(assets_path).await?;
+        let level = Level::load(level_path, assets_path.join("prefabs"), false).await?;
+        Ok(Self::new(Rc::new(config), Rc::new(params), level, seed))
+    }
+
+    /// Insert a new guy, optionally randomizing its position and rotation a bit
+    pub fn spawn_guy(&mut self, id: Id, pos: vec2<f32>, jitter: bool) {
+        let mut guy = Guy::new(id, pos, &self.config);
+        if jitter {
+            guy.state.pos.x += self.rng.gen_range(-1.0..=1.0);
+            guy.state.rot = Angle::from_radians(self.rng.gen_range(-1.0..=1.0));
+        }
+        self.guys.insert(guy);
+    }
+
+    /// Moves the platform clock by the offset, gradually unless the offset is big,
+    /// replaces the previous correction if it was not applied yet
+    pub fn correct_platform_time(&mut self, offset: f64) {
+        if offset.abs() > MAX_PLATFORM_CLOCK_SLEW_OFFSET {
+            self.platform_time += offset;
+            self.platform_time_correction = 0.0;
+        } else {
+            self.platform_time_correction = offset;
+        }
+    }
+
+    pub fn platform_time_correction(&self) -> f64 {
+        self.platform_time_correction
+    }
+
+    pub fn take_events(&mut self) -> Vec<Event> {
+        mem::take(&mut self.events)
+    }
+
+    pub fn time_scale_at(&self, pos: vec2<f32>) -> f32 {
+        time_scale_at(&self.level, &self.params, &self.config, pos)
+    }
+
+    pub fn gravity_at(&self, pos: vec2<f32>) -> vec2<f32> {
+        gravity_at(&self.level, &self.params, &self.config, pos)
+    }
+
+    /// Whether there is still a checkpoint object at the position, since the level may have changed
+    pub fn is_checkpoint(&self, pos: vec2<f32>) -> bool {
+        self.level
+            .objects_in(Aabb2::point(pos).extend_uniform(EPS))
+            .into_iter()
+            .any(|object| object.pos == pos && self.params.objects[&object.type_name].checkpoint)
+    }
+
+    /// Fart type actually used by the guy right now
+    pub fn fart_type<'a>(&'a self, guy: &'a Guy) -> &'a str {
+        current_fart_type(&self.level, &self.params, guy)
+    }
+
+    pub fn update(&mut self, delta_time: f32) {
+        let level = &self.level;
+        let config = &self.config;
+        let params = &self.params;
+        let events = &mut self.events;
+        let is_splashing = |guy: &Guy| -> bool {
+            let aabb = Aabb2::point(guy.state.pos).extend_uniform(guy.radius());
+            for surface in level.surfaces_in(aabb) {
+                let v = surface.vector_from(guy.state.pos);
+                let penetration = guy.radius() - v.len();
+                if penetration > EPS && params.surfaces[&surface.type_name].splash {
+                    return true;
+                }
+            }
+            false
+        };
+        let max_pickup_radius = params
+            .objects
+            .values()
+            .map(|object| object.pickup_radius)
+            .fold(0.0, f32::max);
+        for guy in &mut self.guys {
+            if guy.paused {
+                continue;
+            }
+            let time_scale = time_scale_at(level, params, config, guy.state.pos);
+            let delta_time = delta_time * time_scale;
+
+            let prev_state = guy.state.clone();
+            let was_colliding_water = is_splashing(guy);
+            if (guy.state.pos - level.finish_point).len() < 1.5 {
+                guy.progress.finished = true;
+                // The next run starts from the beginning
+                guy.progress.checkpoint = None;
+            }
+            let mut enter_bubble = false;
+            {
+                let mut new_fart_type = None;
+                let mut checkpoint = None;
+                let aabb = Aabb2::point(guy.state.pos).extend_uniform(max_pickup_radius);
+                for object in level.objects_in(aabb) {
+                    let object_params = &params.objects[&object.type_name];
+                    if (guy.state.pos - object.pos).len() >= object_params.pickup_radius {
+                        continue;
+                    }
+                    if object_params.one_shot {
+                        if guy.progress.used_objects.contains(object) {
+                            continue;
+                        }
+                        guy.progress.used_objects.push(object.clone());
+                    }
+                    if let Some(fart_type) = &object_params.fart_type {
+                        new_fart_type = Some(fart_type.clone());
+                    }
+                    if object_params.bubble {
+                        enter_bubble = true;
+                    }
+                    if object_params.checkpoint {
+                        checkpoint = Some(object.pos);
+                    }
+                }
+                if let Some(new_fart_type) = new_fart_type {
+                    if new_fart_type != guy.state.fart_type {
+                        guy.state.fart_type = new_fart_type;
+                        guy.state.fart_pressure = config.max_fart_pressure;
+                    }
+                }
+                if let Some(pos) = checkpoint {
+                    guy.progress.checkpoint = Some(Checkpoint {
+                        pos,
+                        fart_type: guy.state.fart_type.clone(),
+                    });
+                }
+            }
+
+            // Bubble
+            if let Some(time) = &mut guy.state.bubble_timer {
+                *time -= delta_time;
+                if *time < 0.0 {
+                    guy.state.bubble_timer = None;
+                }
+                guy.state.vel += (guy.state.vel.normalize_or_zero() * config.bubble_target_speed
+                    - guy.state.vel)
+                    .clamp_len(..=config.bubble_acceleration * delta_time);
+            }
+            if enter_bubble {
+                guy.state.bubble_timer = Some(config.bubble_time);
+            }
+
+            if let std::ops::ControlFlow::Break(()) = features::cannon::update_guy(
+                &mut guy.state,
+                delta_time,
+                level.flattened(),
+                config,
+                events,
+            ) {
+                continue;
+            }
+
+            if guy.progress.finished {
+                guy.state.fart_pressure = 0.0;
+                guy.state.rot -= Angle::from_radians(delta_time);
+                guy.state.pos = level.finish_point
+                    + (guy.state.pos - level.finish_point)
+                        .normalize_or_zero()
+                        .rotate(Angle::from_radians(delta_time))
+                        * 1.0;
+                continue;
+            }
+
+            guy.state.w += Angle::from_radians(
+                (guy.input.roll_direction().clamp(-1.0, 1.0) * config.angular_acceleration
+                    / guy.mass(config)
+                    * delta_time)
+                    .clamp(
+                        -(guy.state.w.as_radians() + config.max_angular_speed).max(0.0),
+                        (config.max_angular_speed - guy.state.w.as_radians()).max(0.0),
+                    ),
+            );
+
+            if guy.state.bubble_timer.is_none() {
+                guy.state.vel += gravity_at(level, params, config, guy.state.pos) * delta_time;
+            }
+
+            let butt = guy.butt();
+            for (tile, triangle) in
+                level.tiles_in(Aabb2::point(guy.state.pos).extend_uniform(config.guy_radius))
+            {
+                if !Aabb2::points_bounding_box(triangle)
+                    .unwrap()
+                    .extend_uniform(config.guy_radius)
+                    .contains(guy.state.pos)
+                {
+                    continue;
+                }
+                let percentage = circle_triangle_intersect_percentage(
+                    guy.state.pos,
+                    config.guy_radius,
+                    triangle,
+                );
+                let relative_vel = guy.state.vel - tile.flow;
+                let flow_direction = tile.flow.normalize_or_zero();
+                let relative_vel_along_flow = vec2::dot(flow_direction, relative_vel);
+                let params = &params.tiles[&tile.type_name];
+                let force_along_flow =
+                    -flow_direction * relative_vel_along_flow * params.friction_along_flow;
+                let friction_force = -relative_vel * params.friction;
+                guy.state.vel += (force_along_flow + params.additional_force + friction_force)
+                    * delta_time
+                    / guy.mass(config)
+                    * percentage;
+                guy.state.w -=
+                    guy.state.w * params.friction * delta_time / guy.mass(config) * percentage;
+                // TODO inertia?
+            }
+
+            let fart_type = current_fart_type(level, params, guy).to_owned();
+
+            let could_fart = guy.state.fart_pressure >= config.fart_pressure_released;
+            if config.fart_continued_force == 0.0 {
+                guy.state.long_farting = false;
+            }
+            if guy.input.force_fart {
+                if guy.state.long_farting {
+                    guy.state.fart_pressure -= delta_time * config.fart_continuation_pressure_speed;
+                    if guy.state.fart_pressure < 0.0 {
+                        guy.state.fart_pressure = 0.0;
+                        guy.state.long_farting = false;
+                    }
+                } else {
+                    guy.state.fart_pressure += delta_time * config.force_fart_pressure_multiplier;
+                }
+            } else {
+                guy.state.long_farting = false;
+                guy.state.fart_pressure += delta_time;
+            };
+
+            if guy.state.long_farting {
+                events.push(Event::LongFart {
+                    guy_id: guy.id,
+                    fart_type,
+                    pos: butt,
+                    vel: guy.state.vel,
+                    rot: guy.state.rot,
+                    delta_time,
+                });
+                guy.state.vel += vec2(0.0, config.fart_continued_force * delta_time)
+                    .rotate(guy.state.rot)
+                    / guy.mass(config);
+            } else if (guy.state.fart_pressure >= config.fart_pressure_released
+                && guy.input.force_fart)
+                || guy.state.fart_pressure >= config.max_fart_pressure
+            {
+                guy.state.bubble_timer = None;
+                guy.state.fart_pressure -= config.fart_pressure_released;
+                guy.state.long_farting = true;
+                events.push(Event::Fart {
+                    guy_id: guy.id,
+                    fart_type,
+                    pos: butt,
+                    vel: guy.state.vel,
+                });
+                guy.state.vel +=
+                    vec2(0.0, config.fart_strength).rotate(guy.state.rot) / guy.mass(config);
+            } else if !could_fart && guy.state.fart_pressure >= config.fart_pressure_released {
+                // Growling stomach recharge
+                events.push(Event::Recharge { guy_id: guy.id });
+                guy.animation.growl_progress = Some(0.0);
+            }
+
+            if let Some(growl) = &mut guy.animation.growl_progress {
+                *growl += delta_time / config.growl_time;
+                if *growl >= 1.0 {
+                    guy.animation.growl_progress = None;
+                }
+            }
+
+            guy.state.vel += guy.state.stick_force / guy.mass(config) * delta_time;
+            guy.state.stick_force -= guy
+                .state
+                .stick_force
+                .clamp_len(..=config.stick_force_fadeout_speed * delta_time);
+
+            // Move only until the next impact so that fast guys can't tunnel through surfaces
+            let mut time_left = delta_time;
+            let mut collided = false;
+            let mut was_colliding_water = was_colliding_water;
+            for substep in 0..MAX_COLLISION_SUBSTEPS {
+                // Platforms keep moving during the tick, on the unscaled clock
+                let elapsed = if time_scale > 0.0 {
+                    (delta_time - time_left) / time_scale
+                } else {
+                    0.0
+                };
+                let platform_surfaces = level
+                    .platform
+                    .surfaces_at(self.platform_time + elapsed as f64);
+                let step =
+                    time_of_impact(level, &platform_surfaces, params, config, guy, time_left)
+                        .map_or(time_left, |time| time.min(time_left));
+                time_left = if substep + 1 == MAX_COLLISION_SUBSTEPS {
+                    // Out of substeps, the rest of the tick is dropped instead of tunneling
+                    0.0
+                } else {
+                    time_left - step
+                };
+                guy.state.pos += guy.state.vel * step;
+                guy.state.rot += guy.state.w * step;
+
+                let mut contacts = Vec::new();
+                let aabb = Aabb2::point(guy.state.pos).extend_uniform(guy.radius());
+                let surfaces = level
+                    .surfaces_in(aabb)
+                    .into_iter()
+                    .map(|surface| (surface, None))
+                    .chain(
+                        platform_surfaces
+                            .iter()
+                            .map(|(surface, transform)| (surface, Some(transform))),
+                    );
+                for (surface, platform) in surfaces {
+                    let from_surface = -surface.vector_from(guy.state.pos);
+                    let penetration = guy.radius() - from_surface.len();
+                    if penetration > 0.0 {
+                        let surface_params = &params.surfaces[&surface.type_name];
+
+                        if surface_params.splash && !was_colliding_water {
+                            was_colliding_water = true;
+                            if vec2::dot(from_surface, guy.state.vel).abs() > 0.5 {
+                                events.push(Event::Splash {
+                                    pos: guy.state.pos - from_surface,
+                                    radius: guy.radius(),
+                                });
+                            }
+                        }
+
+                        if surface_params.non_collidable {
+                            continue;
+                        }
+                        let normal = from_surface.normalize_or_zero();
+                        let surface_vel = platform.map_or(vec2::ZERO, |transform| {
+                            transform.vel_at(guy.state.pos - from_surface)
+                        });
+                        let normal_vel = vec2::dot(normal, guy.state.vel - surface_vel);
+                        if normal_vel < -EPS
+                            && normal_vel > -surface_params.fallthrough_speed.unwrap_or(1e9)
+                            && vec2::skew(surface.p2 - surface.p1, normal) > 0.0
+                            && penetration < config.max_penetration
+                        {
+                            contacts.push(Contact {
+                                penetration,
+                                surface,
+                                surface_vel,
+                                normal,
+                                params: surface_params,
+                            });
+                        }
+                    }
+                }
+
+                if !contacts.is_empty() {
+                    collided = true;
+                    resolve_contacts(guy, &contacts, step, config, events);
+                }
+                if time_left <= 0.0 {
+                    break;
+                }
+            }
+            if !collided {
+                guy.state.stick_force = vec2::ZERO;
+            }
+
+            // Portals
+            let portals = &level.flattened().portals;
+            for portal in portals {
+                let is_colliding =
+                    |pos: vec2<f32>| -> bool { (pos - portal.pos).len() < config.portal.size };
+                if !is_colliding(prev_state.pos) && is_colliding(guy.state.pos) {
+                    if let Some(dest) = portal.dest {
+                        guy.state.pos = portals[dest].pos;
+                        break;
+                    }
+                }
+            }
+        }
+        let step = delta_time as f64;
+        let slew = self
+            .platform_time_correction
+            .clamp(-step * PLATFORM_CLOCK_SLEW, step * PLATFORM_CLOCK_SLEW);
+        self.platform_time_correction -= slew;
+        self.platform_time += step + slew;
+    }
+}
+
+struct Contact<'a> {
+    penetration: f32,
+    normal: vec2<f32>,
+    surface: &'a Surface,
+    /// Velocity of a moving platform at the contact point
+    surface_vel: vec2<f32>,
+    params: &'a SurfaceParams,
+}
+
+/// What a single contact would do if it was the only one, and how much of it is applied so far
+struct ContactImpulse {
+    normal_vel: f32,
+    bounce_impulse: f32,
+    impulse: f32,
+    target_normal_vel: f32,
+    target_tangent_vel: f32,
+    max_friction_impulse: f32,
+    applied_normal: f32,
+    applied_friction: f32,
+}
+
+/// Resolve every touching surface together, so corners and seams don't make the guy jitter
+fn resolve_contacts(
+    guy: &mut Guy,
+    contacts: &[Contact],
+    delta_time: f32,
+    config: &Config,
+    events: &mut Vec<Event>,
+) {
+    guy.state.bubble_timer = None;
+
+    let before = guy.state.clone();
+    let mass = guy.mass(config);
+    let radius = guy.radius();
+    let tangent_vel = |state: &PhysicsState, contact: &Contact| -> f32 {
+        vec2::dot(state.vel - contact.surface_vel, contact.normal.rotate_90())
+            - state.w.as_radians() * radius
+            + contact.surface.flow
+    };
+
+    let mut impulses: Vec<ContactImpulse> = contacts
+        .iter()
+        .map(|contact| {
+            let normal_vel = vec2::dot(before.vel - contact.surface_vel, contact.normal);
+            let tangent_vel = tangent_vel(&before, contact);
+            let bounce_impulse = -normal_vel * (1.0 + contact.params.bounciness);
+            let impulse = bounce_impulse.max(-normal_vel + contact.params.min_bounce_vel);
+            let max_friction_impulse = normal_vel.abs() * contact.params.friction;
+            let friction_impulse = -tangent_vel.clamp_abs(max_friction_impulse);
+            ContactImpulse {
+                normal_vel,
+                bounce_impulse,
+                impulse,
+                target_normal_vel: normal_vel + impulse / mass,
+                // Friction impulse changes both linear and angular velocity
+                target_tangent_vel: tangent_vel + 2.0 * friction_impulse / mass,
+                max_friction_impulse,
+                applied_normal: 0.0,
+                applied_friction: 0.0,
+            }
+        })
+        .collect();
+
+    let mut shift = vec2::ZERO;
+    for _ in 0..CONTACT_SOLVER_ITERATIONS {
+        for (contact, impulse) in contacts.iter().zip(&mut impulses) {
+            let normal_vel = vec2::dot(guy.state.vel - contact.surface_vel, contact.normal);
+            let applied_normal =
+                (impulse.applied_normal + (impulse.target_normal_vel - normal_vel) * mass).max(0.0);
+            guy.state.vel += contact.normal * (applied_normal - impulse.applied_normal) / mass;
+            impulse.applied_normal = applied_normal;
+
+            let tangent_vel = tangent_vel(&guy.state, contact);
+            let applied_friction = (impulse.applied_friction
+                + (impulse.target_tangent_vel - tangent_vel) * mass / 2.0)
+                .clamp_abs(impulse.max_friction_impulse);
+            let friction_impulse = applied_friction - impulse.applied_friction;
+            guy.state.vel += contact.normal.rotate_90() * friction_impulse / mass;
+            guy.state.w -= Angle::from_radians(friction_impulse / radius / mass);
+            impulse.applied_friction = applied_friction;
+
+            let penetration = contact.penetration - vec2::dot(shift, contact.normal);
+            if penetration > 0.0 {
+                shift += contact.normal * penetration;
+            }
+        }
+    }
+    guy.state.pos += shift;
+
+    let speed_friction_contact = contacts
+        .iter()
+        .max_by_key(|contact| r32(contact.params.speed_friction))
+        .unwrap();
+    let rotation_friction = contacts
+        .iter()
+        .map(|contact| contact.params.rotation_friction)
+        .fold(0.0, f32::max);
+    guy.state.vel -= (guy.state.vel - speed_friction_contact.surface_vel)
+        * (delta_time * speed_friction_contact.params.speed_friction).min(1.0);
+    guy.state.w -= guy.state.w * (delta_time * rotation_friction).min(1.0);
+
+    // Stickiness
+    for (contact, impulse) in contacts.iter().zip(&impulses) {
+        guy.state.stick_force = std::cmp::max_by_key(
+            guy.state.stick_force,
+            (impulse.normal_vel * contact.params.stick_strength)
+                .clamp_abs(contact.params.max_stick_force)
+                * contact.normal,
+            |force| r32(force.len()),
+        );
+    }
+
+    // Snow layer
+    let snow_accumulation = contacts
+        .iter()
+        .map(|contact| contact.params.snow_accumulation)
+        .fold(0.0, f32::max);
+    guy.state.snow_layer += guy.state.w.as_radians().abs() * delta_time * snow_accumulation;
+
+    for (contact, impulse) in contacts.iter().zip(&impulses) {
+        let snow_falloff = ((impulse.bounce_impulse.abs() - config.snow_falloff_impulse_min)
+            / (config.snow_falloff_impulse_max - config.snow_falloff_impulse_min))
+            .clamp(0.0, 1.0)
+            * config.max_snow_layer
+            * contact.params.snow_falloff;
+        let snow_falloff = snow_falloff.min(guy.state.snow_layer);
+        guy.state.snow_layer -= snow_falloff;
+        if snow_falloff > 0.0 {
+            events.push(Event::SnowFalloff {
+                pos: guy.state.pos,
+                radius: guy.radius(),
+                vel: before.vel,
+                amount: snow_falloff / config.max_snow_layer,
+            });
+        }
+    }
+    guy.state.snow_layer = guy.state.snow_layer.clamp(0.0, config.max_snow_layer);
+
+    for (contact, impulse) in contacts.iter().zip(&impulses) {
+        if contact.params.sound {
+            let volume = ((-0.5 + impulse.impulse / 2.0) / 2.0).clamp(0.0, 1.0);
+            if volume > 0.0 {
+                events.push(Event::Bounce {
+                    surface_type: contact.surface.type_name.clone(),
+                    volume,
+                    pos: guy.state.pos,
+                });
+            }
+        }
+    }
+}
+
+/// Time until the guy gets deep enough into some surface for the collision to be resolved
+fn time_of_impact(
+    level: &Level,
+    platform_surfaces: &[(Surface, features::platform::PlatformTransform)],
+    params: &PhysicsParams,
+    config: &Config,
+    guy: &Guy,
+    max_time: f32,
+) -> Option<f32> {
+    let radius = guy.radius() - config.max_penetration / 2.0;
+    let aabb =
+        Aabb2::points_bounding_box([guy.state.pos, guy.state.pos + guy.state.vel * max_time])
+            .unwrap()
+            .extend_uniform(guy.radius());
+    let hit_time = |surface: &Surface, vel: vec2<f32>| -> Option<f32> {
+        let params = &params.surfaces[&surface.type_name];
+        if params.non_collidable
+            || -vec2::dot(vel, surface.normal()) >= params.fallthrough_speed.unwrap_or(1e9)
+        {
+            return None;
+        }
+        circle_segment_hit_time(guy.state.pos, vel, radius, surface.p1, surface.p2)
+    };
+    let static_hits = level
+        .surfaces_in(aabb)
+        .into_iter()
+        .filter_map(|surface| hit_time(surface, guy.state.vel));
+    // Moving surfaces are swept in their own frame of reference
+    let platform_hits = platform_surfaces.iter().filter_map(|(surface, transform)| {
+        let closest = guy.state.pos + surface.vector_from(guy.state.pos);
+        hit_time(surface, guy.state.vel - transform.vel_at(closest))
+    });
+    static_hits
+        .chain(platform_hits)
+        .min_by_key(|&time| r32(time))
+}
+
+fn time_scale_at(level: &Level, params: &PhysicsParams, config: &Config, pos: vec2<f32>) -> f32 {
+    let mut time_scale = 1.0;
+    for (tile, triangle) in level.tiles_in(Aabb2::point(pos).extend_uniform(config.guy_radius)) {
+        if !Aabb2::points_bounding_box(triangle)
+            .unwrap()
+            .extend_uniform(config.guy_radius)
+            .contains(pos)
+        {
+            continue;
+        }
+        if let Some(this_time_scale) = params.tiles[&tile.type_name].time_scale {
+            let percentage = circle_triangle_intersect_percentage(pos, config.guy_radius, triangle);
+            time_scale *= this_time_scale.powf(percentage);
+        }
+    }
+    time_scale
+}
+
+fn gravity_at(level: &Level, params: &PhysicsParams, config: &Config, pos: vec2<f32>) -> vec2<f32> {
+    let mut gravity = vec2(0.0, -config.gravity);
+    // Triangles of a tile come one after another, and every tile is blended in only once
+    let mut coverage: Vec<(&Tile, f32)> = Vec::new();
+    for (tile, triangle) in level.tiles_in(Aabb2::point(pos).extend_uniform(config.guy_radius)) {
+        if params.tiles[&tile.type_name].gravity.is_none()
+            || !Aabb2::points_bounding_box(triangle)
+                .unwrap()
+                .extend_uniform(config.guy_radius)
+                .contains(pos)
+        {
+            continue;
+        }
+        let percentage = circle_triangle_intersect_percentage(pos, config.guy_radius, triangle);
+        match coverage.last_mut() {
+            Some((last, sum)) if std::ptr::eq(*last, tile) => *sum += percentage,
+            _ => coverage.push((tile, percentage)),
+        }
+    }
+    for (tile, percentage) in coverage {
+        let tile_gravity = params.tiles[&tile.type_name].gravity.unwrap();
+        gravity += (tile_gravity - gravity) * percentage.min(1.0);
+    }
+    gravity
+}
+
+fn current_fart_type<'a>(level: &Level, params: &'a PhysicsParams, guy: &'a Guy) -> &'a str {
+    let butt = guy.butt();
+    level
+        .tiles_in(Aabb2::point(butt))
+        .into_iter()
+        .filter(|&(_tile, triangle)| inside_triangle(butt, triangle))
+        .find_map(|(tile, _triangle)| params.tiles[&tile.type_name].fart_type.as_deref())
+        .unwrap_or(guy.state.fart_type.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_simulation(seed: u64, build: impl FnOnce(&mut LevelInfo)) -> Simulation {
+        let (config, params) = fixtures::physics();
+        let mut level = fixtures::new_level();
+        let info = level.modify();
+        info.finish_point = vec2(100.0, 100.0);
+        build(info);
+        Simulation::new(Rc::new(config), Rc::new(params), level, seed)
+    }
+
+    fn floor(level: &mut LevelInfo) {
+        level.layers[0].surfaces.push(fixtures::surface(
+            vec2(-10.0, 0.0),
+            vec2(10.0, 0.0),
+            "stone",
+        ));
+    }
+
+    fn run(simulation: &mut Simulation, ticks: usize) {
+        for _ in 0..ticks {
+            simulation.update(FIXED_DELTA_TIME as f32);
+        }
+    }
+
+    #[test]
+    fn free_fall() {
+        let mut simulation = new_simulation(0, |_| {});
+        let id = Id::replay(0);
+        simulation.spawn_guy(id, vec2(0.0, 10.0), false);
+        run(&mut simulation, 200);
+        let state = &simulation.guys.get(&id).unwrap().state;
+        let gravity = simulation.config.gravity;
+        assert!((state.vel.y + gravity).abs() < 1e-3, "{:?}", state.vel);
+        assert!(state.pos.y < 10.0 - gravity / 2.0 + 0.1);
+        assert_eq!(state.vel.x, 0.0);
+    }
+
+    #[test]
+    fn guy_lands_on_floor() {
+        let mut simulation = new_simulation(0, floor);
+        let id = Id::replay(0);
+        simulation.spawn_guy(id, vec2(0.0, 2.0), false);
+        for _ in 0..600 {
+            run(&mut simulation, 1);
+            let pos = simulation.guys.get(&id).unwrap().state.pos;
+            assert!(pos.y > 0.15, "fell into the floor at {pos:?}");
+        }
+        let state = &simulation.guys.get(&id).unwrap().state;
+        assert!(state.pos.y < 0.35, "{:?}", state.pos);
+        assert!(state.vel.len() < 0.1, "{:?}", state.vel);
+    }
+
+    #[test]
+    fn fast_guy_does_not_tunnel() {
+        let mut simulation = new_simulation(0, floor);
+        let id = Id::replay(0);
+        simulation.spawn_guy(id, vec2(0.0, 1.0), false);
+        simulation.guys.get_mut(&id).unwrap().state.vel = vec2(0.0, -200.0);
+        run(&mut simulation, 5);
+        let state = &simulation.guys.get(&id).unwrap().state;
+        assert!(state.pos.y > 0.0, "{:?}", state.pos);
+        assert!(state.vel.y > 0.0, "{:?}", state.vel);
+    }
+
+    #[test]
+    fn fast_guy_stops_at_first_of_stacked_surfaces() {
+        // More surfaces in the way than there are substeps
+        let mut simulation = new_simulation(0, |level| {
+            for i in 0..=MAX_COLLISION_SUBSTEPS {
+                let y = -0.1 * i as f32;
+                level.layers[0].surfaces.push(fixtures::surface(
+                    vec2(-10.0, y),
+                    vec2(10.0, y),
+                    "stone",
+                ));
+            }
+        });
+        let id = Id::replay(0);
+        simulation.spawn_guy(id, vec2(0.0, 1.0), false);
+        simulation.guys.get_mut(&id).unwrap().state.vel = vec2(0.0, -2000.0);
+        run(&mut simulation, 1);
+        let state = &simulation.guys.get(&id).unwrap().state;
+        assert!(state.pos.y > 0.0, "{:?}", state.pos);
+    }
+
+    #[test]
+    fn same_seed_same_result() {
+        let states: Vec<serde_json::Value> = [0, 0, 1]
+            .into_iter()
+            .map(|seed| {
+                let mut simulation = new_simulation(seed, floor);
+                let id = Id::replay(0);
+                simulation.spawn_guy(id, vec2(0.0, 2.0), true);
+                for tick in 0..400 {
+                    simulation.guys.get_mut(&id).unwrap().input = Input {
+                        roll_left: if tick < 200 { 1.0 } else { 0.0 },
+                        roll_right: 0.0,
+                        force_fart: tick % 100 < 10,
+                    };
+                    run(&mut simulation, 1);
+                }
+                serde_json::to_value(&simulation.guys.get(&id).unwrap().state).unwrap()
+            })
+            .collect();
+        assert_eq!(states[0], states[1]);
+        assert_ne!(states[0], states[2]);
+    }
+
+    #[test]
+    fn objects_set_fart_type() {
+        let mut simulation = new_simulation(0, |level| {
+            level.layers[0].objects.push(Object {
+                type_name: "guitar".to_owned(),
+                pos: vec2(0.0, 1.0),
+            });
+        });
+        let id = Id::replay(0);
+        simulation.spawn_guy(id, vec2(0.0, 1.0), false);
+        run(&mut simulation, 1);
+        let guy = simulation.guys.get(&id).unwrap();
+        assert_eq!(guy.state.fart_type, "melody");
+        assert!(guy.progress.used_objects.is_empty());
+    }
+
+    #[test]
+    fn one_shot_objects_stay_used_after_edits() {
+        let mut simulation = new_simulation(0, |level| {
+            level.layers[0].objects.push(Object {
+                type_name: "one-shot-guitar".to_owned(),
+                pos: vec2(0.0, 1.0),
+            });
+        });
+        let params = Rc::get_mut(&mut simulation.params).unwrap();
+        let mut one_shot = params.objects["guitar"].clone();
+        one_shot.one_shot = true;
+        params
+            .objects
+            .insert("one-shot-guitar".to_owned(), one_shot);
+        let id = Id::replay(0);
+        simulation.spawn_guy(id, vec2(0.0, 1.0), false);
+        run(&mut simulation, 1);
+        let default_fart_type = simulation.config.default_fart_type.clone();
+        let guy = simulation.guys.get_mut(&id).unwrap();
+        assert_eq!(guy.state.fart_type, "melody");
+        guy.state.fart_type = default_fart_type.clone();
+
+        // Another object before it in the layer does not make it usable again
+        simulation.level.modify().layers[0].objects.insert(
+            0,
+            Object {
+                type_name: "guitar".to_owned(),
+                pos: vec2(50.0, 50.0),
+            },
+        );
+        run(&mut simulation, 1);
+        let guy = simulation.guys.get(&id).unwrap();
+        assert_eq!(guy.state.fart_type, default_fart_type);
+        assert_eq!(guy.progress.used_objects.len(), 1);
+    }
+
+    #[test]
+    fn checkpoint_keeps_fart_type() {
+        let mut simulation = new_simulation(0, |level| {
+            let objects = &mut level.layers[0].objects;
+            objects.push(Object {
+                type_name: "guitar".to_owned(),
+                pos: vec2(0.0, 1.0),
+            });
+            objects.push(Object {
+                type_name: "checkpoint".to_owned(),
+                pos: vec2(0.0, 1.5),
+            });
+        });
+        let id = Id::replay(0);
+        simulation.spawn_guy(id, vec2(0.0, 1.0), false);
+        run(&mut simulation, 1);
+        assert_eq!(
+            simulation.guys.get(&id).unwrap().progress.checkpoint,
+            Some(Checkpoint {
+                pos: vec2(0.0, 1.5),
+                fart_type: "melody".to_owned(),
+            }),
+        );
+        assert!(simulation.is_checkpoint(vec2(0.0, 1.5)));
+        assert!(!simulation.is_checkpoint(vec2(0.0, 1.0)));
+
+        // Gone from the level, so respawning there is not possible anymore
+        simulation.level.modify().layers[0].objects.pop();
+        assert!(!simulation.is_checkpoint(vec2(0.0, 1.5)));
+    }
+
+    #[test]
+    fn finishing_clears_checkpoint() {
+        let mut simulation = new_simulation(0, |level| {
+            level.finish_point = vec2(0.0, 1.0);
+        });
+        let id = Id::replay(0);
+        simulation.spawn_guy(id, vec2(0.0, 1.0), false);
+        simulation.guys.get_mut(&id).unwrap().progress.checkpoint = Some(Checkpoint {
+            pos: vec2(0.0, 1.5),
+            fart_type: "melody".to_owned(),
+        });
+        run(&mut simulation, 1);
+        let guy = simulation.guys.get(&id).unwrap();
+        assert!(guy.progress.finished);
+        assert_eq!(guy.progress.checkpoint, None);
+    }
+
+    #[test]
+    fn tile_gravity_blends_over_the_edge() {
+        let mut simulation = new_simulation(0, |level| {
+            level.layers[0].tiles.push(Tile::new(
+                fixtures::square(vec2::ZERO, 10.0),
+                "antigravity".to_owned(),
+            ));
+        });
+        let params = Rc::get_mut(&mut simulation.params).unwrap();
+        let mut antigravity = params.tiles["stone"].clone();
+        antigravity.gravity = Some(vec2(0.0, 7.0));
+        params.tiles.insert("antigravity".to_owned(), antigravity);
+        // Fully inside, the tile gravity is used as is even though it is made of two triangles
+        assert!((simulation.gravity_at(vec2::ZERO) - vec2(0.0, 7.0)).len() < 1e-3);
+        assert_eq!(
+            simulation.gravity_at(vec2(20.0, 0.0)),
+            vec2(0.0, -simulation.config.gravity),
+        );
+        // Half inside, halfway between the two
+        let edge = simulation.gravity_at(vec2(5.0, 0.0));
+        assert!(
+            edge.y > -simulation.config.gravity && edge.y < 7.0,
+            "{edge:?}"
+        );
+    }
+
+    #[test]
+    fn tile_time_scale() {
+        let simulation = new_simulation(0, |level| {
+            level.layers[0].tiles.push(Tile::new(
+                fixtures::square(vec2::ZERO, 10.0),
+                "slowtime".to_owned(),
+            ));
+        });
+        assert!((simulation.time_scale_at(vec2::ZERO) - 0.5).abs() < 1e-3);
+        assert_eq!(simulation.time_scale_at(vec2(20.0, 0.0)), 1.0);
+    }
+
+    #[test]
+    fn platform_clock_slews_towards_corrections() {
+        let mut simulation = new_simulation(0, |_| {});
+        simulation.correct_platform_time(0.5);
+        simulation.update(1.0);
+        assert!((simulation.platform_time - 1.0 - PLATFORM_CLOCK_SLEW).abs() < 1e-6);
+        for _ in 0..10 {
+            simulation.update(1.0);
+        }
+        assert!((simulation.platform_time - 11.5).abs() < 1e-6);
+        assert_eq!(simulation.platform_time_correction(), 0.0);
+
+        // Big corrections are not worth waiting for
+        simulation.correct_platform_time(-5.0);
+        assert!((simulation.platform_time - 6.5).abs() < 1e-6);
+    }
+}
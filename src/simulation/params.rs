@@ -0,0 +1,64 @@
+use super::*;
+
+/// Everything from the assets that affects physics, without textures or sounds
+pub struct PhysicsParams {
+    pub surfaces: HashMap<String, SurfaceParams>,
+    pub tiles: HashMap<String, TileParams>,
+    pub objects: HashMap<String, ObjectParams>,
+}
+
+impl PhysicsParams {
+    pub fn from_assets(assets: &Assets) -> Self {
+        Self {
+            surfaces: assets
+                .surfaces
+                .keys()
+                .map(|name| (name.to_owned(), assets.surfaces[name].params.clone()))
+                .collect(),
+            tiles: assets
+                .tiles
+                .keys()
+                .map(|name| (name.to_owned(), assets.tiles[name].params.clone()))
+                .collect(),
+            objects: assets
+                .objects
+                .keys()
+                .map(|name| (name.to_owned(), assets.objects[name].params.clone()))
+                .collect(),
+        }
+    }
+
+    /// Load only the params files, so this works with no window or audio
+    pub async fn load(assets_path: impl AsRef<std::path::Path>) -> anyhow::Result<Self> {
+        let assets_path = assets_path.as_ref();
+        async fn load_params<T: serde::de::DeserializeOwned>(
+            path: std::path::PathBuf,
+            file_name: impl Fn(&str) -> String,
+        ) -> anyhow::Result<HashMap<String, T>> {
+            let list: Vec<String> = file::load_detect(path.join("_list.ron")).await?;
+            future::try_join_all(list.into_iter().map(|name| {
+                let path = path.join(file_name(&name));
+                async move {
+                    let params: T = file::load_json(&path)
+                        .await
+                        .context(format!("Failed to load {path:?}"))?;
+                    Ok::<_, anyhow::Error>((name, params))
+                }
+            }))
+            .await
+            .map(|list| list.into_iter().collect())
+        }
+        Ok(Self {
+            surfaces: load_params(assets_path.join("surfaces"), |name| {
+                format!("{name}/params.json")
+            })
+            .await?,
+            tiles: load_params(assets_path.join("tiles"), |name| {
+                format!("{name}/params.json")
+            })
+            .await?,
+            objects: load_params(assets_path.join("objects"), |name| format!("{name}.json"))
+                .await?,
+        })
+    }
+}
@@ -1,6 +1,8 @@
 use super::*;
 
-pub use resvg::usvg::{Node, Tree};
+pub use resvg::usvg::{
+    Node, NodeExt, NodeKind, Paint, Path, PathSegment, Transform, Tree, Visibility,
+};
 
 pub struct Document {
     pub raw_xml: String,
@@ -15,6 +17,11 @@ pub async fn load(path: impl AsRef<std::path::Path>) -> anyhow::Result<Document>
 }
 
 pub fn render(ugli: &Ugli, tree: &Tree, node: Option<&Node>) -> ugli::Texture {
+    ugli::Texture::from_image_image(ugli, render_image(tree, node))
+}
+
+/// Renders on the cpu, with straight alpha
+pub fn render_image(tree: &Tree, node: Option<&Node>) -> image::RgbaImage {
     let size = tree.size.to_screen_size();
     let mut pixmap = resvg::tiny_skia::Pixmap::new(size.width(), size.height())
         .expect("Failed to create pixmap");
@@ -51,6 +58,5 @@ pub fn render(ugli: &Ugli, tree: &Tree, node: Option<&Node>) -> ugli::Texture {
         color[2] = rgba.blue();
         color[3] = rgba.alpha();
     }
-    let image = image::RgbaImage::from_vec(size.width(), size.height(), image_data).unwrap();
-    ugli::Texture::from_image_image(ugli, image)
+    image::RgbaImage::from_vec(size.width(), size.height(), image_data).unwrap()
 }
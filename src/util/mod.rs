@@ -38,15 +38,115 @@ pub fn inside_triangle(p: vec2<f32>, tri: [vec2<f32>; 3]) -> bool {
     true
 }
 
+pub fn distance_to_segment(p: vec2<f32>, a: vec2<f32>, b: vec2<f32>) -> f32 {
+    let t = if a == b {
+        0.0
+    } else {
+        (vec2::dot(p - a, b - a) / (b - a).len_sqr()).clamp(0.0, 1.0)
+    };
+    (a + (b - a) * t - p).len()
+}
+
+/// Positive for counter-clockwise polygons
+pub fn polygon_area(points: &[vec2<f32>]) -> f32 {
+    let mut sum = 0.0;
+    for i in 0..points.len() {
+        sum += vec2::skew(points[i], points[(i + 1) % points.len()]);
+    }
+    sum / 2.0
+}
+
+/// Ear clipping, returns counter-clockwise triangles
+pub fn triangulate(polygon: &[vec2<f32>]) -> Vec<[vec2<f32>; 3]> {
+    let mut points = polygon.to_vec();
+    if polygon_area(&points) < 0.0 {
+        points.reverse();
+    }
+    let mut result = vec![];
+    while points.len() > 3 {
+        let n = points.len();
+        let ear = (0..n).find(|&i| {
+            let tri = [points[(i + n - 1) % n], points[i], points[(i + 1) % n]];
+            vec2::skew(tri[1] - tri[0], tri[2] - tri[0]) > EPS
+                && points
+                    .iter()
+                    .all(|&p| tri.contains(&p) || !inside_triangle(p, tri))
+        });
+        // Self intersecting polygons may have no ears left
+        let Some(i) = ear else { break };
+        result.push([points[(i + n - 1) % n], points[i], points[(i + 1) % n]]);
+        points.remove(i);
+    }
+    if points.len() == 3 && polygon_area(&points) > EPS {
+        result.push([points[0], points[1], points[2]]);
+    }
+    result
+}
+
+/// Whether the segments intersect, not counting touching at the ends
+fn segments_cross(a: vec2<f32>, b: vec2<f32>, c: vec2<f32>, d: vec2<f32>) -> bool {
+    let side = |p: vec2<f32>, q: vec2<f32>, r: vec2<f32>| vec2::skew(q - p, r - p);
+    side(a, b, c) * side(a, b, d) < 0.0 && side(c, d, a) * side(c, d, b) < 0.0
+}
+
+fn ring_crosses(a: vec2<f32>, b: vec2<f32>, ring: &[vec2<f32>]) -> bool {
+    (0..ring.len()).any(|i| segments_cross(a, b, ring[i], ring[(i + 1) % ring.len()]))
+}
+
+/// Each hole is joined to the outline by a bridge, so that the result can be ear clipped
+pub fn triangulate_with_holes(
+    outline: &[vec2<f32>],
+    holes: &[Vec<vec2<f32>>],
+) -> Vec<[vec2<f32>; 3]> {
+    let mut points = outline.to_vec();
+    if polygon_area(&points) < 0.0 {
+        points.reverse();
+    }
+    for hole in holes {
+        if hole.len() < 3 {
+            continue;
+        }
+        let mut hole = hole.clone();
+        if polygon_area(&hole) > 0.0 {
+            hole.reverse();
+        }
+        let mut bridge = None;
+        for h in 0..hole.len() {
+            for p in 0..points.len() {
+                let (a, b) = (hole[h], points[p]);
+                if ring_crosses(a, b, &points)
+                    || holes.iter().any(|other| ring_crosses(a, b, other))
+                {
+                    continue;
+                }
+                let len = (a - b).len();
+                if bridge.map_or(true, |(_, _, best)| len < best) {
+                    bridge = Some((h, p, len));
+                }
+            }
+        }
+        let Some((h, p, _)) = bridge else {
+            log::warn!("Failed to connect a hole to the tile outline");
+            continue;
+        };
+        let mut merged = points[..=p].to_vec();
+        merged.extend(hole[h..].iter().chain(&hole[..=h]));
+        merged.extend(&points[p..]);
+        points = merged;
+    }
+    triangulate(&points)
+}
+
 pub fn circle_triangle_intersect_percentage(
     center: vec2<f32>,
     radius: f32,
     tri: [vec2<f32>; 3],
 ) -> f32 {
+    // Fixed seed so that simulation is deterministic
     static RNG: once_cell::sync::Lazy<Vec<vec2<f32>>> = once_cell::sync::Lazy::new(|| {
-        (0..100)
-            .map(|_| thread_rng().gen_circle(vec2::ZERO, 1.0))
-            .collect()
+        use rand::SeedableRng;
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        (0..100).map(|_| rng.gen_circle(vec2::ZERO, 1.0)).collect()
     });
     RNG.iter()
         .filter(|&&p| inside_triangle(center + p * radius, tri))
@@ -90,3 +190,143 @@ pub fn ray_hit_time(
     // dot(ray_start + ray_vel * t - line_p, line_normal) = 0
     vec2::dot(line_p - ray_start, line_normal) / vec2::dot(ray_vel, line_normal)
 }
+
+/// First time when a moving point gets at the given distance from the center, if it does
+pub fn ray_circle_hit_time(
+    ray_start: vec2<f32>,
+    ray_vel: vec2<f32>,
+    center: vec2<f32>,
+    radius: f32,
+) -> Option<f32> {
+    // |ray_start + ray_vel * t - center| = radius
+    let d = ray_start - center;
+    let a = ray_vel.len_sqr();
+    let b = 2.0 * vec2::dot(d, ray_vel);
+    let c = d.len_sqr() - radius.sqr();
+    if a < EPS {
+        return None;
+    }
+    let discriminant = b.sqr() - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let t = (-b - discriminant.sqrt()) / (2.0 * a);
+    if t < 0.0 {
+        return None;
+    }
+    Some(t)
+}
+
+/// First time when a moving circle touches the front side of a segment, if it does
+pub fn circle_segment_hit_time(
+    start: vec2<f32>,
+    vel: vec2<f32>,
+    radius: f32,
+    p1: vec2<f32>,
+    p2: vec2<f32>,
+) -> Option<f32> {
+    let normal = (p2 - p1).rotate_90().normalize_or_zero();
+    let mut result = None;
+    let mut update = |t: f32| {
+        if t >= 0.0 && result.map_or(true, |result| t < result) {
+            result = Some(t);
+        }
+    };
+    if vec2::dot(vel, normal) < 0.0 && vec2::dot(start - p1, normal) >= radius {
+        let t = ray_hit_time(start, vel, p1 + normal * radius, normal);
+        let along = vec2::dot(start + vel * t - p1, p2 - p1);
+        if along >= 0.0 && along <= (p2 - p1).len_sqr() {
+            update(t);
+        }
+    }
+    for p in [p1, p2] {
+        if let Some(t) = ray_circle_hit_time(start, vel, p, radius) {
+            if vec2::skew(p2 - p1, start + vel * t - p) > 0.0 {
+                update(t);
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn total_area(triangles: &[[vec2<f32>; 3]]) -> f32 {
+        triangles
+            .iter()
+            .map(|triangle| polygon_area(triangle))
+            .sum()
+    }
+
+    #[test]
+    fn triangulate_concave() {
+        let polygon = vec![
+            vec2(0.0, 0.0),
+            vec2(2.0, 0.0),
+            vec2(2.0, 1.0),
+            vec2(1.0, 1.0),
+            vec2(1.0, 2.0),
+            vec2(0.0, 2.0),
+        ];
+        let triangles = triangulate(&polygon);
+        assert_eq!(triangles.len(), polygon.len() - 2);
+        assert!((total_area(&triangles) - polygon_area(&polygon)).abs() < 1e-5);
+        for triangle in &triangles {
+            assert!(polygon_area(triangle) > 0.0);
+            assert!(triangle.iter().all(|p| polygon.contains(p)));
+        }
+    }
+
+    #[test]
+    fn triangulate_clockwise() {
+        let mut polygon = fixtures::square(vec2::ZERO, 2.0);
+        polygon.reverse();
+        let triangles = triangulate(&polygon);
+        assert_eq!(triangles.len(), 2);
+        assert!((total_area(&triangles) - 4.0).abs() < 1e-5);
+        assert!(triangles
+            .iter()
+            .all(|triangle| polygon_area(triangle) > 0.0));
+    }
+
+    #[test]
+    fn triangulate_degenerate() {
+        assert!(triangulate(&[]).is_empty());
+        assert!(triangulate(&[vec2(0.0, 0.0), vec2(1.0, 0.0)]).is_empty());
+        assert!(triangulate(&[vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(2.0, 0.0)]).is_empty());
+        assert_eq!(
+            triangulate(&[vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(0.0, 1.0)]).len(),
+            1,
+        );
+    }
+
+    #[test]
+    fn triangulate_holes() {
+        let outline = fixtures::square(vec2::ZERO, 4.0);
+        let holes = vec![
+            fixtures::square(vec2(-1.0, 0.0), 1.0),
+            fixtures::square(vec2(1.0, 0.0), 1.0),
+        ];
+        let triangles = triangulate_with_holes(&outline, &holes);
+        assert!((total_area(&triangles) - 14.0).abs() < 1e-4);
+        assert!(triangles
+            .iter()
+            .all(|triangle| polygon_area(triangle) > 0.0));
+
+        // Winding of the hole does not matter
+        let mut hole = fixtures::square(vec2::ZERO, 2.0);
+        hole.reverse();
+        let triangles = triangulate_with_holes(&outline, &[hole]);
+        assert!((total_area(&triangles) - 12.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn triangulate_skips_degenerate_holes() {
+        let outline = fixtures::square(vec2::ZERO, 4.0);
+        let triangles = triangulate_with_holes(&outline, &[vec![vec2(0.0, 0.0), vec2(1.0, 0.0)]]);
+        assert_eq!(triangles.len(), 2);
+        assert!((total_area(&triangles) - 16.0).abs() < 1e-4);
+    }
+}
@@ -142,7 +142,7 @@ impl VideoEditor {
     fn stop(game: &mut Game) {
         let editor = game.video_editor.as_mut().unwrap();
         if let Some(mut rec) = game.recording.take() {
-            if let Some(guy) = game.my_guy.and_then(|id| game.guys.get(&id)) {
+            if let Some(guy) = game.my_guy.and_then(|id| game.simulation.guys.get(&id)) {
                 rec.push(game.simulation_time, guy);
             }
             editor.save.segments[editor.current_segment]
@@ -153,7 +153,9 @@ impl VideoEditor {
     fn restart_segment(game: &mut Game, segment_index: usize) {
         let editor = game.video_editor.as_mut().unwrap();
         let segment = &editor.save.segments[segment_index];
-        game.guys.retain(|guy| Some(guy.id) == game.my_guy);
+        game.simulation
+            .guys
+            .retain(|guy| Some(guy.id) == game.my_guy);
         editor.time = segment.config.start_time;
         game.replays = segment
             .replays
@@ -262,7 +264,7 @@ impl Game {
         }
         let play = Button::new(cx, "play");
         if start_recording.was_clicked() {
-            if let Some(guy) = game.my_guy.and_then(|id| game.guys.get(&id)) {
+            if let Some(guy) = game.my_guy.and_then(|id| game.simulation.guys.get(&id)) {
                 game.recording = Some(Replay::new(game.simulation_time, guy));
                 let segment = editor.current_segment;
                 VideoEditor::restart_segment(game, segment);